@@ -0,0 +1,228 @@
+// Anchors
+// An anchor is a byte position registered with a TextBuffer that is shifted automatically as
+// text is inserted and removed, so features that remember locations (snippet tab stops,
+// decorations, markers) stay valid across edits without re-deriving offsets themselves.
+use std::collections::{HashMap, HashSet};
+
+use ropey::Rope;
+
+use crate::rope_utils::split_line_ending;
+use crate::{TextBuffer, TextPosition, TextRange};
+
+
+// Anchor Identifier
+// Opaque handle returned when an anchor is created; used to resolve or remove it later.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct AnchorId(u64);
+
+
+// Anchor Bias
+// Decides which side of an insertion made exactly at the anchor's position the anchor ends up on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AnchorBias {
+    // The anchor stays put; text inserted at its position ends up after it.
+    Left,
+    // The anchor moves with the insertion; text inserted at its position ends up before it.
+    Right,
+}
+
+
+// Anchored Range
+// A pair of anchors describing a range that tracks edits.
+// The start is left-biased and the end right-biased, so text typed at either edge of the range
+// (including into an empty range) becomes part of it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct AnchoredRange {
+    pub start: AnchorId,
+    pub end: AnchorId,
+}
+
+
+#[derive(Debug, Clone, Copy)]
+struct Anchor {
+    byte_idx: usize,
+    bias: AnchorBias,
+    // Line anchors are deleted together with their line (see 'drop_removed_lines').
+    line: bool,
+}
+
+
+// Anchor Set
+// Storage for all anchors of one buffer. Only mutated while the buffer's content lock is held,
+// so anchor positions always agree with the rope.
+#[derive(Debug, Default)]
+pub(crate) struct AnchorSet {
+    next_id: u64,
+    anchors: HashMap<AnchorId, Anchor>,
+    // Span ranges by start anchor. An edit touching a span (overlapping or adjacent to it) marks
+    // it in 'touched' until the span is removed (see 'HighlightLayer').
+    spans: HashMap<AnchorId, AnchorId>,
+    touched: HashSet<AnchorId>,
+}
+
+impl AnchorSet {
+    pub(crate) fn create(&mut self, byte_idx: usize, bias: AnchorBias) -> AnchorId {
+        let id = AnchorId(self.next_id);
+        self.next_id += 1;
+        self.anchors.insert(id, Anchor { byte_idx, bias, line: false });
+        id
+    }
+
+    // Creates a right-biased anchor that is deleted when its line is, for per-line data.
+    pub(crate) fn create_line(&mut self, byte_idx: usize) -> AnchorId {
+        let id = self.create(byte_idx, AnchorBias::Right);
+        if let Some(anchor) = self.anchors.get_mut(&id) {
+            anchor.line = true;
+        }
+        id
+    }
+
+    pub(crate) fn create_range(&mut self, range: TextRange) -> AnchoredRange {
+        AnchoredRange {
+            start: self.create(range.start, AnchorBias::Left),
+            end: self.create(range.end, AnchorBias::Right),
+        }
+    }
+
+    // Creates a range like 'create_range' that is marked as touched by any edit inside it or at
+    // its edges.
+    pub(crate) fn create_span(&mut self, range: TextRange) -> AnchoredRange {
+        let span = self.create_range(range);
+        self.spans.insert(span.start, span.end);
+        span
+    }
+
+    // Returns true if an edit touched 'span' since it was created.
+    pub(crate) fn is_touched(&self, span: &AnchoredRange) -> bool {
+        self.touched.contains(&span.start)
+    }
+
+    pub(crate) fn remove_span(&mut self, span: &AnchoredRange) {
+        self.spans.remove(&span.start);
+        self.touched.remove(&span.start);
+        self.remove(span.start);
+        self.remove(span.end);
+    }
+
+    // Marks the untouched spans whose range '[start, end]' (edges included) meets '[from, to]'.
+    fn touch_spans(&mut self, from: usize, to: usize) {
+        for (start, end) in &self.spans {
+            if self.touched.contains(start) {
+                continue;
+            }
+            let (Some(start_idx), Some(end_idx)) = (self.position(*start), self.position(*end)) else {
+                continue;
+            };
+            if start_idx <= to && from <= end_idx {
+                self.touched.insert(*start);
+            }
+        }
+    }
+
+    pub(crate) fn position(&self, id: AnchorId) -> Option<usize> {
+        self.anchors.get(&id).map(|anchor| anchor.byte_idx)
+    }
+
+    // Moves an existing anchor to 'byte_idx'.
+    pub(crate) fn set_position(&mut self, id: AnchorId, byte_idx: usize) {
+        if let Some(anchor) = self.anchors.get_mut(&id) {
+            anchor.byte_idx = byte_idx;
+        }
+    }
+
+    pub(crate) fn remove(&mut self, id: AnchorId) -> bool {
+        self.anchors.remove(&id).is_some()
+    }
+
+    // Shifts anchors for 'len_bytes' inserted at 'byte_idx'.
+    pub(crate) fn shift_for_insert(&mut self, byte_idx: usize, len_bytes: usize) {
+        self.touch_spans(byte_idx, byte_idx);
+        for anchor in self.anchors.values_mut() {
+            if anchor.byte_idx > byte_idx || (anchor.byte_idx == byte_idx && anchor.bias == AnchorBias::Right) {
+                anchor.byte_idx += len_bytes;
+            }
+        }
+    }
+
+    // Deletes the line anchors whose line disappears when 'len_bytes' are removed at 'byte_idx'.
+    // Must be called before the removal is applied to 'rope'. A line disappears when everything
+    // from the anchor to the end of its line is removed together with a line break next to it;
+    // removing only the break (joining lines) keeps the anchor.
+    pub(crate) fn drop_removed_lines(&mut self, rope: &Rope, byte_idx: usize, len_bytes: usize) {
+        let end_byte_idx = byte_idx + len_bytes;
+        self.anchors.retain(|_, anchor| {
+            if !anchor.line || anchor.byte_idx < byte_idx || anchor.byte_idx > end_byte_idx {
+                return true;
+            }
+            let line = rope.byte_to_line(anchor.byte_idx);
+            let line_start = rope.line_to_byte(line);
+            let content_end = line_start + split_line_ending(&rope.line(line).to_string()).0.len();
+            let content_removed = end_byte_idx >= content_end;
+            let break_removed = end_byte_idx > content_end || byte_idx < line_start;
+            !(content_removed && break_removed)
+        });
+    }
+
+    // Shifts anchors for 'len_bytes' removed at 'byte_idx'.
+    // Anchors inside the removed span collapse onto its start.
+    pub(crate) fn shift_for_remove(&mut self, byte_idx: usize, len_bytes: usize) {
+        let end_byte_idx = byte_idx + len_bytes;
+        self.touch_spans(byte_idx, end_byte_idx);
+        for anchor in self.anchors.values_mut() {
+            if anchor.byte_idx >= end_byte_idx {
+                anchor.byte_idx -= len_bytes;
+            } else if anchor.byte_idx > byte_idx {
+                anchor.byte_idx = byte_idx;
+            }
+        }
+    }
+}
+
+
+impl TextBuffer {
+    // Anchor Management
+
+    // Registers an anchor at the given position. It is shifted by every subsequent edit.
+    pub fn create_anchor(&self, position: TextPosition, bias: AnchorBias) -> AnchorId {
+        let _rope = self.content.lock(); // Hold the content lock so the position can't go stale
+        self.anchors.lock().create(position.byte_idx, bias)
+    }
+
+    // Registers a pair of anchors tracking the given range.
+    pub fn create_anchored_range(&self, range: TextRange) -> AnchoredRange {
+        let _rope = self.content.lock();
+        self.anchors.lock().create_range(range)
+    }
+
+    // Returns the current position of an anchor, or None if it was removed.
+    pub fn anchor_position(&self, id: AnchorId) -> Option<TextPosition> {
+        self.anchors.lock().position(id).map(|byte_idx| TextPosition { byte_idx })
+    }
+
+    // Resolves an anchored range to its current byte range.
+    pub fn resolve_range(&self, range: &AnchoredRange) -> Option<TextRange> {
+        let anchors = self.anchors.lock();
+        Some(TextRange::new(anchors.position(range.start)?, anchors.position(range.end)?))
+    }
+
+    // Registers a line anchor at the start of 'line', or None if there is no such line.
+    pub(crate) fn create_line_anchor(&self, line: usize) -> Option<AnchorId> {
+        let rope = self.content.lock();
+        if line >= rope.len_lines() {
+            return None;
+        }
+        Some(self.anchors.lock().create_line(rope.line_to_byte(line)))
+    }
+
+    // Stops tracking an anchor. Returns false if it did not exist.
+    pub fn remove_anchor(&self, id: AnchorId) -> bool {
+        self.anchors.lock().remove(id)
+    }
+
+    // Stops tracking both anchors of a range.
+    pub fn remove_anchored_range(&self, range: &AnchoredRange) {
+        let mut anchors = self.anchors.lock();
+        anchors.remove(range.start);
+        anchors.remove(range.end);
+    }
+}
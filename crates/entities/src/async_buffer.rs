@@ -0,0 +1,129 @@
+// Async Text Buffer
+// A variant of 'TextBuffer' whose content sits behind a 'tokio::sync::RwLock' instead of a
+// 'parking_lot::Mutex'. Its guards may be held across '.await' (e.g. streaming the text to a slow
+// sink), at the cost of an async acquire on every access.
+// 'TextBuffer' remains the default: it is cheaper for latency-sensitive editing and carries the
+// full feature set (anchors, search, snippets, ...). Pick this type only when a component really
+// needs to await while holding the text.
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use parking_lot::Mutex;
+use ropey::Rope;
+use tokio::sync::{mpsc, RwLock, RwLockReadGuard};
+
+use crate::{
+    insert_into_rope, notify_all, register_observer, remove_from_rope, unregister_observer, EditOrigin,
+    ObserverEntry, ObserverId, TextBuffer, TextBufferChangedEvent, TextPosition,
+};
+
+
+// AsyncTextBuffer Entity
+// Same editing and observer contract as 'TextBuffer': every edit emits the same events, and
+// observers are notified after the write lock has been released.
+pub struct AsyncTextBuffer {
+    // Many readers or one writer; readers may await while holding their guard.
+    content: Arc<RwLock<Rope>>,
+
+    // Registered observers, sorted by delivery order (see 'ObserverEntry').
+    // Never held across an await by the buffer itself, so a plain mutex is enough.
+    observers: Arc<Mutex<Vec<ObserverEntry>>>,
+
+    // Number of edits applied so far. Only bumped while the write lock is held.
+    version: AtomicU64,
+}
+
+impl AsyncTextBuffer {
+    // Create a new 'AsyncTextBuffer' instance with initial text.
+    pub fn new(initial_text: &str) -> Self {
+        Self {
+            content: Arc::new(RwLock::new(Rope::from_str(initial_text))),
+            observers: Arc::new(Mutex::new(Vec::new())),
+            version: AtomicU64::new(0),
+        }
+    }
+
+    // Returns the buffer version: the number of edits applied since creation.
+    pub fn version(&self) -> u64 {
+        self.version.load(Ordering::SeqCst)
+    }
+
+    fn bump_version(&self) -> u64 {
+        self.version.fetch_add(1, Ordering::SeqCst) + 1
+    }
+
+
+    // Public API for AsyncTextBuffer Manipulation
+
+    // Inserts text at a given byte position. Inserting "" is a no-op (see 'TextBuffer::insert').
+    pub async fn insert(&self, position: TextPosition, text: &str) {
+        if text.is_empty() {
+            return;
+        }
+        let mut rope = self.content.write().await;
+        let event = insert_into_rope(&mut rope, position.byte_idx, text, EditOrigin::User, self.bump_version());
+        drop(rope);
+
+        notify_all(&self.observers, event).await;
+    }
+
+    // Removes text from a given byte position for a specified length. Removing 0 bytes is a no-op.
+    pub async fn remove(&self, position: TextPosition, len_bytes: usize) {
+        if len_bytes == 0 {
+            return;
+        }
+        let mut rope = self.content.write().await;
+        let event = remove_from_rope(&mut rope, position.byte_idx, len_bytes, EditOrigin::User, self.bump_version());
+        drop(rope);
+
+        notify_all(&self.observers, event).await;
+    }
+
+
+    // Returns a read guard over the rope. It may be held across awaits; edits wait until every
+    // guard has been dropped, so keep long reads off the editing path.
+    pub async fn read(&self) -> RwLockReadGuard<'_, Rope> {
+        self.content.read().await
+    }
+
+    // Retrieves the entire text content as a String.
+    pub async fn get_text(&self) -> String {
+        self.content.read().await.to_string()
+    }
+
+    // Retrieves a substring within the given byte range.
+    pub async fn get_range(&self, start_byte_idx: usize, end_byte_idx: usize) -> String {
+        self.content.read().await
+            .byte_slice(start_byte_idx..end_byte_idx)
+            .to_string()
+    }
+
+    // Returns the total length of the text in bytes.
+    pub async fn len_bytes(&self) -> usize {
+        self.content.read().await.len_bytes()
+    }
+
+    // Returns the total number of lines.
+    pub async fn len_lines(&self) -> usize {
+        self.content.read().await.len_lines()
+    }
+
+
+    // Observer Management
+
+    // Adds a new observer with the default priority.
+    pub fn add_observer(&self, sender: mpsc::Sender<TextBufferChangedEvent>) -> ObserverId {
+        self.add_observer_with_priority(sender, TextBuffer::DEFAULT_OBSERVER_PRIORITY)
+    }
+
+    // Adds a new observer with an explicit delivery priority; same ordering rules as
+    // 'TextBuffer::add_observer_with_priority'.
+    pub fn add_observer_with_priority(&self, sender: mpsc::Sender<TextBufferChangedEvent>, priority: i32) -> ObserverId {
+        register_observer(&self.observers, sender, priority)
+    }
+
+    // Unregisters an observer. Returns false if it was not registered (or already pruned).
+    pub fn remove_observer(&self, id: ObserverId) -> bool {
+        unregister_observer(&self.observers, id)
+    }
+}
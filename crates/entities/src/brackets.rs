@@ -0,0 +1,110 @@
+// Bracket Matching
+// Finding the partner of a bracket and the innermost pair around a position, for "jump to
+// matching bracket", "select inside brackets" and auto-surround. Only '()', '[]' and '{}' count,
+// and brackets inside strings or comments are not told apart from code ones.
+use ropey::Rope;
+
+use crate::rope_utils::{char_at, char_before};
+use crate::TextBuffer;
+
+
+const BRACKETS: [(char, char); 3] = [('(', ')'), ('[', ']'), ('{', '}')];
+
+
+fn closing_for(opening: char) -> Option<char> {
+    BRACKETS.iter().find(|(open, _)| *open == opening).map(|(_, close)| *close)
+}
+
+fn opening_for(closing: char) -> Option<char> {
+    BRACKETS.iter().find(|(_, close)| *close == closing).map(|(open, _)| *open)
+}
+
+// Returns the byte index of the 'closing' bracket that balances text starting at 'from', i.e.
+// the first one not closing a bracket opened after 'from'. Closers that don't match the innermost
+// open bracket are ignored as stray.
+fn find_closing(rope: &Rope, from: usize, closing: char) -> Option<usize> {
+    let mut open: Vec<char> = Vec::new();
+    let mut byte_idx = from;
+    for c in rope.chars_at(rope.byte_to_char(from)) {
+        if let Some(close) = closing_for(c) {
+            open.push(close);
+        } else if opening_for(c).is_some() {
+            match open.last() {
+                Some(&expected) if expected == c => {
+                    open.pop();
+                }
+                None if c == closing => return Some(byte_idx),
+                _ => {}
+            }
+        }
+        byte_idx += c.len_utf8();
+    }
+    None
+}
+
+// Like 'find_closing', scanning backwards from 'from' for the 'opening' bracket.
+fn find_opening(rope: &Rope, from: usize, opening: char) -> Option<usize> {
+    let mut closed: Vec<char> = Vec::new();
+    let mut byte_idx = from;
+    while let Some(c) = char_before(rope, byte_idx) {
+        byte_idx -= c.len_utf8();
+        if let Some(open) = opening_for(c) {
+            closed.push(open);
+        } else if closing_for(c).is_some() {
+            match closed.last() {
+                Some(&expected) if expected == c => {
+                    closed.pop();
+                }
+                None if c == opening => return Some(byte_idx),
+                _ => {}
+            }
+        }
+    }
+    None
+}
+
+
+impl TextBuffer {
+    // Brackets
+
+    // Returns the byte index of the bracket matching the one at 'byte_idx', or None if there is
+    // no bracket there or it is unbalanced.
+    pub fn matching_bracket(&self, byte_idx: usize) -> Option<usize> {
+        let rope = self.content.lock();
+        let c = char_at(&rope, byte_idx)?;
+        match (closing_for(c), opening_for(c)) {
+            (Some(closing), _) => find_closing(&rope, byte_idx + c.len_utf8(), closing),
+            (_, Some(opening)) => find_opening(&rope, byte_idx, opening),
+            _ => None,
+        }
+    }
+
+    // Returns the byte indices of the opening and closing bracket of the innermost pair around
+    // 'byte_idx' (a cursor between them), or None if it isn't inside any balanced pair. A cursor
+    // right before an opening bracket or right after a closing one is outside that pair.
+    pub fn enclosing_brackets(&self, byte_idx: usize) -> Option<(usize, usize)> {
+        let rope = self.content.lock();
+        if byte_idx > rope.len_bytes() {
+            return None;
+        }
+        // Each unmatched opener before the cursor is a candidate, innermost first; one without
+        // a closer after the cursor is unbalanced, so the search moves further out.
+        let mut from = byte_idx;
+        let mut closed: Vec<char> = Vec::new();
+        while let Some(c) = char_before(&rope, from) {
+            from -= c.len_utf8();
+            if let Some(open) = opening_for(c) {
+                closed.push(open);
+            } else if let Some(close) = closing_for(c) {
+                if closed.last() == Some(&c) {
+                    closed.pop();
+                } else if closed.is_empty()
+                    && let Some(close_idx) = find_closing(&rope, byte_idx, close)
+                {
+                    return Some((from, close_idx));
+                }
+            }
+        }
+        None
+    }
+}
@@ -0,0 +1,115 @@
+// Document Builder
+// Constructs a 'Document' with everything 'Document::new' leaves at its defaults, e.g. a
+// read-only scratch document in a specific encoding:
+//     DocumentBuilder::new().initial_content("...").encoding(Encoding::Latin1).read_only(true).build()
+use std::sync::Arc;
+
+use crate::{Document, DocumentSettings, EditDecision, EditIntent, Encoding, EncodingDetection, PreEditObserver};
+
+
+// Vetoes every edit of a read-only document's buffer.
+struct ReadOnlyGuard;
+
+impl PreEditObserver for ReadOnlyGuard {
+    fn before_edit(&self, _intent: &EditIntent<'_>) -> EditDecision {
+        EditDecision::Veto
+    }
+}
+
+
+// Document Builder Struct
+// Starts out like 'Document::new(None, "", "plaintext")'. Each method replaces one option.
+#[derive(Debug, Clone)]
+pub struct DocumentBuilder {
+    path: Option<String>,
+    language_id: String,
+    encoding: Encoding,
+    read_only: bool,
+    initial_content: String,
+    settings: DocumentSettings,
+    dirty: bool,
+}
+
+impl Default for DocumentBuilder {
+    fn default() -> Self {
+        Self {
+            path: None,
+            language_id: "plaintext".to_string(),
+            encoding: Encoding::Utf8,
+            read_only: false,
+            initial_content: String::new(),
+            settings: DocumentSettings::default(),
+            dirty: false,
+        }
+    }
+}
+
+impl DocumentBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn path(mut self, path: &str) -> Self {
+        self.path = Some(path.to_string());
+        self
+    }
+
+    pub fn language(mut self, language_id: &str) -> Self {
+        self.language_id = language_id.to_string();
+        self
+    }
+
+    // The encoding the document is saved in, taken as certain.
+    pub fn encoding(mut self, encoding: Encoding) -> Self {
+        self.encoding = encoding;
+        self
+    }
+
+    // A read-only document's buffer rejects every edit with 'EditError::Vetoed'.
+    pub fn read_only(mut self, read_only: bool) -> Self {
+        self.read_only = read_only;
+        self
+    }
+
+    pub fn initial_content(mut self, text: &str) -> Self {
+        self.initial_content = text.to_string();
+        self
+    }
+
+    pub fn settings(mut self, settings: DocumentSettings) -> Self {
+        self.settings = settings;
+        self
+    }
+
+    // Whether the document starts out with unsaved changes, e.g. when restoring a session.
+    pub fn dirty(mut self, dirty: bool) -> Self {
+        self.dirty = dirty;
+        self
+    }
+
+    pub fn build(self) -> Document {
+        let mut document = Document::new(self.path, &self.initial_content, self.language_id);
+        document.read_only = self.read_only;
+        if self.read_only {
+            document.text_buffer.add_pre_edit_observer(Arc::new(ReadOnlyGuard));
+        }
+        *document.encoding.lock() = EncodingDetection::certain(self.encoding);
+        document.set_settings(self.settings);
+        document.set_dirty(self.dirty);
+        document
+    }
+}
+
+
+impl Document {
+    // Creates a read-only view of content that doesn't live in a file, e.g. a git blob shown by a
+    // diff or history view. It has no path, 'display_name' returns 'name' (say
+    // "main.rs @ 3f2a1c"), and it never becomes dirty, so it doesn't show up as savable the way
+    // an untitled document does.
+    pub fn from_memory(name: &str, content: &str, language_id: &str) -> Document {
+        let mut document = DocumentBuilder::new().initial_content(content).language(language_id).read_only(true).build();
+        document.memory_name = Some(name.to_string());
+        document.set_track_dirty(false);
+        document
+    }
+}
@@ -0,0 +1,65 @@
+// Byte Streaming
+// The UTF-8 bytes of the buffer one rope chunk at a time, for consumers that hash or transfer
+// the content and shouldn't need one giant 'Vec<u8>'.
+use std::sync::Arc;
+
+use parking_lot::MutexGuard;
+use ropey::Rope;
+
+use crate::TextBuffer;
+
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0100_0000_01b3;
+
+// Feeds 'bytes' into the FNV-1a 'hash'.
+fn fnv1a(hash: u64, bytes: &[u8]) -> u64 {
+    bytes.iter().fold(hash, |hash, &byte| (hash ^ u64::from(byte)).wrapping_mul(FNV_PRIME))
+}
+
+// Returns what 'content_hash' would for a buffer holding 'text'.
+pub(crate) fn text_hash(text: &str) -> u64 {
+    fnv1a(FNV_OFFSET_BASIS, text.as_bytes())
+}
+
+
+// Iterator returned by 'TextBuffer::byte_chunks'. It owns the content lock, so the chunks it
+// yields are all from the same version of the text.
+struct ByteChunks<'a> {
+    rope: MutexGuard<'a, Rope>,
+    // Start of the next chunk to yield.
+    byte_idx: usize,
+}
+
+impl Iterator for ByteChunks<'_> {
+    type Item = Arc<[u8]>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.byte_idx >= self.rope.len_bytes() {
+            return None;
+        }
+        let (chunk, chunk_start, _, _) = self.rope.chunk_at_byte(self.byte_idx);
+        let rest = &chunk.as_bytes()[self.byte_idx - chunk_start..];
+        self.byte_idx += rest.len();
+        Some(Arc::from(rest))
+    }
+}
+
+
+impl TextBuffer {
+    // Byte Streaming
+
+    // Yields the UTF-8 bytes of the text chunk by chunk. The iterator holds the buffer's lock
+    // until it is dropped, blocking every edit (and every other reader) meanwhile: consume it
+    // promptly, or copy the text out first for long-lived iteration.
+    pub fn byte_chunks(&self) -> impl Iterator<Item = Arc<[u8]>> + '_ {
+        ByteChunks { rope: self.content.lock(), byte_idx: 0 }
+    }
+
+    // Returns a 64-bit FNV-1a hash of the text, streamed through 'byte_chunks'. It depends only
+    // on the bytes (not on how the rope is chunked) and is stable across runs and platforms, so
+    // it can key a content-addressed cache. It is not cryptographic.
+    pub fn content_hash(&self) -> u64 {
+        self.byte_chunks().fold(FNV_OFFSET_BASIS, |hash, chunk| fnv1a(hash, &chunk))
+    }
+}
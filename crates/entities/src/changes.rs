@@ -0,0 +1,129 @@
+// Change History
+// A bounded log of recent edits keyed by version, for consumers that poll for "what changed since
+// I last looked?" on their own schedule (e.g. a background indexer) instead of observing every
+// event.
+use std::collections::VecDeque;
+
+use crate::{TextBuffer, TextBufferChangedEvent, TextRange};
+
+
+// One logged edit, in the coordinates of the text right before it.
+#[derive(Debug, Clone, Copy)]
+enum Change {
+    Inserted { start: usize, len: usize },
+    Removed { start: usize, len: usize },
+    Reloaded { len: usize },
+}
+
+
+// Dirty Since
+// The answer of 'dirty_span_since'.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DirtySince {
+    // Nothing changed after the version.
+    UpToDate,
+    // The span of the current text covering everything that changed after the version (empty
+    // where text was only removed).
+    Changed(TextRange),
+    // The edits after the version are no longer logged; re-read everything.
+    FullResync,
+}
+
+
+// Change Log
+// The most recent edits, oldest first. Only written while the buffer's content is locked, so the
+// entries' versions are consecutive.
+#[derive(Debug, Default)]
+pub(crate) struct ChangeLog {
+    entries: VecDeque<(u64, Change)>,
+}
+
+impl ChangeLog {
+    // Number of edits kept; older ones are evicted.
+    pub(crate) const CAPACITY: usize = 1024;
+
+    // Logs the precise edits 'event' describes.
+    pub(crate) fn record(&mut self, event: &TextBufferChangedEvent) {
+        let change = match *event {
+            TextBufferChangedEvent::Inserted { start_byte_idx, len_bytes, .. }
+            | TextBufferChangedEvent::Appended { start_byte_idx, len_bytes, .. } => Change::Inserted { start: start_byte_idx, len: len_bytes },
+            TextBufferChangedEvent::Removed { start_byte_idx, len_bytes, .. } => Change::Removed { start: start_byte_idx, len: len_bytes },
+            TextBufferChangedEvent::Reloaded { len_bytes, .. } => Change::Reloaded { len: len_bytes },
+            TextBufferChangedEvent::Batch(ref events) => {
+                events.iter().for_each(|event| self.record(event));
+                return;
+            }
+        };
+        if self.entries.len() == Self::CAPACITY {
+            self.entries.pop_front();
+        }
+        self.entries.push_back((event.version(), change));
+    }
+
+    // Returns the ranges of the current text (as of 'current', the latest version) changed by the
+    // edits after 'version', or None if some of them were evicted.
+    pub(crate) fn ranges_since(&self, version: u64, current: u64) -> Option<Vec<TextRange>> {
+        if version > current {
+            return None;
+        }
+        let oldest_logged = self.entries.front().map_or(current + 1, |(entry_version, _)| *entry_version);
+        if version + 1 < oldest_logged && version < current {
+            return None;
+        }
+        let mut ranges: Vec<TextRange> = Vec::new();
+        for (_, change) in self.entries.iter().filter(|(entry_version, _)| *entry_version > version) {
+            match *change {
+                Change::Inserted { start, len } => {
+                    for range in &mut ranges {
+                        // A range touching the insertion grows to include it.
+                        range.start += if range.start > start { len } else { 0 };
+                        range.end += if range.end >= start { len } else { 0 };
+                    }
+                    ranges.push(TextRange::new(start, start + len));
+                }
+                Change::Removed { start, len } => {
+                    let map = |idx: usize| if idx <= start { idx } else { idx.saturating_sub(len).max(start) };
+                    for range in &mut ranges {
+                        *range = TextRange::new(map(range.start), map(range.end));
+                    }
+                    // An empty range marks where text disappeared.
+                    ranges.push(TextRange::new(start, start));
+                }
+                Change::Reloaded { len } => ranges = vec![TextRange::new(0, len)],
+            }
+        }
+        ranges.sort_by_key(|range| (range.start, range.end));
+        let mut merged: Vec<TextRange> = Vec::with_capacity(ranges.len());
+        for range in ranges {
+            match merged.last_mut() {
+                Some(last) if range.start <= last.end => last.end = last.end.max(range.end),
+                _ => merged.push(range),
+            }
+        }
+        Some(merged)
+    }
+}
+
+
+impl TextBuffer {
+    // Returns the merged byte ranges of the current text that changed after 'version' (see
+    // 'version'), sorted and non-overlapping; an empty range marks text that was only removed.
+    // Empty if nothing changed. None if 'version' is older than the last 1024 edits (or newer
+    // than the buffer), meaning "re-read everything".
+    pub fn changes_since(&self, version: u64) -> Option<Vec<TextRange>> {
+        let _rope = self.content.lock(); // Keep the version and the log in step
+        self.changes.lock().ranges_since(version, self.version())
+    }
+
+    // Like 'changes_since', but merges the changes into one span, for consumers that re-process
+    // a single region anyway: a compact poll for "anything new since I last looked, and where?".
+    pub fn dirty_span_since(&self, version: u64) -> DirtySince {
+        match self.changes_since(version) {
+            None => DirtySince::FullResync,
+            Some(ranges) => match (ranges.first(), ranges.last()) {
+                (Some(first), Some(last)) => DirtySince::Changed(TextRange::new(first.start, last.end)),
+                _ => DirtySince::UpToDate,
+            },
+        }
+    }
+}
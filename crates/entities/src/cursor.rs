@@ -0,0 +1,192 @@
+// Cursor Tracking
+// A cursor that knows its '(line, grapheme column)' without re-segmenting its line on every
+// motion, for frontends that move it thousands of times a second. The tracker keeps the grapheme
+// boundaries of the cursor's line, so moving within the line is an index step; a line is only
+// segmented when the cursor moves onto it, or when an edit touches it (or anything before it).
+// Edits elsewhere are followed through an anchor.
+use std::sync::Arc;
+
+use ropey::Rope;
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::rope_utils::split_line_ending;
+use crate::{AnchorBias, AnchorId, TextBuffer, TextPosition};
+
+
+// Cursor Tracker
+// Text typed at the cursor ends up before it, as with an editor's caret.
+pub struct CursorTracker {
+    buffer: Arc<TextBuffer>,
+    anchor: AnchorId,
+    line: usize,
+    line_start: usize,
+    // Start offsets of the line's grapheme clusters, relative to 'line_start', followed by the
+    // end of its content (the column after the last cluster).
+    boundaries: Vec<usize>,
+    grapheme_col: usize,
+    // The column 'move_up'/'move_down' aim for, kept while passing shorter lines.
+    goal_col: usize,
+    // The buffer version the cached line is up to date with.
+    version: u64,
+}
+
+impl CursorTracker {
+    // Starts tracking a cursor at 'position', moved to the start of the grapheme cluster it is in
+    // (and clamped to the end of the buffer).
+    pub fn new(buffer: Arc<TextBuffer>, position: TextPosition) -> Self {
+        let rope = Arc::clone(&buffer.content);
+        let rope = rope.lock();
+        let anchor = buffer.anchors.lock().create(position.byte_idx, AnchorBias::Right);
+        let mut tracker = CursorTracker {
+            version: buffer.version(),
+            buffer,
+            anchor,
+            line: 0,
+            line_start: 0,
+            boundaries: vec![0],
+            grapheme_col: 0,
+            goal_col: 0,
+        };
+        tracker.place(&rope, position.byte_idx);
+        tracker.goal_col = tracker.grapheme_col;
+        tracker.store();
+        drop(rope);
+        tracker
+    }
+
+    // Returns the cursor's current position.
+    pub fn position(&mut self) -> TextPosition {
+        let buffer = Arc::clone(&self.buffer);
+        let rope = buffer.content.lock();
+        self.sync(&rope);
+        self.store()
+    }
+
+    // Returns the cursor's '(line, grapheme column)', as 'TextBuffer::byte_to_grapheme' would.
+    pub fn line_col(&mut self) -> (usize, usize) {
+        let buffer = Arc::clone(&self.buffer);
+        let rope = buffer.content.lock();
+        self.sync(&rope);
+        (self.line, self.grapheme_col)
+    }
+
+    // Moves the cursor to 'position' (see 'new').
+    pub fn set_position(&mut self, position: TextPosition) -> TextPosition {
+        let buffer = Arc::clone(&self.buffer);
+        let rope = buffer.content.lock();
+        self.version = buffer.version();
+        self.place(&rope, position.byte_idx);
+        self.goal_col = self.grapheme_col;
+        self.store()
+    }
+
+    // Moves one grapheme cluster right, onto the start of the next line at the end of a line.
+    // Stays put at the end of the buffer.
+    pub fn move_right(&mut self) -> TextPosition {
+        let buffer = Arc::clone(&self.buffer);
+        let rope = buffer.content.lock();
+        self.sync(&rope);
+        if self.grapheme_col + 1 < self.boundaries.len() {
+            self.grapheme_col += 1;
+        } else if self.line + 1 < rope.len_lines() {
+            self.load_line(&rope, self.line + 1);
+            self.grapheme_col = 0;
+        }
+        self.goal_col = self.grapheme_col;
+        self.store()
+    }
+
+    // Moves one grapheme cluster left, onto the end of the previous line at the start of a line.
+    // Stays put at the start of the buffer.
+    pub fn move_left(&mut self) -> TextPosition {
+        let buffer = Arc::clone(&self.buffer);
+        let rope = buffer.content.lock();
+        self.sync(&rope);
+        if self.grapheme_col > 0 {
+            self.grapheme_col -= 1;
+        } else if self.line > 0 {
+            self.load_line(&rope, self.line - 1);
+            self.grapheme_col = self.boundaries.len() - 1;
+        }
+        self.goal_col = self.grapheme_col;
+        self.store()
+    }
+
+    // Moves to the line above, as close to the goal column as that line allows. Stays put on the
+    // first line.
+    pub fn move_up(&mut self) -> TextPosition {
+        let buffer = Arc::clone(&self.buffer);
+        let rope = buffer.content.lock();
+        self.sync(&rope);
+        if self.line > 0 {
+            self.load_line(&rope, self.line - 1);
+            self.grapheme_col = self.goal_col.min(self.boundaries.len() - 1);
+        }
+        self.store()
+    }
+
+    // Moves to the line below, as close to the goal column as that line allows. Stays put on
+    // the last line.
+    pub fn move_down(&mut self) -> TextPosition {
+        let buffer = Arc::clone(&self.buffer);
+        let rope = buffer.content.lock();
+        self.sync(&rope);
+        if self.line + 1 < rope.len_lines() {
+            self.load_line(&rope, self.line + 1);
+            self.grapheme_col = self.goal_col.min(self.boundaries.len() - 1);
+        }
+        self.store()
+    }
+
+    // Catches up with edits made since the cached line was loaded. Edits that all start after
+    // the line's content leave the line, its start and the cursor untouched; anything else
+    // re-reads the line at the anchor's position. Must be called with 'content' locked.
+    fn sync(&mut self, rope: &Rope) {
+        let version = self.buffer.version();
+        if version == self.version {
+            return;
+        }
+        let content_end = self.line_start + self.boundaries[self.boundaries.len() - 1];
+        let untouched = self.buffer.changes.lock()
+            .ranges_since(self.version, version)
+            .is_some_and(|ranges| ranges.iter().all(|range| range.start > content_end));
+        self.version = version;
+        if !untouched {
+            let byte_idx = self.buffer.anchors.lock().position(self.anchor).unwrap_or(0);
+            self.place(rope, byte_idx);
+        }
+    }
+
+    // Puts the cursor at the start of the cluster containing 'byte_idx', or after the content if
+    // 'byte_idx' is inside the line break.
+    fn place(&mut self, rope: &Rope, byte_idx: usize) {
+        let byte_idx = byte_idx.min(rope.len_bytes());
+        self.load_line(rope, rope.byte_to_line(byte_idx));
+        let offset = byte_idx - self.line_start;
+        self.grapheme_col = self.boundaries.partition_point(|&boundary| boundary <= offset) - 1;
+    }
+
+    // Segments 'line' into 'boundaries'.
+    fn load_line(&mut self, rope: &Rope, line: usize) {
+        let text = rope.line(line).to_string();
+        let content = split_line_ending(&text).0;
+        self.line = line;
+        self.line_start = rope.line_to_byte(line);
+        self.boundaries = content.grapheme_indices(true).map(|(offset, _)| offset).collect();
+        self.boundaries.push(content.len());
+    }
+
+    // Moves the anchor to the cursor and returns its position. Must be called with 'content'
+    // locked.
+    fn store(&self) -> TextPosition {
+        let byte_idx = self.line_start + self.boundaries[self.grapheme_col];
+        self.buffer.anchors.lock().set_position(self.anchor, byte_idx);
+        TextPosition { byte_idx }
+    }
+}
+
+impl Drop for CursorTracker {
+    fn drop(&mut self) {
+        self.buffer.remove_anchor(self.anchor);
+    }
+}
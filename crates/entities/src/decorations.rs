@@ -0,0 +1,102 @@
+// Line Decorations
+// Arbitrary per-line data (breakpoints, git gutter markers, coverage) that follows its line
+// through edits. Each decoration is backed by a line anchor at the start of its line, so lines
+// inserted or removed above shift it, and it is dropped when its line is deleted.
+use std::sync::Arc;
+
+use crate::{AnchorId, TextBuffer};
+
+
+// LineDecorations Struct
+// A set of values keyed by line, tracking one TextBuffer.
+// When edits join two decorated lines, the decoration of the upper line wins; the other one is
+// hidden rather than lost and reappears if the lines are split again.
+pub struct LineDecorations<T> {
+    buffer: Arc<TextBuffer>,
+    entries: Vec<(AnchorId, T)>,
+}
+
+impl<T> LineDecorations<T> {
+    // Creates an empty decoration layer for 'buffer'.
+    pub fn new(buffer: Arc<TextBuffer>) -> Self {
+        Self { buffer, entries: Vec::new() }
+    }
+
+    // Decorates 'line', replacing any decoration it already has.
+    // Returns false if the buffer has no such line.
+    pub fn set(&mut self, line: usize, value: T) -> bool {
+        self.prune();
+        if let Some(idx) = self.visible_index(line) {
+            self.entries[idx].1 = value;
+            return true;
+        }
+        match self.buffer.create_line_anchor(line) {
+            Some(anchor) => {
+                self.entries.push((anchor, value));
+                true
+            }
+            None => false,
+        }
+    }
+
+    // Returns the decoration currently on 'line'.
+    pub fn get(&self, line: usize) -> Option<&T> {
+        self.visible_index(line).map(|idx| &self.entries[idx].1)
+    }
+
+    // Removes and returns the decoration on 'line'.
+    pub fn remove(&mut self, line: usize) -> Option<T> {
+        let idx = self.visible_index(line)?;
+        let (anchor, value) = self.entries.swap_remove(idx);
+        self.buffer.remove_anchor(anchor);
+        Some(value)
+    }
+
+    // Returns every visible decoration with its current line, in line order (e.g. to paint the
+    // gutter in one pass).
+    pub fn iter(&self) -> Vec<(usize, &T)> {
+        let mut visible: Vec<(usize, usize, &T)> = self.resolve()
+            .into_iter()
+            .filter_map(|(idx, resolved)| resolved.map(|(line, byte_idx)| (line, byte_idx, &self.entries[idx].1)))
+            .collect();
+        visible.sort_by_key(|&(line, byte_idx, _)| (line, byte_idx));
+        visible.dedup_by_key(|&mut (line, _, _)| line);
+        visible.into_iter().map(|(line, _, value)| (line, value)).collect()
+    }
+
+    // Current '(line, byte_idx)' of each entry's anchor, or None if its line was deleted.
+    // Resolved under one lock so all entries see the same buffer state.
+    fn resolve(&self) -> Vec<(usize, Option<(usize, usize)>)> {
+        let rope = self.buffer.content.lock();
+        let anchors = self.buffer.anchors.lock();
+        self.entries.iter()
+            .enumerate()
+            .map(|(idx, (anchor, _))| {
+                (idx, anchors.position(*anchor).map(|byte_idx| (rope.byte_to_line(byte_idx), byte_idx)))
+            })
+            .collect()
+    }
+
+    // Index of the entry shown on 'line': the one whose anchor comes first on that line.
+    fn visible_index(&self, line: usize) -> Option<usize> {
+        self.resolve()
+            .into_iter()
+            .filter_map(|(idx, resolved)| resolved.filter(|(l, _)| *l == line).map(|(_, byte_idx)| (byte_idx, idx)))
+            .min()
+            .map(|(_, idx)| idx)
+    }
+
+    // Forgets entries whose line was deleted.
+    fn prune(&mut self) {
+        let buffer = &self.buffer;
+        self.entries.retain(|(anchor, _)| buffer.anchor_position(*anchor).is_some());
+    }
+}
+
+impl<T> Drop for LineDecorations<T> {
+    fn drop(&mut self) {
+        for (anchor, _) in &self.entries {
+            self.buffer.remove_anchor(*anchor);
+        }
+    }
+}
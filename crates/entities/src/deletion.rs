@@ -0,0 +1,51 @@
+// Deletion Keys
+// Backspace and Delete as editors implement them: with a selection they remove the selection,
+// without one they remove a single grapheme cluster (so "e" + combining accent, an emoji ZWJ
+// sequence or "\r\n" go away in one keystroke).
+use ropey::Rope;
+
+use crate::edits::check_range;
+use crate::rope_utils::{grapheme_len_after, grapheme_len_before};
+use crate::{EditError, EditOrigin, TextBuffer, TextPosition, TextRange};
+
+
+impl TextBuffer {
+    // Backspace: removes 'selection', or the grapheme before the cursor when it is empty.
+    // Returns the resulting cursor position.
+    pub async fn backspace(&self, selection: TextRange) -> Result<TextPosition, EditError> {
+        self.delete_selection_or(selection, |rope, cursor| {
+            let len = grapheme_len_before(rope, cursor);
+            TextRange::new(cursor - len, cursor)
+        }).await
+    }
+
+    // Delete: removes 'selection', or the grapheme after the cursor when it is empty.
+    // Returns the resulting cursor position.
+    pub async fn delete_forward(&self, selection: TextRange) -> Result<TextPosition, EditError> {
+        self.delete_selection_or(selection, |rope, cursor| {
+            TextRange::new(cursor, cursor + grapheme_len_after(rope, cursor))
+        }).await
+    }
+
+    // Removes 'selection' if it isn't empty, otherwise the range 'around_cursor' picks. Fails,
+    // changing nothing, if the removal touches a protected region or is vetoed.
+    async fn delete_selection_or(
+        &self,
+        selection: TextRange,
+        around_cursor: impl FnOnce(&Rope, usize) -> TextRange,
+    ) -> Result<TextPosition, EditError> {
+        let mut rope = self.content.lock();
+        check_range(&rope, selection.start, selection.end)?;
+        let range = if selection.is_empty() { around_cursor(&rope, selection.start) } else { selection };
+        if range.is_empty() {
+            return Ok(TextPosition { byte_idx: range.start });
+        }
+        let event = self.apply_remove(&mut rope, range.start, range.len(), EditOrigin::User)?;
+        drop(rope);
+
+        if let Some(event) = event {
+            self.notify_observers(event).await;
+        }
+        Ok(TextPosition { byte_idx: range.start })
+    }
+}
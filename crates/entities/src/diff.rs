@@ -0,0 +1,480 @@
+// Line Diff
+// Computes line-by-line differences (Myers' O(ND) algorithm) between two texts and formats them
+// as a standard unified diff. Lines keep their terminators, so a changed line ending counts as a
+// changed line.
+use std::fmt::Write as _;
+use std::io;
+use std::sync::atomic::Ordering;
+
+use ropey::Rope;
+
+use crate::stats::count_words;
+use crate::store::{DocumentStore, LocalFsStore};
+use crate::{Document, EditOrigin, TextBuffer, TextBufferChangedEvent};
+
+
+// Diff Operation
+// A run of 'len' lines. 'old_start'/'new_start' are the 0-based line indices where the run
+// begins in the old and new text ('Insert' consumes no old lines, 'Delete' no new lines).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiffOp {
+    Equal { old_start: usize, new_start: usize, len: usize },
+    Delete { old_start: usize, new_start: usize, len: usize },
+    Insert { old_start: usize, new_start: usize, len: usize },
+}
+
+impl DiffOp {
+    fn kind(&self) -> LineKind {
+        match self {
+            DiffOp::Equal { .. } => LineKind::Equal,
+            DiffOp::Delete { .. } => LineKind::Delete,
+            DiffOp::Insert { .. } => LineKind::Insert,
+        }
+    }
+}
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LineKind {
+    Equal,
+    Delete,
+    Insert,
+}
+
+
+// Splits text into lines, each keeping its "\n" terminator (the last line may have none).
+pub(crate) fn split_lines(text: &str) -> Vec<&str> {
+    text.split_inclusive('\n').collect()
+}
+
+
+// Computes the line diff turning 'old' into 'new'.
+pub fn diff_lines(old: &str, new: &str) -> Vec<DiffOp> {
+    let old_lines = split_lines(old);
+    let new_lines = split_lines(new);
+    diff_line_slices(&old_lines, &new_lines)
+}
+
+pub(crate) fn diff_line_slices(old: &[&str], new: &[&str]) -> Vec<DiffOp> {
+    // Common prefix and suffix are cheap to strip and are the bulk of most real diffs.
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..].iter().rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    let mut kinds = Vec::new();
+    kinds.extend(std::iter::repeat_n(LineKind::Equal, prefix));
+    myers(&old[prefix..old.len() - suffix], &new[prefix..new.len() - suffix], &mut kinds);
+    kinds.extend(std::iter::repeat_n(LineKind::Equal, suffix));
+
+    // Merge the per-line steps into runs.
+    let mut ops = Vec::new();
+    let (mut old_idx, mut new_idx) = (0, 0);
+    let mut i = 0;
+    while i < kinds.len() {
+        let kind = kinds[i];
+        let len = kinds[i..].iter().take_while(|k| **k == kind).count();
+        let (old_start, new_start) = (old_idx, new_idx);
+        match kind {
+            LineKind::Equal => {
+                ops.push(DiffOp::Equal { old_start, new_start, len });
+                old_idx += len;
+                new_idx += len;
+            }
+            LineKind::Delete => {
+                ops.push(DiffOp::Delete { old_start, new_start, len });
+                old_idx += len;
+            }
+            LineKind::Insert => {
+                ops.push(DiffOp::Insert { old_start, new_start, len });
+                new_idx += len;
+            }
+        }
+        i += len;
+    }
+    ops
+}
+
+// Appends the shortest edit script between 'old' and 'new' to 'out', one step per line.
+// Keeps one 'V' snapshot per edit distance (O(D^2) memory), which is fine once the common
+// prefix/suffix has been trimmed.
+fn myers(old: &[&str], new: &[&str], out: &mut Vec<LineKind>) {
+    let (n, m) = (old.len() as isize, new.len() as isize);
+    let max = n + m;
+    let offset = max + 1;
+    let mut v = vec![0isize; (2 * max + 3) as usize];
+    // trace[d] holds v[-(d+1)..=(d+1)] as it was at the start of round d.
+    let mut trace: Vec<Vec<isize>> = Vec::new();
+
+    'search: for d in 0..=max {
+        trace.push(v[(offset - d - 1) as usize..=(offset + d + 1) as usize].to_vec());
+        for k in (-d..=d).step_by(2) {
+            let idx = (offset + k) as usize;
+            let mut x = if k == -d || (k != d && v[idx - 1] < v[idx + 1]) { v[idx + 1] } else { v[idx - 1] + 1 };
+            let mut y = x - k;
+            while x < n && y < m && old[x as usize] == new[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            v[idx] = x;
+            if x >= n && y >= m {
+                break 'search;
+            }
+        }
+    }
+
+    // Walk back from (n, m) to (0, 0) collecting the steps in reverse.
+    let mut steps = Vec::new();
+    let (mut x, mut y) = (n, m);
+    for d in (0..trace.len() as isize).rev() {
+        let snapshot = &trace[d as usize];
+        let at = |k: isize| snapshot[(k + d + 1) as usize];
+        let k = x - y;
+        let prev_k = if k == -d || (k != d && at(k - 1) < at(k + 1)) { k + 1 } else { k - 1 };
+        let prev_x = at(prev_k);
+        let prev_y = prev_x - prev_k;
+        while x > prev_x && y > prev_y {
+            steps.push(LineKind::Equal);
+            x -= 1;
+            y -= 1;
+        }
+        if d > 0 {
+            steps.push(if x == prev_x { LineKind::Insert } else { LineKind::Delete });
+        }
+        x = prev_x;
+        y = prev_y;
+    }
+    out.extend(steps.into_iter().rev());
+}
+
+
+// Returns true if 'rope' holds exactly 'text', comparing chunk by chunk.
+fn rope_equals(rope: &Rope, text: &str) -> bool {
+    if rope.len_bytes() != text.len() {
+        return false;
+    }
+    let mut rest = text.as_bytes();
+    rope.chunks().all(|chunk| {
+        let (head, tail) = rest.split_at(chunk.len());
+        rest = tail;
+        head == chunk.as_bytes()
+    })
+}
+
+
+// Turns the line diff from 'old' to 'new' into '(start_byte, end_byte, new_text)' replacements
+// against 'old', as accepted by 'apply_edits'. A deletion directly followed by an insertion
+// becomes one replacement.
+fn diff_to_edits(old: &str, new: &str) -> Vec<(usize, usize, String)> {
+    let old_lines = split_lines(old);
+    let new_lines = split_lines(new);
+    // Start byte of every old line, plus the end of the text.
+    let mut old_offsets = Vec::with_capacity(old_lines.len() + 1);
+    let mut offset = 0;
+    for line in &old_lines {
+        old_offsets.push(offset);
+        offset += line.len();
+    }
+    old_offsets.push(offset);
+
+    let mut edits: Vec<(usize, usize, String)> = Vec::new();
+    for op in diff_line_slices(&old_lines, &new_lines) {
+        match op {
+            DiffOp::Equal { .. } => {}
+            DiffOp::Delete { old_start, len, .. } => {
+                edits.push((old_offsets[old_start], old_offsets[old_start + len], String::new()));
+            }
+            DiffOp::Insert { old_start, new_start, len } => {
+                let text = new_lines[new_start..new_start + len].concat();
+                let at = old_offsets[old_start];
+                match edits.last_mut() {
+                    Some(last) if last.1 == at && last.2.is_empty() => last.2 = text,
+                    _ => edits.push((at, at, text)),
+                }
+            }
+        }
+    }
+    edits
+}
+
+
+// Formats the diff from 'old' to 'new' as a unified diff with 'context' lines around each change.
+// Returns an empty string when the texts are identical.
+pub fn unified_diff(old: &str, new: &str, old_label: &str, new_label: &str, context: usize) -> String {
+    let old_lines = split_lines(old);
+    let new_lines = split_lines(new);
+    let ops = diff_line_slices(&old_lines, &new_lines);
+    if ops.iter().all(|op| op.kind() == LineKind::Equal) {
+        return String::new();
+    }
+
+    // Flatten to one entry per line: (kind, old line index, new line index).
+    let mut lines = Vec::new();
+    for op in &ops {
+        let (DiffOp::Equal { old_start, new_start, len }
+        | DiffOp::Delete { old_start, new_start, len }
+        | DiffOp::Insert { old_start, new_start, len }) = *op;
+        for i in 0..len {
+            lines.push(match op.kind() {
+                LineKind::Equal => (LineKind::Equal, old_start + i, new_start + i),
+                LineKind::Delete => (LineKind::Delete, old_start + i, new_start),
+                LineKind::Insert => (LineKind::Insert, old_start, new_start + i),
+            });
+        }
+    }
+
+    // Group changed lines into hunks whose context would touch or overlap.
+    let changed: Vec<usize> = (0..lines.len()).filter(|&i| lines[i].0 != LineKind::Equal).collect();
+    let mut hunks: Vec<(usize, usize)> = Vec::new();
+    for &i in &changed {
+        let start = i.saturating_sub(context);
+        let end = (i + context + 1).min(lines.len());
+        match hunks.last_mut() {
+            Some(last) if start <= last.1 => last.1 = end,
+            _ => hunks.push((start, end)),
+        }
+    }
+
+    let mut out = String::new();
+    let _ = writeln!(out, "--- {old_label}");
+    let _ = writeln!(out, "+++ {new_label}");
+    for (start, end) in hunks {
+        let hunk = &lines[start..end];
+        let old_count = hunk.iter().filter(|l| l.0 != LineKind::Insert).count();
+        let new_count = hunk.iter().filter(|l| l.0 != LineKind::Delete).count();
+        // Unified diff line numbers are 1-based; an empty side is reported as "start-1,0".
+        let old_first = if old_count == 0 { hunk[0].1 } else { hunk[0].1 + 1 };
+        let new_first = if new_count == 0 { hunk[0].2 } else { hunk[0].2 + 1 };
+        let _ = writeln!(out, "@@ -{old_first},{old_count} +{new_first},{new_count} @@");
+
+        for &(kind, old_idx, new_idx) in hunk {
+            let (marker, line) = match kind {
+                LineKind::Equal => (' ', old_lines[old_idx]),
+                LineKind::Delete => ('-', old_lines[old_idx]),
+                LineKind::Insert => ('+', new_lines[new_idx]),
+            };
+            out.push(marker);
+            match line.strip_suffix('\n') {
+                Some(content) => {
+                    out.push_str(content);
+                    out.push('\n');
+                }
+                None => {
+                    out.push_str(line);
+                    out.push_str("\n\\ No newline at end of file\n");
+                }
+            }
+        }
+    }
+    out
+}
+
+
+impl TextBuffer {
+    // Fraction of the old content 'set_contents_diffed' may remove before it reports 'Reloaded'.
+    pub const DIFFED_RELOAD_RATIO: f64 = 0.5;
+
+    // Returns the line diff turning the buffer's current content into 'other'.
+    pub fn diff(&self, other: &str) -> Vec<DiffOp> {
+        diff_lines(&self.get_text(), other)
+    }
+
+    // Maps each byte position in 'positions' through the line diff turning the current content
+    // into 'new_text', so cursors and selections land in the same logical place after a reload
+    // or a formatter run. Positions in unchanged text move with it; positions inside a changed
+    // region map to the start of its replacement. Positions past the end are clamped to it.
+    pub fn remap_through_diff(&self, positions: &[usize], new_text: &str) -> Vec<usize> {
+        let old_text = self.get_text();
+        let edits = diff_to_edits(&old_text, new_text);
+        // Start of each edit in the new text.
+        let mut new_starts = Vec::with_capacity(edits.len());
+        let mut delta = 0isize;
+        for (start, end, text) in &edits {
+            new_starts.push(start.saturating_add_signed(delta));
+            delta += text.len() as isize - (end - start) as isize;
+        }
+
+        positions.iter().map(|&position| {
+            let position = position.min(old_text.len());
+            // The last edit starting at or before 'position' decides where it goes.
+            let i = edits.partition_point(|(start, _, _)| *start <= position);
+            let Some(i) = i.checked_sub(1) else {
+                return position;
+            };
+            let (_, end, text) = &edits[i];
+            if position < *end {
+                new_starts[i]
+            } else {
+                new_starts[i] + text.len() + (position - end)
+            }
+        }).collect()
+    }
+
+    // Replaces the whole content with 'new_text' (e.g. formatter output) by applying only the
+    // line-level differences, all under one lock. Anchors on unchanged lines stay put and
+    // observers receive a 'Batch' of precise events to do incremental work with. If the removed
+    // text covers more than 'DIFFED_RELOAD_RATIO' of the old content, a single 'Reloaded' is
+    // reported instead; the edits themselves, and so the anchors, are still applied precisely.
+    // Nothing is emitted when the content is already equal, or when a pre-edit observer vetoes
+    // any of the edits or one touches a protected region (the content is then left as it was).
+    pub async fn set_contents_diffed(&self, new_text: &str) {
+        let mut rope = self.content.lock();
+        let old_text = rope.to_string();
+        let edits = diff_to_edits(&old_text, new_text);
+        let removed: usize = edits.iter().map(|(start, end, _)| end - start).sum();
+        // Edits computed from the buffer's own text are valid, so this only fails if they are
+        // vetoed or touch a protected region.
+        let Ok((events, _)) = self.apply_edits_locked(&mut rope, edits) else {
+            return;
+        };
+
+        let event = if events.is_empty() {
+            None
+        } else if removed as f64 > Self::DIFFED_RELOAD_RATIO * old_text.len() as f64 {
+            Some(TextBufferChangedEvent::Reloaded {
+                len_bytes: rope.len_bytes(),
+                len_lines: rope.len_lines(),
+                origin: EditOrigin::User,
+                version: events.last().map_or(0, TextBufferChangedEvent::version),
+            })
+        } else {
+            Some(self.batch(&rope, events))
+        };
+        drop(rope);
+
+        if let Some(event) = event {
+            self.notify_observers(event).await;
+        }
+    }
+
+    // Replaces the whole content with 'new_text' and emits a single 'Reloaded', e.g. when the
+    // file changed on disk. Anchors are treated as if all text was removed and re-inserted, and
+    // line anchors are dropped; use 'set_contents_diffed' to keep them.
+    // If the content is already equal (a file watcher firing after a mere touch), nothing is
+    // replaced or emitted and false is returned.
+    pub async fn reload(&self, new_text: &str) -> bool {
+        let event = {
+            let mut rope = self.content.lock();
+            if rope_equals(&rope, new_text) {
+                return false;
+            }
+            let old_len = rope.len_bytes();
+            let mut anchors = self.anchors.lock();
+            anchors.drop_removed_lines(&rope, 0, old_len);
+            anchors.shift_for_remove(0, old_len);
+            anchors.shift_for_insert(0, new_text.len());
+            drop(anchors);
+            *rope = Rope::from_str(new_text);
+            self.word_count.store(count_words(new_text), Ordering::SeqCst);
+            let event = TextBufferChangedEvent::Reloaded {
+                len_bytes: rope.len_bytes(),
+                len_lines: rope.len_lines(),
+                origin: EditOrigin::User,
+                version: self.bump_version(),
+            };
+            self.changes.lock().record(&event);
+            event
+        };
+        self.notify_observers(event).await;
+        true
+    }
+
+    // Returns the half-open byte range '(start, end)' of the buffer's content that differs from
+    // 'other', found by skipping the common prefix and suffix, or None if they are identical.
+    // The range is widened to whole chars. Text outside it is unchanged, so a renderer can keep
+    // its state for those lines after a wholesale reload. Much cheaper than 'diff' for a small
+    // change in a big file, since it only compares bytes and never allocates the buffer's text.
+    pub fn first_last_difference(&self, other: &str) -> Option<(usize, usize)> {
+        let rope = self.content.lock();
+        let other = other.as_bytes();
+        let chunks: Vec<&str> = rope.chunks().collect();
+
+        let mut prefix = 0;
+        for chunk in &chunks {
+            let common = chunk.bytes().zip(&other[prefix..]).take_while(|(a, b)| a == *b).count();
+            prefix += common;
+            if common < chunk.len() {
+                break;
+            }
+        }
+        let len = rope.len_bytes();
+        if prefix == len && prefix == other.len() {
+            return None;
+        }
+
+        // The suffix may not overlap the prefix in either text.
+        let max_suffix = len.min(other.len()) - prefix;
+        let mut suffix = 0;
+        for chunk in chunks.iter().rev() {
+            let other_rest = &other[..other.len() - suffix];
+            let common = chunk.bytes().rev()
+                .zip(other_rest.iter().rev())
+                .take_while(|(a, b)| a == *b)
+                .count()
+                .min(max_suffix - suffix);
+            suffix += common;
+            if common < chunk.len() {
+                break;
+            }
+        }
+
+        // Widen to char boundaries: a differing char may share its leading bytes.
+        let start = rope.char_to_byte(rope.byte_to_char(prefix));
+        let mut end = len - suffix;
+        let end_char = rope.byte_to_char(end);
+        if rope.char_to_byte(end_char) < end {
+            end = rope.char_to_byte(end_char + 1);
+        }
+        Some((start, end))
+    }
+}
+
+
+impl Document {
+    // Returns a unified diff of what saving would change: the file on disk ("a/") against the
+    // current buffer ("b/"), reading the local filesystem (see 'diff_against_disk_with').
+    pub async fn diff_against_disk(&self) -> io::Result<String> {
+        self.diff_against_disk_with(&LocalFsStore).await
+    }
+
+    // Like 'diff_against_disk', reading the file from 'store'. Empty if they are identical. The
+    // file is decoded with the document's encoding, so a Latin-1 or UTF-16 file diffs as text.
+    // Fails if the document has no path or the file can't be read.
+    pub async fn diff_against_disk_with<S: DocumentStore>(&self, store: &S) -> io::Result<String> {
+        let path = self.file_path.as_deref().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "document has no file path")
+        })?;
+        let on_disk = self.encoding().encoding.decode(&store.read(path).await?);
+        let current = self.text_buffer.get_text();
+        Ok(unified_diff(&on_disk, &current, &format!("a/{path}"), &format!("b/{path}"), 3))
+    }
+
+    // Returns the (0-based) buffer lines that were added or modified compared to the file on
+    // the local filesystem (see 'changed_lines_with').
+    pub async fn changed_lines(&self) -> io::Result<Vec<usize>> {
+        self.changed_lines_with(&LocalFsStore).await
+    }
+
+    // Like 'changed_lines', reading the file from 'store', for "modified line" markers in the
+    // gutter. Deleted lines have no line of their own and are not reported. If the text still
+    // hashes the same as when it was last loaded or saved, the file isn't read at all.
+    // Fails if the document has no path or the file can't be read.
+    pub async fn changed_lines_with<S: DocumentStore>(&self, store: &S) -> io::Result<Vec<usize>> {
+        if *self.saved_hash.lock() == Some(self.text_buffer.content_hash()) {
+            return Ok(Vec::new());
+        }
+        let path = self.file_path.as_deref().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "document has no file path")
+        })?;
+        let on_disk = self.encoding().encoding.decode(&store.read(path).await?);
+        let current = self.text_buffer.get_text();
+        Ok(diff_lines(&on_disk, &current).into_iter()
+            .filter_map(|op| match op {
+                DiffOp::Insert { new_start, len, .. } => Some(new_start..new_start + len),
+                DiffOp::Equal { .. } | DiffOp::Delete { .. } => None,
+            })
+            .flatten()
+            .collect())
+    }
+}
@@ -0,0 +1,44 @@
+// Debug Output
+// Logging a buffer without copying it into a 'String' first: 'Display' streams the rope's chunks,
+// and 'debug_dump' summarizes the buffer's state with a short preview of the text.
+use std::fmt;
+
+use crate::graphemes::leading_graphemes;
+use crate::TextBuffer;
+
+
+// Writes the whole text chunk by chunk. The buffer stays locked while it is written, so the
+// output is one consistent version, but writing into a slow sink blocks edits meanwhile.
+impl fmt::Display for TextBuffer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let rope = self.content.lock();
+        for chunk in rope.chunks() {
+            f.write_str(chunk)?;
+        }
+        Ok(())
+    }
+}
+
+
+impl TextBuffer {
+    // Number of grapheme clusters shown in the 'debug_dump' preview.
+    const DUMP_PREVIEW_GRAPHEMES: usize = 40;
+
+    // Returns a one-line summary of the buffer for logs: version, sizes, observer count and the
+    // start of the text with line breaks and other control chars escaped.
+    pub fn debug_dump(&self) -> String {
+        let observer_count = self.observer_count();
+        let rope = self.content.lock();
+        let preview = leading_graphemes(rope.slice(..), Self::DUMP_PREVIEW_GRAPHEMES);
+        let ellipsis = if preview.len() < rope.len_bytes() { "..." } else { "" };
+        format!(
+            "TextBuffer {{ version: {}, len_bytes: {}, len_lines: {}, observer_count: {}, preview: \"{}\"{} }}",
+            self.version(),
+            rope.len_bytes(),
+            rope.len_lines(),
+            observer_count,
+            preview.escape_debug(),
+            ellipsis,
+        )
+    }
+}
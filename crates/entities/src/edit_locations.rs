@@ -0,0 +1,69 @@
+// Edit Locations
+// "Go to last edit": the buffer remembers where its recent edits happened. The locations are
+// anchors, so they stay valid while the text around them changes.
+use std::collections::VecDeque;
+
+use ropey::Rope;
+
+use crate::anchor::AnchorSet;
+use crate::{AnchorBias, AnchorId, TextBuffer, TextPosition};
+
+
+// Edit Locations Struct
+// Anchors of the most recent edit locations, oldest first. At most 'CAPACITY' are kept.
+#[derive(Debug, Default)]
+pub(crate) struct EditLocations {
+    entries: VecDeque<AnchorId>,
+}
+
+impl EditLocations {
+    const CAPACITY: usize = 32;
+
+    // Records an edit that left the cursor at 'byte_idx' in the edited 'rope'. An edit on the
+    // same line as the most recent location moves that location instead, so typing a word or a
+    // line leaves one entry rather than one per keystroke.
+    pub(crate) fn record(&mut self, anchors: &mut AnchorSet, rope: &Rope, byte_idx: usize) {
+        if let Some(&last) = self.entries.back()
+            && anchors.position(last).is_some_and(|last_idx| rope.byte_to_line(last_idx) == rope.byte_to_line(byte_idx))
+        {
+            self.entries.pop_back();
+            anchors.remove(last);
+        }
+        self.entries.push_back(anchors.create(byte_idx, AnchorBias::Right));
+        if self.entries.len() > Self::CAPACITY
+            && let Some(oldest) = self.entries.pop_front()
+        {
+            anchors.remove(oldest);
+        }
+    }
+}
+
+
+impl TextBuffer {
+    // Edit Locations
+
+    // Returns where the most recent edit left the cursor: the end of the inserted text or the
+    // point of a removal, shifted by the edits since. None if the buffer was never edited.
+    pub fn last_edit_position(&self) -> Option<TextPosition> {
+        self.edit_history_positions(1).pop()
+    }
+
+    // Returns up to 'n' recent edit locations, the most recent first, for cycling back through
+    // them. Edits on one line in a row count as one location, and locations that edits have
+    // since merged into the same position are reported once.
+    pub fn edit_history_positions(&self, n: usize) -> Vec<TextPosition> {
+        let anchors = self.anchors.lock();
+        let mut positions: Vec<TextPosition> = Vec::new();
+        for &id in self.edit_locations.lock().entries.iter().rev() {
+            if positions.len() == n {
+                break;
+            }
+            if let Some(byte_idx) = anchors.position(id)
+                && positions.last().is_none_or(|last| last.byte_idx != byte_idx)
+            {
+                positions.push(TextPosition { byte_idx });
+            }
+        }
+        positions
+    }
+}
@@ -0,0 +1,272 @@
+// Grouped Edits
+// Applying several edits as one unit: 'apply_edits' for a list of replacements expressed against
+// the current text (LSP-style), and 'transaction' for a sequence of edits computed on the fly.
+// Both hold the content lock for the whole group and deliver a single 'Batch' event afterwards.
+use std::fmt;
+
+use parking_lot::MutexGuard;
+use ropey::Rope;
+
+use crate::{EditOrigin, TextBuffer, TextBufferChangedEvent, TextPosition, TextRange};
+
+
+// Edit Error
+// Why an edit was rejected. Rejected edits leave the buffer untouched.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EditError {
+    // The byte range '[start, end)' reaches past the end of the text ('len_bytes').
+    OutOfBounds { start: usize, end: usize, len_bytes: usize },
+    // The char range '[start, end)' reaches past the end of the text ('len_chars').
+    CharOutOfBounds { start: usize, end: usize, len_chars: usize },
+    // 'byte_idx' falls inside a multi-byte char.
+    NotCharBoundary { byte_idx: usize },
+    // Two edits of one 'apply_edits' call overlap.
+    OverlappingEdits,
+    // A pre-edit observer vetoed the edit (see 'PreEditObserver').
+    Vetoed,
+    // The edit would change text of a protected region (see 'add_protected_region').
+    ProtectedRegion,
+    // The text an event changed is no longer where the event says (see 'apply_inverse').
+    TextMismatch,
+    // The event doesn't carry enough to be inverted ('Reloaded', 'Appended').
+    NotInvertible,
+    // 'line' doesn't exist in a text of 'len_lines' lines.
+    LineOutOfBounds { line: usize, len_lines: usize },
+}
+
+impl fmt::Display for EditError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EditError::OutOfBounds { start, end, len_bytes } => {
+                write!(f, "byte range {start}..{end} is out of bounds for a text of {len_bytes} bytes")
+            }
+            EditError::CharOutOfBounds { start, end, len_chars } => {
+                write!(f, "char range {start}..{end} is out of bounds for a text of {len_chars} chars")
+            }
+            EditError::NotCharBoundary { byte_idx } => write!(f, "byte {byte_idx} is not on a char boundary"),
+            EditError::OverlappingEdits => write!(f, "edits overlap"),
+            EditError::Vetoed => write!(f, "edit was vetoed"),
+            EditError::ProtectedRegion => write!(f, "edit touches a protected region"),
+            EditError::TextMismatch => write!(f, "text does not match the event"),
+            EditError::NotInvertible => write!(f, "event cannot be inverted"),
+            EditError::LineOutOfBounds { line, len_lines } => {
+                write!(f, "line {line} is out of bounds for a text of {len_lines} lines")
+            }
+        }
+    }
+}
+
+impl std::error::Error for EditError {}
+
+
+// Checks that '[start, end)' is a valid, char-aligned range of 'rope'.
+pub(crate) fn check_range(rope: &Rope, start: usize, end: usize) -> Result<(), EditError> {
+    let len_bytes = rope.len_bytes();
+    if start > end || end > len_bytes {
+        return Err(EditError::OutOfBounds { start, end, len_bytes });
+    }
+    for byte_idx in [start, end] {
+        if rope.get_byte_slice(byte_idx..byte_idx).is_none() {
+            return Err(EditError::NotCharBoundary { byte_idx });
+        }
+    }
+    Ok(())
+}
+
+
+// Edit Summary
+// What 'apply_edits' did, for remapping cursors and selections to the new text. Built from the
+// edited regions rather than a closure, so it can be kept and reused.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EditSummary {
+    // '(old_start, old_end, new_len)' for each edit, in text order.
+    regions: Vec<(usize, usize, usize)>,
+}
+
+impl EditSummary {
+    // Returns the net change in length, in bytes.
+    pub fn delta(&self) -> isize {
+        self.regions.iter().map(|(start, end, new_len)| *new_len as isize - (end - start) as isize).sum()
+    }
+
+    // Maps a byte offset in the text before the edits to the text after them. Offsets outside
+    // every edited region shift by the edits before them; offsets inside a replaced region
+    // (including its start) map to the start of its new text. An offset exactly at an
+    // insertion or at the end of a region ends up after the new text.
+    pub fn map_position(&self, old_byte: usize) -> usize {
+        let mut delta: isize = 0;
+        for (start, end, new_len) in &self.regions {
+            if old_byte < *start {
+                break;
+            }
+            if old_byte < *end {
+                return start.checked_add_signed(delta).expect("mapped offset underflowed");
+            }
+            delta += *new_len as isize - (end - start) as isize;
+        }
+        old_byte.checked_add_signed(delta).expect("mapped offset underflowed")
+    }
+}
+
+
+// Checks that '[start, end)' is a valid char range of 'rope'.
+fn check_char_range(rope: &Rope, start: usize, end: usize) -> Result<(), EditError> {
+    let len_chars = rope.len_chars();
+    if start > end || end > len_chars {
+        return Err(EditError::CharOutOfBounds { start, end, len_chars });
+    }
+    Ok(())
+}
+
+
+// Transaction
+// Edits made inside 'TextBuffer::transaction'. Each edit sees the effect of the previous ones, and
+// no other writer can interleave because the content lock is held throughout.
+pub struct Transaction<'a> {
+    buffer: &'a TextBuffer,
+    rope: MutexGuard<'a, Rope>,
+    events: Vec<TextBufferChangedEvent>,
+}
+
+impl Transaction<'_> {
+    // Inserts text at a given byte position. Fails, changing nothing, if the position is invalid,
+    // inside a protected region or the insertion is vetoed.
+    pub fn insert(&mut self, position: TextPosition, text: &str) -> Result<(), EditError> {
+        check_range(&self.rope, position.byte_idx, position.byte_idx)?;
+        let event = self.buffer.apply_insert(&mut self.rope, position.byte_idx, text, EditOrigin::User)?;
+        self.events.extend(event);
+        Ok(())
+    }
+
+    // Removes text from a given byte position for a specified length. Fails like 'insert'.
+    pub fn remove(&mut self, position: TextPosition, len_bytes: usize) -> Result<(), EditError> {
+        check_range(&self.rope, position.byte_idx, position.byte_idx + len_bytes)?;
+        let event = self.buffer.apply_remove(&mut self.rope, position.byte_idx, len_bytes, EditOrigin::User)?;
+        self.events.extend(event);
+        Ok(())
+    }
+
+    // Retrieves the text as it is inside the transaction.
+    pub fn get_text(&self) -> String {
+        self.rope.to_string()
+    }
+
+    // Returns the length of the text inside the transaction in bytes.
+    pub fn len_bytes(&self) -> usize {
+        self.rope.len_bytes()
+    }
+}
+
+
+impl TextBuffer {
+    // Grouped Edits
+
+    // Applies replacements given as '(start_byte, end_byte, new_text)' against the current text,
+    // all under one lock, and notifies observers with a single 'Batch'. The returned summary maps
+    // positions from the old text to the new one.
+    // Edits may come in any order but must not overlap (an insertion at the very end of another
+    // edit's range is fine). Every edit is validated first, so on error nothing is applied.
+    pub async fn apply_edits(&self, edits: Vec<(usize, usize, String)>) -> Result<EditSummary, EditError> {
+        let mut rope = self.content.lock();
+        let (events, summary) = self.apply_edits_locked(&mut rope, edits)?;
+        let event = (!events.is_empty()).then(|| self.batch(&rope, events));
+        drop(rope);
+
+        if let Some(event) = event {
+            self.notify_observers(event).await;
+        }
+        Ok(summary)
+    }
+
+    // The body of 'apply_edits' on an already-locked rope. Returns the member events, in order.
+    pub(crate) fn apply_edits_locked(
+        &self,
+        rope: &mut Rope,
+        mut edits: Vec<(usize, usize, String)>,
+    ) -> Result<(Vec<TextBufferChangedEvent>, EditSummary), EditError> {
+        // Stable, so insertions at the same offset keep the caller's order.
+        edits.sort_by_key(|(start, end, _)| (*start, *end));
+
+        for (start, end, _) in &edits {
+            check_range(rope, *start, *end)?;
+        }
+        if edits.windows(2).any(|pair| pair[1].0 < pair[0].1) {
+            return Err(EditError::OverlappingEdits);
+        }
+        for (start, end, text) in &edits {
+            self.check_edit(rope, TextRange::new(*start, *end), text, EditOrigin::User)?;
+        }
+
+        // Front to back, shifting later edits by the size change so far. This way each member
+        // event's line numbers are also valid after the whole batch.
+        let mut events = Vec::new();
+        let mut delta: isize = 0;
+        for (start, end, text) in &edits {
+            let removed = end - start;
+            // Earlier edits never move an offset before 0, so the sum can't go negative.
+            let start = start.checked_add_signed(delta).expect("shifted edit offset underflowed");
+            if removed > 0 {
+                events.push(self.apply_remove_unchecked(rope, start, removed, EditOrigin::User));
+            }
+            if !text.is_empty() {
+                events.push(self.apply_insert_unchecked(rope, start, text, EditOrigin::User));
+            }
+            delta += text.len() as isize - removed as isize;
+        }
+        let summary = EditSummary { regions: edits.iter().map(|(start, end, text)| (*start, *end, text.len())).collect() };
+        Ok((events, summary))
+    }
+
+    // Char-Indexed Edits
+    // For callers that keep positions in chars. Indices are converted to bytes once, under the
+    // same lock as the edit.
+
+    // Inserts 'text' at char index 'char_idx' and returns the char index just after it.
+    pub async fn insert_char_idx(&self, char_idx: usize, text: &str) -> Result<usize, EditError> {
+        let event = {
+            let mut rope = self.content.lock();
+            check_char_range(&rope, char_idx, char_idx)?;
+            if text.is_empty() {
+                return Ok(char_idx);
+            }
+            let byte_idx = rope.char_to_byte(char_idx);
+            self.apply_insert(&mut rope, byte_idx, text, EditOrigin::User)?
+        };
+        if let Some(event) = event {
+            self.notify_observers(event).await;
+        }
+        Ok(char_idx + text.chars().count())
+    }
+
+    // Removes the chars '[start_char, end_char)' and returns 'start_char', where the cursor ends up.
+    pub async fn remove_char_range(&self, start_char: usize, end_char: usize) -> Result<usize, EditError> {
+        let event = {
+            let mut rope = self.content.lock();
+            check_char_range(&rope, start_char, end_char)?;
+            if start_char == end_char {
+                return Ok(start_char);
+            }
+            let (start, end) = (rope.char_to_byte(start_char), rope.char_to_byte(end_char));
+            self.apply_remove(&mut rope, start, end - start, EditOrigin::User)?
+        };
+        if let Some(event) = event {
+            self.notify_observers(event).await;
+        }
+        Ok(start_char)
+    }
+
+    // Runs 'f' with exclusive access to the text and delivers everything it changed as a single
+    // 'Batch' once it returns. Keep 'f' short: the buffer is locked while it runs.
+    pub async fn transaction<R>(&self, f: impl FnOnce(&mut Transaction<'_>) -> R) -> R {
+        let mut transaction = Transaction { buffer: self, rope: self.content.lock(), events: Vec::new() };
+        let result = f(&mut transaction);
+        let Transaction { rope, events, .. } = transaction;
+        let event = (!events.is_empty()).then(|| self.batch(&rope, events));
+        drop(rope);
+
+        if let Some(event) = event {
+            self.notify_observers(event).await;
+        }
+        result
+    }
+}
@@ -0,0 +1,52 @@
+// Emptiness Tracking
+// Reports 'DocumentEvent::EmptinessChanged' when a document's text goes from empty to non-empty
+// or back, for placeholders ("Start typing...") and validations that care about nothing else.
+// A sync observer on the buffer compares the length after each change with the last state it
+// reported, so concurrent edits never report the same transition twice.
+use std::sync::{Arc, Weak};
+
+use parking_lot::Mutex;
+use tokio::sync::mpsc;
+
+use crate::{DocumentEvent, ISyncTextBufferObserver, TextBuffer, TextBufferChangedEvent};
+
+
+pub(crate) struct EmptinessWatcher {
+    // Weak, as the buffer owns this observer.
+    buffer: Weak<TextBuffer>,
+    was_empty: Mutex<bool>,
+    observers: Arc<Mutex<Vec<mpsc::Sender<DocumentEvent>>>>,
+}
+
+impl EmptinessWatcher {
+    // Starts watching 'buffer', sending transitions to the document observers in 'observers'.
+    pub(crate) fn watch(buffer: &Arc<TextBuffer>, observers: Arc<Mutex<Vec<mpsc::Sender<DocumentEvent>>>>) {
+        let watcher = EmptinessWatcher {
+            buffer: Arc::downgrade(buffer),
+            was_empty: Mutex::new(buffer.len_bytes() == 0),
+            observers,
+        };
+        buffer.add_sync_observer(Arc::new(watcher));
+    }
+}
+
+impl ISyncTextBufferObserver for EmptinessWatcher {
+    // Sync observers can't wait, so the event is sent with 'try_send': an observer whose channel
+    // is full misses it (and can still call 'TextBuffer::len_bytes').
+    fn on_buffer_changed(&self, _event: &TextBufferChangedEvent) {
+        let Some(buffer) = self.buffer.upgrade() else {
+            return;
+        };
+        let mut was_empty = self.was_empty.lock();
+        let is_empty = buffer.len_bytes() == 0;
+        if is_empty == *was_empty {
+            return;
+        }
+        *was_empty = is_empty;
+        let mut observers = self.observers.lock();
+        observers.retain(|sender| !sender.is_closed());
+        for sender in observers.iter() {
+            let _ = sender.try_send(DocumentEvent::EmptinessChanged(is_empty));
+        }
+    }
+}
@@ -0,0 +1,188 @@
+// Text Encodings
+// Guessing the encoding of a file's bytes and decoding them. The guess comes with a confidence so
+// the UI can flag shaky guesses ("UTF-8 (guessed)") and let the user re-open the file with the
+// right encoding.
+use std::io;
+
+use crate::store::{DocumentStore, LocalFsStore};
+use crate::{Document, TextPosition};
+
+
+// Encoding
+// The encodings a document can be decoded from. 'Latin1' (ISO-8859-1) maps every byte to a char,
+// so it is the fallback that never fails.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+    Latin1,
+}
+
+impl Encoding {
+    // Returns the name to show to the user.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Encoding::Utf8 => "UTF-8",
+            Encoding::Utf16Le => "UTF-16LE",
+            Encoding::Utf16Be => "UTF-16BE",
+            Encoding::Latin1 => "ISO-8859-1",
+        }
+    }
+
+    // Returns the byte order mark of the encoding (empty for Latin-1, which has none).
+    pub fn bom(&self) -> &'static [u8] {
+        match self {
+            Encoding::Utf8 => b"\xEF\xBB\xBF",
+            Encoding::Utf16Le => b"\xFF\xFE",
+            Encoding::Utf16Be => b"\xFE\xFF",
+            Encoding::Latin1 => b"",
+        }
+    }
+
+    // Decodes 'bytes', skipping a leading BOM of this encoding. Invalid sequences become U+FFFD.
+    pub fn decode(&self, bytes: &[u8]) -> String {
+        let bytes = bytes.strip_prefix(self.bom()).unwrap_or(bytes);
+        match self {
+            Encoding::Utf8 => String::from_utf8_lossy(bytes).into_owned(),
+            Encoding::Utf16Le | Encoding::Utf16Be => {
+                let units = bytes.chunks(2).map(|pair| match (pair, self) {
+                    ([lo, hi], Encoding::Utf16Le) => u16::from_le_bytes([*lo, *hi]),
+                    ([hi, lo], _) => u16::from_be_bytes([*hi, *lo]),
+                    // A dangling odd byte can't be a whole code unit.
+                    _ => 0xFFFD,
+                });
+                char::decode_utf16(units).map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER)).collect()
+            }
+            Encoding::Latin1 => bytes.iter().map(|&b| char::from(b)).collect(),
+        }
+    }
+
+    // Encodes 'text', prefixed with the BOM if 'with_bom'. Fails with 'InvalidData' if the text
+    // has a char Latin-1 can't represent, rather than losing it.
+    pub fn encode(&self, text: &str, with_bom: bool) -> io::Result<Vec<u8>> {
+        let mut bytes = if with_bom { self.bom().to_vec() } else { Vec::new() };
+        match self {
+            Encoding::Utf8 => bytes.extend_from_slice(text.as_bytes()),
+            Encoding::Utf16Le => bytes.extend(text.encode_utf16().flat_map(u16::to_le_bytes)),
+            Encoding::Utf16Be => bytes.extend(text.encode_utf16().flat_map(u16::to_be_bytes)),
+            Encoding::Latin1 => {
+                for c in text.chars() {
+                    let byte = u8::try_from(c).map_err(|_| {
+                        io::Error::new(io::ErrorKind::InvalidData, format!("{c:?} can't be encoded as {}", self.name()))
+                    })?;
+                    bytes.push(byte);
+                }
+            }
+        }
+        Ok(bytes)
+    }
+}
+
+
+// Encoding Detection
+// The result of guessing a file's encoding. 'confidence' runs from 0.0 to 1.0; a BOM or an
+// explicit choice by the user gives 1.0.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EncodingDetection {
+    pub encoding: Encoding,
+    pub confidence: f32,
+    pub had_bom: bool,
+}
+
+impl EncodingDetection {
+    // Confidence below which a detection should be presented as a guess.
+    pub const CONFIDENT: f32 = 0.8;
+
+    // A detection that is certain, e.g. for text that didn't come from bytes.
+    pub fn certain(encoding: Encoding) -> Self {
+        Self { encoding, confidence: 1.0, had_bom: false }
+    }
+
+    // Returns true if the user should be told the encoding was guessed.
+    pub fn is_guess(&self) -> bool {
+        self.confidence < Self::CONFIDENT
+    }
+}
+
+
+// Guesses the encoding of 'bytes'. A BOM decides outright. Otherwise UTF-16 is recognized by the
+// zero bytes of ASCII-range code units, valid UTF-8 is taken as UTF-8, and anything else falls
+// back to Latin-1 with a low confidence.
+pub fn detect_encoding(bytes: &[u8]) -> EncodingDetection {
+    for encoding in [Encoding::Utf8, Encoding::Utf16Le, Encoding::Utf16Be] {
+        if bytes.starts_with(encoding.bom()) {
+            return EncodingDetection { encoding, confidence: 1.0, had_bom: true };
+        }
+    }
+
+    if bytes.contains(&0) && bytes.len() >= 2 {
+        let pairs = (bytes.len() / 2) as f32;
+        let zeros_at = |parity: usize| bytes.chunks_exact(2).filter(|pair| pair[parity] == 0).count() as f32;
+        let (zero_high, zero_low) = (zeros_at(1), zeros_at(0));
+        // Mostly-ASCII UTF-16 has a zero in (nearly) every code unit, always on the same side.
+        let (encoding, ratio) = if zero_high >= zero_low {
+            (Encoding::Utf16Le, zero_high / pairs)
+        } else {
+            (Encoding::Utf16Be, zero_low / pairs)
+        };
+        if ratio >= 0.3 {
+            return EncodingDetection { encoding, confidence: ratio.min(0.95), had_bom: false };
+        }
+    }
+
+    match std::str::from_utf8(bytes) {
+        // Zero bytes are valid UTF-8 but suggest a binary file.
+        Ok(_) if bytes.contains(&0) => EncodingDetection { encoding: Encoding::Utf8, confidence: 0.5, had_bom: false },
+        Ok(_) => EncodingDetection::certain(Encoding::Utf8),
+        Err(_) => EncodingDetection { encoding: Encoding::Latin1, confidence: 0.4, had_bom: false },
+    }
+}
+
+
+impl Document {
+    // Opens the file at 'path' on the local filesystem, guessing its encoding (see
+    // 'detect_encoding'). The guess is kept on the document; check 'encoding().is_guess()' to
+    // offer 'reopen_with_encoding'. Use 'open_with' for other stores.
+    pub async fn open(path: &str, language_id: String) -> io::Result<Self> {
+        Document::open_with(&LocalFsStore, path, language_id).await
+    }
+
+    // Opens the file at 'path' like 'open' and resolves the 0-based 'line' and char column 'col'
+    // against its text, clamped as by 'TextBuffer::clamped_position' (e.g. for "file.rs:120:8"
+    // from the command line or a compiler error; convert 1-based numbers first).
+    pub async fn open_at(path: &str, line: usize, col: usize, language_id: String) -> io::Result<(Self, TextPosition)> {
+        let document = Document::open(path, language_id).await?;
+        let position = document.text_buffer.clamped_position(line, col);
+        Ok((document, position))
+    }
+
+    // Returns how the document's text was decoded.
+    pub fn encoding(&self) -> EncodingDetection {
+        *self.encoding.lock()
+    }
+
+    // Re-reads the file from the local filesystem decoding it as 'encoding' (see
+    // 'reopen_with_encoding_with').
+    pub async fn reopen_with_encoding(&self, encoding: Encoding) -> io::Result<()> {
+        self.reopen_with_encoding_with(&LocalFsStore, encoding).await
+    }
+
+    // Re-reads the file from 'store' decoding it as 'encoding' (the user's choice, so it is
+    // certain) and replaces the buffer content with the minimal edits (see 'set_contents_diffed').
+    // Unsaved changes are discarded and the document is clean afterwards.
+    // Fails if the document has no path or the file can't be read.
+    pub async fn reopen_with_encoding_with<S: DocumentStore>(&self, store: &S, encoding: Encoding) -> io::Result<()> {
+        let path = self.file_path.as_deref().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "document has no file path")
+        })?;
+        let bytes = store.read(path).await?;
+        let had_bom = !encoding.bom().is_empty() && bytes.starts_with(encoding.bom());
+        let text = encoding.decode(&bytes);
+        self.text_buffer.set_contents_diffed(&text).await;
+        *self.encoding.lock() = EncodingDetection { encoding, confidence: 1.0, had_bom };
+        self.set_dirty(false);
+        self.mark_saved(&text);
+        Ok(())
+    }
+}
@@ -0,0 +1,124 @@
+// Frame Dirty Tracking
+// Accumulates the lines touched by edits between two rendered frames, so a renderer only has to
+// repaint what changed. Register it with 'TextBuffer::add_sync_observer'; it is fed on the editing
+// call itself, so a frame taken right after an edit already includes it.
+use std::ops::Range;
+
+use parking_lot::Mutex;
+
+use crate::{ISyncTextBufferObserver, TextBufferChangedEvent};
+
+
+// Frame
+// What to repaint since the previous frame. When 'full_repaint' is set 'dirty_lines' is empty and
+// everything must be redrawn. A range ending at 'usize::MAX' means "to the end of the buffer"
+// (lines shifted up or down). 'version' is the buffer version the frame is up to date with.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Frame {
+    pub dirty_lines: Vec<Range<usize>>,
+    pub full_repaint: bool,
+    pub version: u64,
+}
+
+impl Frame {
+    // Returns true if nothing needs repainting.
+    pub fn is_clean(&self) -> bool {
+        !self.full_repaint && self.dirty_lines.is_empty()
+    }
+}
+
+
+// FrameDirtyTracker Entity
+// Keeps the dirty lines as a sorted set of disjoint, non-adjacent ranges. Falls back to a full
+// repaint after a reload, when the set grows past 'max_ranges', or when events arrive out of
+// version order (two edits racing to notify), since their line numbers then can't be trusted.
+#[derive(Debug)]
+pub struct FrameDirtyTracker {
+    state: Mutex<Frame>,
+    max_ranges: usize,
+}
+
+impl Default for FrameDirtyTracker {
+    fn default() -> Self {
+        Self::new(Self::DEFAULT_MAX_RANGES)
+    }
+}
+
+impl FrameDirtyTracker {
+    pub const DEFAULT_MAX_RANGES: usize = 32;
+
+    // Creates a tracker that gives up on precise ranges beyond 'max_ranges' of them.
+    pub fn new(max_ranges: usize) -> Self {
+        Self {
+            state: Mutex::new(Frame::default()),
+            max_ranges,
+        }
+    }
+
+    // Forces the next frame to be a full repaint (e.g. after a resize or theme change).
+    pub fn request_full_repaint(&self) {
+        let mut state = self.state.lock();
+        state.full_repaint = true;
+        state.dirty_lines.clear();
+    }
+
+    // Returns what changed since the previous call and starts a new, clean frame.
+    pub fn take_frame(&self) -> Frame {
+        let mut state = self.state.lock();
+        let version = state.version;
+        std::mem::replace(&mut *state, Frame { version, ..Frame::default() })
+    }
+
+    fn record(&self, state: &mut Frame, event: &TextBufferChangedEvent) {
+        if event.version() < state.version {
+            state.full_repaint = true;
+        }
+        state.version = state.version.max(event.version());
+        if state.full_repaint {
+            state.dirty_lines.clear();
+            return;
+        }
+
+        for member in event.events() {
+            let lines = member.dirty_line_range();
+            let range = match member {
+                TextBufferChangedEvent::Reloaded { .. } => {
+                    state.full_repaint = true;
+                    state.dirty_lines.clear();
+                    return;
+                }
+                // Every following line moved, so it all has to be redrawn.
+                _ if member.affects_line_count() => lines.start..usize::MAX,
+                _ => lines,
+            };
+            if !range.is_empty() {
+                merge_range(&mut state.dirty_lines, range);
+            }
+        }
+
+        if state.dirty_lines.len() > self.max_ranges {
+            state.full_repaint = true;
+            state.dirty_lines.clear();
+        }
+    }
+}
+
+impl ISyncTextBufferObserver for FrameDirtyTracker {
+    fn on_buffer_changed(&self, event: &TextBufferChangedEvent) {
+        let mut state = self.state.lock();
+        self.record(&mut state, event);
+    }
+}
+
+
+// Inserts 'range' into a sorted set of disjoint ranges, merging it with any range it overlaps or
+// touches.
+fn merge_range(ranges: &mut Vec<Range<usize>>, mut range: Range<usize>) {
+    let first = ranges.partition_point(|existing| existing.end < range.start);
+    let last = ranges.partition_point(|existing| existing.start <= range.end);
+    if first < last {
+        range.start = range.start.min(ranges[first].start);
+        range.end = range.end.max(ranges[last - 1].end);
+    }
+    ranges.splice(first..last, std::iter::once(range));
+}
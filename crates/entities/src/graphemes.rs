@@ -0,0 +1,84 @@
+// Grapheme Helpers
+// Text queries counted in grapheme clusters (what a user sees as one character), for previews and
+// grid-based rendering where cutting inside a cluster would produce garbage.
+use ropey::RopeSlice;
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::rope_utils::split_line_ending;
+use crate::TextBuffer;
+
+
+// Returns up to 'max_graphemes' leading grapheme clusters of 'slice'.
+// Reads chunk by chunk and stops as soon as one cluster more than needed has started, since only
+// then is the end of the last wanted cluster certain.
+pub(crate) fn leading_graphemes(slice: RopeSlice<'_>, max_graphemes: usize) -> String {
+    let mut text = String::new();
+    for chunk in slice.chunks() {
+        text.push_str(chunk);
+        if let Some((cut, _)) = text.grapheme_indices(true).nth(max_graphemes) {
+            text.truncate(cut);
+            return text;
+        }
+    }
+    text
+}
+
+
+impl TextBuffer {
+    // Grapheme Helpers
+
+    // Returns the first 'max_graphemes' grapheme clusters of the buffer (all of it if shorter),
+    // never splitting a cluster. Line breaks count as clusters.
+    pub fn prefix(&self, max_graphemes: usize) -> String {
+        let rope = self.content.lock();
+        leading_graphemes(rope.slice(..), max_graphemes)
+    }
+
+    // Returns the first 'max_graphemes' grapheme clusters of 'line' without its line break, or an
+    // empty string if the line doesn't exist.
+    pub fn line_prefix(&self, line: usize, max_graphemes: usize) -> String {
+        let rope = self.content.lock();
+        let Some(slice) = rope.get_line(line) else {
+            return String::new();
+        };
+        let mut prefix = leading_graphemes(slice, max_graphemes);
+        let content_len = split_line_ending(&prefix).0.len();
+        prefix.truncate(content_len);
+        prefix
+    }
+
+    // Returns the byte offset of grapheme column 'grapheme_col' on 'line', for grid-based
+    // frontends that address cells by cluster (a ZWJ emoji sequence is one column). The column
+    // just past the last cluster is the end of the line's content. None if there is no such line
+    // or the column lies beyond that.
+    pub fn grapheme_to_byte(&self, line: usize, grapheme_col: usize) -> Option<usize> {
+        let rope = self.content.lock();
+        let slice = rope.get_line(line)?;
+        let text = slice.to_string();
+        let content = split_line_ending(&text).0;
+        let offset = match content.grapheme_indices(true).nth(grapheme_col) {
+            Some((offset, _)) => offset,
+            None if content.graphemes(true).count() == grapheme_col => content.len(),
+            None => return None,
+        };
+        Some(rope.line_to_byte(line) + offset)
+    }
+
+    // Returns the '(line, grapheme column)' of 'byte_idx', the inverse of 'grapheme_to_byte'.
+    // An offset inside a cluster maps to that cluster's column, and one inside the line break to
+    // the column after the content. None if 'byte_idx' is past the end.
+    pub fn byte_to_grapheme(&self, byte_idx: usize) -> Option<(usize, usize)> {
+        let rope = self.content.lock();
+        if byte_idx > rope.len_bytes() {
+            return None;
+        }
+        let line = rope.byte_to_line(byte_idx);
+        let offset = byte_idx - rope.line_to_byte(line);
+        let text = rope.line(line).to_string();
+        let column = split_line_ending(&text).0
+            .grapheme_indices(true)
+            .take_while(|(start, grapheme)| start + grapheme.len() <= offset)
+            .count();
+        Some((line, column))
+    }
+}
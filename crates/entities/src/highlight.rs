@@ -0,0 +1,87 @@
+// Highlight Layer
+// Storage for the styled spans a syntax highlighter produces, kept separate from the highlighter
+// itself. Spans are anchor-backed, so they follow the text through edits. A span an edit touches
+// (inside it or right at its edges) can no longer be trusted: it is hidden and reported as stale,
+// so the highlighter only has to recompute those ranges.
+use std::sync::Arc;
+
+use crate::{AnchoredRange, TextBuffer, TextRange};
+
+
+// Style Identifier
+// An index into the highlighter's own style table; the layer never interprets it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct StyleId(pub u32);
+
+
+// HighlightLayer Struct
+// One highlighter's spans over one TextBuffer.
+pub struct HighlightLayer {
+    buffer: Arc<TextBuffer>,
+    spans: Vec<(AnchoredRange, StyleId)>,
+}
+
+impl HighlightLayer {
+    // Creates an empty layer for 'buffer'.
+    pub fn new(buffer: Arc<TextBuffer>) -> Self {
+        Self { buffer, spans: Vec::new() }
+    }
+
+    // Replaces all spans, including stale ones. Empty ranges are ignored.
+    pub fn set_spans(&mut self, spans: Vec<(TextRange, StyleId)>) {
+        self.clear();
+        let _rope = self.buffer.content.lock(); // Hold the content lock so the ranges can't go stale
+        let mut anchors = self.buffer.anchors.lock();
+        self.spans = spans.into_iter()
+            .filter(|(range, _)| !range.is_empty())
+            .map(|(range, style)| (anchors.create_span(range), style))
+            .collect();
+    }
+
+    // Returns the current spans overlapping 'range', ordered by start. Stale spans are left out.
+    pub fn spans_in_range(&self, range: TextRange) -> Vec<(TextRange, StyleId)> {
+        let anchors = self.buffer.anchors.lock();
+        let mut found: Vec<(TextRange, StyleId)> = self.spans.iter()
+            .filter(|(span, _)| !anchors.is_touched(span))
+            .filter_map(|(span, style)| {
+                let current = TextRange::new(anchors.position(span.start)?, anchors.position(span.end)?);
+                (current.start < range.end && range.start < current.end).then_some((current, *style))
+            })
+            .collect();
+        found.sort_by_key(|(span, _)| (span.start, span.end));
+        found
+    }
+
+    // Removes the spans edits have touched and returns their current ranges (which include the
+    // edited text), ordered by start, for the highlighter to recompute.
+    pub fn take_stale(&mut self) -> Vec<TextRange> {
+        let mut anchors = self.buffer.anchors.lock();
+        let mut stale = Vec::new();
+        self.spans.retain(|(span, _)| {
+            if !anchors.is_touched(span) {
+                return true;
+            }
+            if let (Some(start), Some(end)) = (anchors.position(span.start), anchors.position(span.end)) {
+                stale.push(TextRange::new(start, end));
+            }
+            anchors.remove_span(span);
+            false
+        });
+        stale.sort_by_key(|range| (range.start, range.end));
+        stale
+    }
+
+    // Removes all spans.
+    pub fn clear(&mut self) {
+        let mut anchors = self.buffer.anchors.lock();
+        for (span, _) in self.spans.drain(..) {
+            anchors.remove_span(&span);
+        }
+    }
+}
+
+impl Drop for HighlightLayer {
+    fn drop(&mut self) {
+        self.clear();
+    }
+}
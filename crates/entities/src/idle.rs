@@ -0,0 +1,43 @@
+// Idle Signal
+// A debounced "the user stopped typing" notification for expensive consumers (full-file linters,
+// spell checkers), so each of them doesn't have to run its own timer over the change events.
+use std::time::Duration;
+
+use tokio::sync::mpsc;
+
+use crate::TextBuffer;
+
+
+impl TextBuffer {
+    // Idle Signal
+
+    // Channel capacity of 'on_idle' subscriptions.
+    const IDLE_CHANNEL_CAPACITY: usize = 16;
+
+    // Returns a channel receiving the buffer version each time editing pauses: once no edit has
+    // happened for 'debounce' after the last one, that edit's version is sent. Nothing is sent
+    // while the buffer is left alone. A background task listens for the edits; it ends with the
+    // buffer, or after the first pause following the receiver being dropped. The channel holds
+    // 16 versions and further ones are dropped, so a consumer that has fallen behind should
+    // skip to the newest queued version. Must be called from within a Tokio runtime.
+    pub fn on_idle(&self, debounce: Duration) -> mpsc::Receiver<u64> {
+        let (mut events, _) = self.register(Self::IDLE_CHANNEL_CAPACITY);
+        let (sender, receiver) = mpsc::channel(Self::IDLE_CHANNEL_CAPACITY);
+        tokio::spawn(async move {
+            while let Some(event) = events.recv().await {
+                let mut version = event.version();
+                loop {
+                    match tokio::time::timeout(debounce, events.recv()).await {
+                        Ok(Some(event)) => version = version.max(event.version()),
+                        Ok(None) => return,
+                        Err(_) => break,
+                    }
+                }
+                if let Err(mpsc::error::TrySendError::Closed(_)) = sender.try_send(version) {
+                    return;
+                }
+            }
+        });
+        receiver
+    }
+}
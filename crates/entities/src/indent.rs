@@ -0,0 +1,208 @@
+// Indentation
+// How Tab and Shift-Tab indent text: a literal tab or spaces up to the next tab stop, and
+// removing one level of leading whitespace.
+use ropey::Rope;
+
+use crate::edits::check_range;
+use crate::rope_utils::{line_content_len, line_prefix, split_line_ending};
+use crate::{EditError, EditOrigin, TextBuffer, TextPosition, TextRange};
+
+
+// Indentation Settings
+// 'soft_tabs' inserts spaces instead of '\t'. 'tab_width' is both the distance between tab stops
+// (how wide a '\t' is drawn) and the size of one indentation level.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Indentation {
+    pub soft_tabs: bool,
+    pub tab_width: usize,
+}
+
+impl Default for Indentation {
+    fn default() -> Self {
+        Self { soft_tabs: true, tab_width: 4 }
+    }
+}
+
+impl Indentation {
+    // The tab width, never 0 (a zero width would make every column a tab stop).
+    fn width(&self) -> usize {
+        self.tab_width.max(1)
+    }
+
+    // Returns the visual column at the end of 'line_prefix', expanding tabs to the next stop.
+    pub fn visual_column(&self, line_prefix: &str) -> usize {
+        line_prefix.chars().fold(0, |column, c| match c {
+            '\t' => (column / self.width() + 1) * self.width(),
+            _ => column + 1,
+        })
+    }
+}
+
+
+// Returns the byte length and visual width of the spaces and tabs starting 'line', reading the
+// rope in place. The line must exist.
+fn leading_indent(rope: &Rope, line: usize, indent: &Indentation) -> (usize, usize) {
+    rope.line(line).chars().take_while(|c| *c == ' ' || *c == '\t').fold((0, 0), |(len, column), c| {
+        let column = match c {
+            '\t' => (column / indent.width() + 1) * indent.width(),
+            _ => column + 1,
+        };
+        (len + 1, column)
+    })
+}
+
+// Returns the visual width of the leading whitespace of 'line', or None if the line is blank
+// (whitespace only), since blank lines don't have a meaningful indentation.
+fn line_indent(rope: &Rope, line: usize, indent: &Indentation) -> Option<usize> {
+    let (len, width) = leading_indent(rope, line, indent);
+    (len < line_content_len(rope, line)).then_some(width)
+}
+
+
+// Returns the indentation block around 'line' (see 'indent_block_at'). The line must exist.
+pub(crate) fn indent_block(rope: &Rope, line: usize) -> TextRange {
+    let indent = Indentation::default();
+    let (mut first, mut last) = (line, line);
+    if let Some(level) = line_indent(rope, line, &indent) {
+        let in_block = |candidate: usize| line_indent(rope, candidate, &indent).is_none_or(|width| width >= level);
+        while first > 0 && in_block(first - 1) {
+            first -= 1;
+        }
+        while last + 1 < rope.len_lines() && in_block(last + 1) {
+            last += 1;
+        }
+        // Blank lines only belong to the block if non-blank lines follow them.
+        while first < line && line_indent(rope, first, &indent).is_none() {
+            first += 1;
+        }
+        while last > line && line_indent(rope, last, &indent).is_none() {
+            last -= 1;
+        }
+    }
+    TextRange::new(rope.line_to_byte(first), rope.line_to_byte(last + 1))
+}
+
+
+impl TextBuffer {
+    // Indentation
+
+    // Returns the byte length and visual width (tabs expanded to 'tab_width' stops) of the spaces
+    // and tabs starting 'line', or None if there is no such line. A blank line reports all of its
+    // whitespace.
+    pub fn indentation_of_line(&self, line: usize, tab_width: usize) -> Option<(usize, usize)> {
+        let rope = self.content.lock();
+        let indent = Indentation { soft_tabs: false, tab_width };
+        (line < rope.len_lines()).then(|| leading_indent(&rope, line, &indent))
+    }
+
+    // Inserts one indentation step at 'position' (the Tab key): a '\t', or with soft tabs the
+    // number of spaces that reaches the next tab stop from the current visual column.
+    // Returns the position after the inserted text. Fails, changing nothing, if 'position' is
+    // invalid, inside a protected region or the insertion is vetoed.
+    pub async fn insert_tab(&self, position: TextPosition, indent: &Indentation) -> Result<TextPosition, EditError> {
+        let mut rope = self.content.lock();
+        check_range(&rope, position.byte_idx, position.byte_idx)?;
+        let text = if indent.soft_tabs {
+            let column = indent.visual_column(&line_prefix(&rope, position.byte_idx));
+            " ".repeat(indent.width() - column % indent.width())
+        } else {
+            "\t".to_string()
+        };
+        let event = self.apply_insert(&mut rope, position.byte_idx, &text, EditOrigin::User)?;
+        drop(rope);
+
+        if let Some(event) = event {
+            self.notify_observers(event).await;
+        }
+        Ok(TextPosition { byte_idx: position.byte_idx + text.len() })
+    }
+
+    // Removes up to one level of leading whitespace from 'line' (Shift-Tab): a single '\t', or
+    // up to 'tab_width' spaces. Returns the number of bytes removed (0 if the line has no
+    // leading whitespace). Fails, changing nothing, if the line doesn't exist, or if the removal
+    // touches a protected region or is vetoed.
+    pub async fn dedent_line(&self, line: usize, indent: &Indentation) -> Result<usize, EditError> {
+        let mut rope = self.content.lock();
+        let len_lines = rope.len_lines();
+        let Some(slice) = rope.get_line(line) else {
+            return Err(EditError::LineOutOfBounds { line, len_lines });
+        };
+        let text = slice.to_string();
+        let content = split_line_ending(&text).0;
+
+        // Spaces before a tab belong to the tab's level.
+        let mut len = 0;
+        for c in content.chars().take(indent.width()) {
+            match c {
+                ' ' => len += 1,
+                '\t' => {
+                    len += 1;
+                    break;
+                }
+                _ => break,
+            }
+        }
+        if len == 0 {
+            return Ok(0);
+        }
+        let line_start = rope.line_to_byte(line);
+        let event = self.apply_remove(&mut rope, line_start, len, EditOrigin::User)?;
+        drop(rope);
+
+        if let Some(event) = event {
+            self.notify_observers(event).await;
+        }
+        Ok(len)
+    }
+
+    // Guesses the indentation style from the leading whitespace of the lines: tabs if more lines
+    // are indented with tabs than with spaces, otherwise spaces with the most common step between
+    // consecutive indentation levels as the width. Returns None if no line is indented.
+    pub fn detect_indentation(&self) -> Option<Indentation> {
+        let rope = self.content.lock();
+        let (mut tab_lines, mut space_lines) = (0, 0);
+        // How often each increase in space indentation between consecutive lines occurs.
+        let mut steps = [0usize; 9];
+        let mut previous = 0;
+        for line in rope.lines() {
+            let text = line.to_string();
+            let content = split_line_ending(&text).0;
+            if content.trim().is_empty() {
+                continue;
+            }
+            if content.starts_with('\t') {
+                tab_lines += 1;
+                continue;
+            }
+            let spaces = content.len() - content.trim_start_matches(' ').len();
+            if spaces > 0 {
+                space_lines += 1;
+            }
+            if spaces > previous && spaces - previous < steps.len() {
+                steps[spaces - previous] += 1;
+            }
+            previous = spaces;
+        }
+
+        if tab_lines == 0 && space_lines == 0 {
+            return None;
+        }
+        if tab_lines > space_lines {
+            return Some(Indentation { soft_tabs: false, ..Indentation::default() });
+        }
+        // Ties go to the larger step, since a 4-space file also has the occasional 2-space step.
+        let width = (2..steps.len()).max_by_key(|&step| steps[step]).filter(|&step| steps[step] > 0);
+        Some(Indentation { soft_tabs: true, tab_width: width.unwrap_or(Indentation::default().tab_width) })
+    }
+
+    // Returns the indentation block around 'line' for folding and "select block" in
+    // indentation-based languages: the contiguous lines, above and below, indented at least as
+    // much as 'line', stopping at the first line indented less. Blank lines inside the block are
+    // included; trailing blank lines are not. Tabs count up to the default tab width.
+    // The range covers whole lines, including the last one's terminator. A blank line is a block
+    // of its own. Returns None if the line doesn't exist.
+    pub fn indent_block_at(&self, line: usize) -> Option<TextRange> {
+        let rope = self.content.lock();
+        (line < rope.len_lines()).then(|| indent_block(&rope, line))
+    }
+}
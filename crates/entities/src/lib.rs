@@ -8,40 +8,395 @@ use ropey::Rope;
 // Arc provides shared ownership, essential for multiple components accessing the same textBuffer.
 use std::sync::Arc;
 
+// Global counter for observer ids.
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+
+// Line ranges reported to observers are half-open 'Range<usize>' values.
+use std::ops::Range;
+
+// Timeouts for lock acquisition that must not block the UI.
+use std::time::Duration;
+
 // For defining asynchronous methods in traits.
 use async_trait::async_trait;
 
 // Tokio's mpsc channel for asynchronous message passing, a common Rust idiom for the Observer pattern.
 use tokio::sync::mpsc;
 
+// Change events are serializable so recorded sessions can be saved and loaded.
+use serde::{Deserialize, Serialize};
+
+// Feature modules. Each extends 'TextBuffer' (or adds supporting types) for one area.
+mod anchor;
+mod async_buffer;
+mod brackets;
+mod builder;
+mod bytes;
+mod changes;
+mod cursor;
+mod decorations;
+mod deletion;
+mod diff;
+mod dump;
+mod edit_locations;
+mod edits;
+mod emptiness;
+mod encoding;
+mod frame;
+mod graphemes;
+mod highlight;
+mod idle;
+mod indent;
+mod line_ending;
+mod lines;
+mod log_buffer;
+mod manager;
+mod markers;
+mod metrics;
+mod normalize;
+mod pairs;
+mod payload;
+mod pre_edit;
+mod protected;
+mod recording;
+mod registers;
+mod rope_utils;
+mod scratch;
+mod search;
+mod selection;
+mod settings;
+mod snippet;
+mod splice;
+mod stats;
+mod store;
+mod syntax;
+mod transpose;
+mod undo;
+mod view;
+mod viewport;
+mod wrap;
+mod writer;
+
+pub use anchor::{AnchorBias, AnchorId, AnchoredRange};
+pub use async_buffer::AsyncTextBuffer;
+pub use builder::DocumentBuilder;
+pub use changes::DirtySince;
+pub use cursor::CursorTracker;
+pub use decorations::LineDecorations;
+pub use diff::{diff_lines, unified_diff, DiffOp};
+pub use edits::{EditError, EditSummary, Transaction};
+pub use encoding::{detect_encoding, Encoding, EncodingDetection};
+pub use frame::{Frame, FrameDirtyTracker};
+pub use highlight::{HighlightLayer, StyleId};
+pub use indent::Indentation;
+pub use line_ending::LineEnding;
+pub use log_buffer::LogBuffer;
+pub use manager::{DocOrder, DocumentManager, WorkspaceEditError};
+pub use markers::{Bookmark, Bookmarks, Diagnostic, DiagnosticSeverity, Diagnostics, Markers};
+pub use metrics::DocMetrics;
+pub use normalize::Normalization;
+pub use pre_edit::{EditDecision, EditIntent, PreEditObserver};
+pub use protected::RegionId;
+pub use recording::{load_recording, replay_recording, save_recording, RecordedEvent, Recording, RecordingObserver, ReplayError};
+pub use registers::Registers;
+pub use search::SearchOptions;
+pub use selection::merge_selections;
+pub use settings::DocumentSettings;
+pub use snippet::SnippetSession;
+pub use stats::TextStats;
+pub use store::{DocumentError, DocumentStore, LocalFsStore};
+pub use syntax::{LangSyntax, PositionClass};
+pub use undo::UndoStack;
+pub use view::ViewRequest;
+pub use viewport::Viewport;
+pub use wrap::truncate_display;
+pub use writer::BufferWriter;
+use anchor::AnchorSet;
+use changes::ChangeLog;
+use edit_locations::EditLocations;
+use emptiness::EmptinessWatcher;
+use metrics::MetricsWatcher;
+
 
 // Text Position Struct
 // Represents a specific position within the text buffer, typically a byte index.
 // This is a core data model entity.
-#[derive(Debug, Clone, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct TextPosition {
     pub byte_idx: usize,
 }
 
+impl TextPosition {
+    // Returns the position of char column 'col' on 'line' of 'buffer' (see 'TextBuffer::position_at').
+    pub fn at_line_col(buffer: &TextBuffer, line: usize, col: usize) -> Option<Self> {
+        buffer.position_at(line, col)
+    }
+}
+
+
+// Text Range Struct
+// A half-open byte range '[start, end)' within the text buffer (selections, matches, tab stops).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TextRange {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl TextRange {
+    // Creates a range; 'start' and 'end' are swapped if given in reverse.
+    pub fn new(start: usize, end: usize) -> Self {
+        if start <= end {
+            Self { start, end }
+        } else {
+            Self { start: end, end: start }
+        }
+    }
+
+    // Returns the length of the range in bytes.
+    pub fn len(&self) -> usize {
+        self.end - self.start
+    }
+
+    // Returns true if the range covers no bytes (e.g. a bare cursor).
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+}
+
+
+// Range Text Struct
+// Text extracted by 'get_range_with_meta', with metadata for cursor math.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RangeText {
+    pub text: String,
+    pub char_len: usize,
+    pub line_count: usize,
+}
+
+
+// Edit Origin
+// Tags every change event with what caused it, so observers that replay history (e.g. an undo
+// stack) can tell their own edits apart from fresh user edits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum EditOrigin {
+    // A regular edit through the public editing API.
+    #[default]
+    User,
+    // An edit applied to revert history.
+    Undo,
+    // An edit applied to re-apply reverted history.
+    Redo,
+}
+
+
+// Edit Kind
+// A hint classifying what an edit changed, so observers (e.g. an LSP adapter) can debounce
+// expensive work for edits that only touch whitespace. Computed from the changed text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum EditKind {
+    #[default]
+    Normal,
+    // Only whitespace (including line breaks) was inserted or removed.
+    WhitespaceOnly,
+    // Only spaces and tabs were inserted or removed within a line's leading whitespace.
+    IndentationOnly,
+    // The whole text was replaced ('Reloaded').
+    Reload,
+}
+
+impl EditKind {
+    // Classifies an edit of 'text' made right after 'line_prefix', the text of its line before it.
+    fn classify(text: &str, line_prefix: impl FnOnce() -> String) -> Self {
+        let is_indent = |c: char| c == ' ' || c == '\t';
+        if text.is_empty() || !text.chars().all(char::is_whitespace) {
+            EditKind::Normal
+        } else if text.chars().all(is_indent) && line_prefix().chars().all(is_indent) {
+            EditKind::IndentationOnly
+        } else {
+            EditKind::WhitespaceOnly
+        }
+    }
+
+    // The kind of several edits together: the common kind, or the broadest one covering all.
+    fn combine(self, other: Self) -> Self {
+        use EditKind::*;
+        match (self, other) {
+            (a, b) if a == b => a,
+            (WhitespaceOnly | IndentationOnly, WhitespaceOnly | IndentationOnly) => WhitespaceOnly,
+            _ => Normal,
+        }
+    }
+}
+
 
 // Text Buffer Change Event
 // This enum defines the types of events that the TextBuffer can emit.
 // It's part of the Observer pattern, carrying data about the change.
-#[derive(Debug, Clone)]
+// Every edit bumps the buffer's version by one; 'version' is the buffer version right after the
+// edit, so observers can tell exactly which state an event leads to.
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum TextBufferChangedEvent {
     // Event indicating that a range of text has been inserted.
-    // Contains the starting byte index and the length of the inserted text.
+    // Contains the starting byte index, the length and a shared copy of the inserted text.
+    // 'dirty_line_range' is the half-open range of post-edit line numbers covering the inserted
+    // text: one line for an edit within a line, a span for a multi-line paste.
+    // 'affects_line_count' is true when the text contains a line break; when false, observers can
+    // take a single-line fast path.
+    // 'start_line_col' is the '(line, column)' of 'start_byte_idx', the column counted in chars.
+    // It is computed during the edit, so it is consistent with the event even if the buffer has
+    // changed again by the time it is observed.
+    // 'kind' classifies the inserted text (see 'EditKind').
     Inserted {
         start_byte_idx: usize,
+        start_line_col: (usize, usize),
         len_bytes: usize,
+        text: Arc<str>,
+        dirty_line_range: Range<usize>,
+        affects_line_count: bool,
+        origin: EditOrigin,
+        kind: EditKind,
+        version: u64,
     },
 
     // Event indicating that a range of text has been removed.
-    // Contains the starting byte index and the length of the removed text.
+    // Contains the starting byte index, the length and a shared copy of the removed text, so
+    // observers such as an undo history can invert the edit without re-reading the buffer.
+    // 'dirty_line_range' is the half-open range of pre-edit line numbers the removed text spanned;
+    // after the edit those lines have collapsed into the single line at 'dirty_line_range.start'.
+    // 'affects_line_count' is true when the removed text contained a line break.
+    // 'start_line_col' is as for 'Inserted' (the start is the same before and after a removal).
+    // 'kind' classifies the removed text.
     Removed {
+        start_byte_idx: usize,
+        start_line_col: (usize, usize),
+        len_bytes: usize,
+        text: Arc<str>,
+        dirty_line_range: Range<usize>,
+        affects_line_count: bool,
+        origin: EditOrigin,
+        kind: EditKind,
+        version: u64,
+    },
+
+    // The text changed so much that a precise description isn't worth building (see
+    // 'set_reload_threshold'): observers should re-read the whole buffer. 'len_bytes' and
+    // 'len_lines' describe the text after the change.
+    Reloaded {
+        len_bytes: usize,
+        len_lines: usize,
+        origin: EditOrigin,
+        version: u64,
+    },
+
+    // 'line_count' whole lines were appended at the end by a 'LogBuffer', starting at
+    // 'start_byte_idx' (the start of a line). Unlike 'Inserted' it carries no copy of the text;
+    // observers that need it read 'len_bytes' from the buffer. Always of origin 'User'.
+    Appended {
         start_byte_idx: usize,
         len_bytes: usize,
+        line_count: usize,
+        dirty_line_range: Range<usize>,
+        version: u64,
     },
+
+    // Several edits applied as one unit by 'apply_edits' or 'transaction', in the order they
+    // were applied; each member's offsets refer to the text as it was right before that member.
+    // Batches never nest, are never empty and never contain 'Reloaded' (a batch that would is
+    // delivered as a single 'Reloaded' instead). Observers that don't care about grouping can
+    // process 'events()' one by one.
+    Batch(Vec<TextBufferChangedEvent>),
+}
+
+impl TextBufferChangedEvent {
+    // Returns the lines an observer (e.g. a renderer) must invalidate for this event.
+    // For a batch this is the union of its members' ranges.
+    pub fn dirty_line_range(&self) -> Range<usize> {
+        match self {
+            TextBufferChangedEvent::Inserted { dirty_line_range, .. }
+            | TextBufferChangedEvent::Removed { dirty_line_range, .. }
+            | TextBufferChangedEvent::Appended { dirty_line_range, .. } => dirty_line_range.clone(),
+            TextBufferChangedEvent::Reloaded { len_lines, .. } => 0..*len_lines,
+            TextBufferChangedEvent::Batch(events) => events.iter()
+                .map(TextBufferChangedEvent::dirty_line_range)
+                .reduce(|a, b| a.start.min(b.start)..a.end.max(b.end))
+                .unwrap_or(0..0),
+        }
+    }
+
+    // Returns the '(line, column)' where the edit starts (for a batch: its first member), or None
+    // for 'Reloaded'.
+    pub fn start_line_col(&self) -> Option<(usize, usize)> {
+        match self {
+            TextBufferChangedEvent::Inserted { start_line_col, .. }
+            | TextBufferChangedEvent::Removed { start_line_col, .. } => Some(*start_line_col),
+            TextBufferChangedEvent::Appended { dirty_line_range, .. } => Some((dirty_line_range.start, 0)),
+            TextBufferChangedEvent::Reloaded { .. } => None,
+            TextBufferChangedEvent::Batch(events) => events.first().and_then(TextBufferChangedEvent::start_line_col),
+        }
+    }
+
+    // Returns true if the edit added or removed line breaks (for a batch: any member did).
+    pub fn affects_line_count(&self) -> bool {
+        match self {
+            TextBufferChangedEvent::Inserted { affects_line_count, .. }
+            | TextBufferChangedEvent::Removed { affects_line_count, .. } => *affects_line_count,
+            TextBufferChangedEvent::Reloaded { .. } | TextBufferChangedEvent::Appended { .. } => true,
+            TextBufferChangedEvent::Batch(events) => events.iter().any(TextBufferChangedEvent::affects_line_count),
+        }
+    }
+
+    // Returns what caused this event.
+    pub fn origin(&self) -> EditOrigin {
+        match self {
+            TextBufferChangedEvent::Inserted { origin, .. }
+            | TextBufferChangedEvent::Removed { origin, .. }
+            | TextBufferChangedEvent::Reloaded { origin, .. } => *origin,
+            TextBufferChangedEvent::Appended { .. } => EditOrigin::User,
+            TextBufferChangedEvent::Batch(events) => events.first().map_or(EditOrigin::User, |event| event.origin()),
+        }
+    }
+
+    // Returns the kind of the edit (for a batch: of all its members together).
+    pub fn kind(&self) -> EditKind {
+        match self {
+            TextBufferChangedEvent::Inserted { kind, .. } | TextBufferChangedEvent::Removed { kind, .. } => *kind,
+            TextBufferChangedEvent::Reloaded { .. } => EditKind::Reload,
+            TextBufferChangedEvent::Appended { .. } => EditKind::Normal,
+            TextBufferChangedEvent::Batch(events) => events.iter()
+                .map(TextBufferChangedEvent::kind)
+                .reduce(EditKind::combine)
+                .unwrap_or_default(),
+        }
+    }
+
+    // Overrides the kind of the edit (of every member of a batch), for commands that know better
+    // than the text-based classification. 'Reloaded' and 'Appended' keep their kind.
+    pub(crate) fn set_kind(&mut self, new_kind: EditKind) {
+        match self {
+            TextBufferChangedEvent::Inserted { kind, .. } | TextBufferChangedEvent::Removed { kind, .. } => *kind = new_kind,
+            TextBufferChangedEvent::Reloaded { .. } | TextBufferChangedEvent::Appended { .. } => {}
+            TextBufferChangedEvent::Batch(events) => events.iter_mut().for_each(|event| event.set_kind(new_kind)),
+        }
+    }
+
+    // Returns the buffer version right after this event (for a batch: after its last member).
+    pub fn version(&self) -> u64 {
+        match self {
+            TextBufferChangedEvent::Inserted { version, .. }
+            | TextBufferChangedEvent::Removed { version, .. }
+            | TextBufferChangedEvent::Reloaded { version, .. }
+            | TextBufferChangedEvent::Appended { version, .. } => *version,
+            TextBufferChangedEvent::Batch(events) => events.last().map_or(0, TextBufferChangedEvent::version),
+        }
+    }
+
+    // Returns the individual edits: the members of a batch, or the event itself.
+    pub fn events(&self) -> &[TextBufferChangedEvent] {
+        match self {
+            TextBufferChangedEvent::Batch(events) => events,
+            single => std::slice::from_ref(single),
+        }
+    }
 }
 
 
@@ -50,12 +405,91 @@ pub enum TextBufferChangedEvent {
 // This trait adheres to the Interface Segregation Principle (ISP) as it's specific to buffer changes.
 // It uses 'async_trait' because UI updates or other reactions might involve asynchronous operations.
 #[async_trait]
-pub trait ITextBufferObserver: send + Sync {
+pub trait ITextBufferObserver: Send + Sync {
     // This method is called when the TextBuffer content changes.
     async fn on_buffer_changed(&self, event: TextBufferChangedEvent);
 }
 
 
+// ISyncTextBufferObserver Trait
+// For observers that only do cheap bookkeeping (e.g. accumulating dirty lines for the next frame)
+// and don't need a channel or a task. They are called synchronously by the editing call, after
+// the content lock has been released and before channel observers are served, in registration
+// order. Implementations must not block and must not edit the buffer.
+pub trait ISyncTextBufferObserver: Send + Sync {
+    fn on_buffer_changed(&self, event: &TextBufferChangedEvent);
+}
+
+
+// Null Observer
+// An observer that ignores every event, for tests and headless tools that need to hand one in.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NullObserver;
+
+#[async_trait]
+impl ITextBufferObserver for NullObserver {
+    async fn on_buffer_changed(&self, _event: TextBufferChangedEvent) {}
+}
+
+// Returns a sender whose events are received and discarded by a background task, so it never
+// fills up or gets pruned as disconnected. Must be called from within a Tokio runtime.
+pub fn devnull_sender() -> mpsc::Sender<TextBufferChangedEvent> {
+    let (sender, mut receiver) = mpsc::channel(16);
+    tokio::spawn(async move { while receiver.recv().await.is_some() {} });
+    sender
+}
+
+
+// Observer Identifier
+// Returned when an observer is registered; pass it to 'remove_observer' to unregister.
+// Unique across all buffers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ObserverId(u64);
+
+impl ObserverId {
+    fn next() -> Self {
+        static NEXT: AtomicU64 = AtomicU64::new(0);
+        ObserverId(NEXT.fetch_add(1, Ordering::Relaxed))
+    }
+}
+
+
+// Observer Guard
+// Keeps a channel observer registered for as long as it lives (see 'register_guarded'): dropping
+// it removes the observer right away, instead of when a later notification finds the channel
+// closed. It doesn't keep the buffer alive.
+pub struct ObserverGuard {
+    id: ObserverId,
+    observers: std::sync::Weak<Mutex<Vec<ObserverEntry>>>,
+}
+
+impl ObserverGuard {
+    // Returns the id of the guarded observer.
+    pub fn id(&self) -> ObserverId {
+        self.id
+    }
+}
+
+impl Drop for ObserverGuard {
+    fn drop(&mut self) {
+        if let Some(observers) = self.observers.upgrade() {
+            unregister_observer(&observers, self.id);
+        }
+    }
+}
+
+
+// Observer Entry
+// A registered observer channel together with its delivery priority.
+// Entries are kept sorted so that iterating the Vec front-to-back *is* the delivery order:
+// higher priority first, and observers with equal priority in the order they were registered.
+struct ObserverEntry {
+    id: ObserverId,
+    sender: mpsc::Sender<TextBufferChangedEvent>,
+    priority: i32,
+}
+
+
 // TextBuffer Entity
 // Manage the actual text content of a document.
 // Adheres to the Single Responsibility Principle (SRP) by solely focusing on text storage and manipulation.
@@ -67,47 +501,256 @@ pub struct TextBuffer {
 
     // Sender part of an MPSC channel to send change events to registered observers.
     // 'Vec' of senders allows multiple observers to receive messages.
-    observers: Arc<Mutex<Vec<mpsc::Sender<TextBufferChangedEvent>>>>,
+    // Always sorted by delivery order (see 'ObserverEntry').
+    observers: Arc<Mutex<Vec<ObserverEntry>>>,
+
+    // Positions that track edits (see 'anchor'). Locked after 'content' whenever both are needed.
+    anchors: Arc<Mutex<AnchorSet>>,
+
+    // Observers called synchronously on every change (see 'ISyncTextBufferObserver').
+    sync_observers: Mutex<Vec<(ObserverId, Arc<dyn ISyncTextBufferObserver>)>>,
+
+    // Observers consulted before an edit (see 'PreEditObserver').
+    pre_edit_observers: Mutex<Vec<(ObserverId, Arc<dyn PreEditObserver>)>>,
+
+    // Anchor pairs of the read-only regions (see 'add_protected_region'). Locked after 'anchors'.
+    protected_regions: Mutex<Vec<AnchoredRange>>,
+
+    // Fraction of the buffer above which an edit is reported as 'Reloaded' (None: never).
+    reload_threshold: Mutex<Option<f64>>,
+
+    // Largest text an 'Inserted' event from 'insert' carries (see 'set_max_event_payload').
+    max_event_payload: Mutex<Option<usize>>,
+
+    // Form user insertions are normalized to (see 'set_normalization').
+    normalization: Mutex<Normalization>,
+
+    // Recent edit locations (see 'last_edit_position'). Locked after 'anchors'.
+    edit_locations: Mutex<EditLocations>,
+
+    // Receivers of non-edit requests such as "reveal this range" (see 'view').
+    view_subscribers: Mutex<Vec<mpsc::Sender<ViewRequest>>>,
+
+    // Number of edits applied so far. Only bumped while 'content' is locked.
+    version: AtomicU64,
+
+    // Recent edits for 'changes_since'. Only written while 'content' is locked.
+    changes: Mutex<ChangeLog>,
+
+    // Held for reading by every notification in progress, so 'flush_notifications' can wait for
+    // them by taking it for writing.
+    notifications: tokio::sync::RwLock<()>,
+
+    // Words in the text, kept up to date by every edit (see 'stats'). Only changed while
+    // 'content' is locked.
+    word_count: AtomicUsize,
 }
 
 
 impl TextBuffer {
+    // Default priority used by 'add_observer'.
+    pub const DEFAULT_OBSERVER_PRIORITY: i32 = 0;
+
     // Create a new 'TextBuffer' instance with initial text.
     pub fn new(initial_text:  &str) -> Self {
         Self {
             content: Arc::new(Mutex::new(Rope::from_str(initial_text))),
             observers: Arc::new(Mutex::new(Vec::new())),
+            anchors: Arc::new(Mutex::new(AnchorSet::default())),
+            sync_observers: Mutex::new(Vec::new()),
+            pre_edit_observers: Mutex::new(Vec::new()),
+            protected_regions: Mutex::new(Vec::new()),
+            reload_threshold: Mutex::new(None),
+            max_event_payload: Mutex::new(None),
+            normalization: Mutex::new(Normalization::None),
+            edit_locations: Mutex::new(EditLocations::default()),
+            view_subscribers: Mutex::new(Vec::new()),
+            version: AtomicU64::new(0),
+            changes: Mutex::new(ChangeLog::default()),
+            notifications: tokio::sync::RwLock::new(()),
+            word_count: AtomicUsize::new(stats::count_words(initial_text)),
         }
     }
 
+    // Returns the buffer version: the number of edits applied since creation.
+    pub fn version(&self) -> u64 {
+        self.version.load(Ordering::SeqCst)
+    }
+
+    // Advances the version for an edit; must be called with 'content' locked.
+    fn bump_version(&self) -> u64 {
+        self.version.fetch_add(1, Ordering::SeqCst) + 1
+    }
+
+
+    // Makes edits that affect more than 'ratio' of the buffer (measured against the larger of the
+    // text before and after the edit) emit 'Reloaded' instead of an event carrying the text, so
+    // near-total rewrites don't allocate huge payloads. 'None', the default, always reports
+    // precise events. Note that a 'Reloaded' can't be undone: 'UndoStack' clears its history.
+    pub fn set_reload_threshold(&self, ratio: Option<f64>) {
+        *self.reload_threshold.lock() = ratio;
+    }
+
 
     // Public API for TextBuffer Manipulation
 
-    // Inserts text at a given byte position.
+    // Inserts text at a given byte position. If the insertion is vetoed by a pre-edit observer or
+    // lies inside a protected region, nothing happens; use 'replace' to find out why.
+    // Inserting "" is a no-op: nothing is locked, the version stays the same and no event is
+    // sent. The same holds for removing 0 bytes, so observers can rely on "no event, no change".
+    // The text is normalized first if 'set_normalization' asks for it.
     pub async fn insert(&self, position: TextPosition, text: &str) {
-        let mut rope = self.content.lock(); // Acquire lock for mutable access
-        rope.insert(position.byte_idx, text); // Perform the insertion
-        drop(rope); // Release lock as soon as mutable operation is done
-
-        // Notify observers asynchronously
-        self.notify_observers(TextBufferChangedEvent::Inserted {
-            start_byte_idx: position.byte_idx,
-            len_bytes: text.len(),
-        }).await;
+        self.insert_with_origin(position, text, EditOrigin::User).await;
     }
 
 
-    // Removes text from a given byte position for a specified length.
+    // Removes text from a given byte position for a specified length. Vetoed removals and
+    // removals touching a protected region are ignored like rejected insertions.
     pub async fn remove(&self, position: TextPosition, len_bytes: usize) {
-        let mut rope = self.content.lock(); // Acquire lock
-        rope.remove(position.byte_idx..(position.byte_idx + len_bytes)); // Perform removal
-        drop(rope);
+        self.remove_with_origin(position, len_bytes, EditOrigin::User).await;
+    }
+
+
+    // Entry points for edits that must be tagged with a non-user origin (undo/redo replay).
+    pub(crate) async fn insert_with_origin(&self, position: TextPosition, text: &str, origin: EditOrigin) {
+        if text.is_empty() {
+            return;
+        }
+        // The guard lives in its own scope so it is released before the await; a 'drop' isn't
+        // enough for the compiler to see that, and the future must stay 'Send' for 'tokio::spawn'.
+        let normalized;
+        let text = if origin == EditOrigin::User {
+            normalized = self.normalization().apply(text);
+            &*normalized
+        } else {
+            text
+        };
+        let events = {
+            let mut rope = self.content.lock(); // Acquire lock for mutable access
+            let range = TextRange::new(position.byte_idx, position.byte_idx);
+            if self.check_edit(&rope, range, text, origin).is_err() {
+                return;
+            }
+            self.apply_insert_chunked(&mut rope, position.byte_idx, text, origin) // Perform the insertion
+        };
+
+        // Notify observers asynchronously
+        for event in events {
+            self.notify_observers(event).await;
+        }
+    }
+
+    pub(crate) async fn remove_with_origin(&self, position: TextPosition, len_bytes: usize, origin: EditOrigin) {
+        if len_bytes == 0 {
+            return;
+        }
+        let event = {
+            let mut rope = self.content.lock(); // Acquire lock
+            // Perform removal
+            let Ok(Some(event)) = self.apply_remove(&mut rope, position.byte_idx, len_bytes, origin) else {
+                return;
+            };
+            event
+        };
 
         // Notify observers asynchronously
-        self.notify_observers(TextBufferChangedEvent::Removed {
-            start_byte_idx: position.byte_idx,
-            len_bytes,
-        }).await;
+        self.notify_observers(event).await;
+    }
+
+
+    // Internal Edit Helpers
+    // These operate on an already-locked rope and return the event describing the change, so the
+    // byte -> line math is done while the pre/post-edit state is known. Callers notify after
+    // releasing the lock.
+    // 'apply_insert'/'apply_remove' run 'check_edit' first, so no edit command can skip the
+    // protected regions and pre-edit observers. The '_unchecked' variants are only for edits the
+    // caller already passed to 'check_edit', e.g. a group of edits checked up front so that none
+    // is applied unless all of them are allowed.
+
+    // Inserts 'text' at 'byte_idx' and builds the matching 'Inserted' event, unless the insertion
+    // touches a protected region or is vetoed. Inserting "" changes nothing and gives no event,
+    // so no command can send an empty one.
+    fn apply_insert(&self, rope: &mut Rope, byte_idx: usize, text: &str, origin: EditOrigin) -> Result<Option<TextBufferChangedEvent>, EditError> {
+        if text.is_empty() {
+            return Ok(None);
+        }
+        self.check_edit(rope, TextRange::new(byte_idx, byte_idx), text, origin)?;
+        Ok(Some(self.apply_insert_unchecked(rope, byte_idx, text, origin)))
+    }
+
+    // Removes 'len_bytes' starting at 'byte_idx' and builds the matching 'Removed' event, unless
+    // the removal touches a protected region or is vetoed. Removing nothing gives no event.
+    fn apply_remove(&self, rope: &mut Rope, byte_idx: usize, len_bytes: usize, origin: EditOrigin) -> Result<Option<TextBufferChangedEvent>, EditError> {
+        if len_bytes == 0 {
+            return Ok(None);
+        }
+        self.check_edit(rope, TextRange::new(byte_idx, byte_idx + len_bytes), "", origin)?;
+        Ok(Some(self.apply_remove_unchecked(rope, byte_idx, len_bytes, origin)))
+    }
+
+    // 'apply_insert' without the checks. 'text' must not be empty.
+    fn apply_insert_unchecked(&self, rope: &mut Rope, byte_idx: usize, text: &str, origin: EditOrigin) -> TextBufferChangedEvent {
+        let event = insert_into_rope(rope, byte_idx, text, origin, self.bump_version());
+        self.changes.lock().record(&event);
+        self.count_inserted_words(rope, byte_idx, byte_idx + text.len());
+        {
+            let mut anchors = self.anchors.lock();
+            anchors.shift_for_insert(byte_idx, text.len());
+            self.edit_locations.lock().record(&mut anchors, rope, byte_idx + text.len());
+        }
+        self.coarsen(rope, event, text.len(), rope.len_bytes())
+    }
+
+    // 'apply_remove' without the checks. 'len_bytes' must not be 0.
+    fn apply_remove_unchecked(&self, rope: &mut Rope, byte_idx: usize, len_bytes: usize, origin: EditOrigin) -> TextBufferChangedEvent {
+        let mut anchors = self.anchors.lock();
+        // Line anchors need the pre-edit lines to tell whether their line is deleted.
+        anchors.drop_removed_lines(rope, byte_idx, len_bytes);
+        let len_before = rope.len_bytes();
+        self.count_removed_words(rope, byte_idx, byte_idx + len_bytes);
+        let event = remove_from_rope(rope, byte_idx, len_bytes, origin, self.bump_version());
+        anchors.shift_for_remove(byte_idx, len_bytes);
+        self.edit_locations.lock().record(&mut anchors, rope, byte_idx);
+        drop(anchors);
+        self.changes.lock().record(&event);
+        self.coarsen(rope, event, len_bytes, len_before)
+    }
+
+    // Replaces 'event' with 'Reloaded' if the 'affected' bytes exceed the reload threshold.
+    // 'larger_len' is the larger of the buffer lengths before and after the edit.
+    fn coarsen(&self, rope: &Rope, event: TextBufferChangedEvent, affected: usize, larger_len: usize) -> TextBufferChangedEvent {
+        if !self.exceeds_reload_threshold(affected, larger_len) {
+            return event;
+        }
+        TextBufferChangedEvent::Reloaded {
+            len_bytes: rope.len_bytes(),
+            len_lines: rope.len_lines(),
+            origin: event.origin(),
+            version: event.version(),
+        }
+    }
+
+    // Returns true if an edit of 'affected' bytes is to be reported as 'Reloaded' (see 'coarsen').
+    fn exceeds_reload_threshold(&self, affected: usize, larger_len: usize) -> bool {
+        match *self.reload_threshold.lock() {
+            Some(ratio) => larger_len > 0 && affected as f64 > ratio * larger_len as f64,
+            None => false,
+        }
+    }
+
+    // Wraps grouped events in a 'Batch', or collapses them into one 'Reloaded' if any of them
+    // already was one (a batch can't be replayed once part of it is imprecise).
+    fn batch(&self, rope: &Rope, events: Vec<TextBufferChangedEvent>) -> TextBufferChangedEvent {
+        if events.iter().any(|event| matches!(event, TextBufferChangedEvent::Reloaded { .. })) {
+            TextBufferChangedEvent::Reloaded {
+                len_bytes: rope.len_bytes(),
+                len_lines: rope.len_lines(),
+                origin: EditOrigin::User,
+                version: events.last().map_or(0, TextBufferChangedEvent::version),
+            }
+        } else {
+            TextBufferChangedEvent::Batch(events)
+        }
     }
 
 
@@ -116,14 +759,74 @@ impl TextBuffer {
         self.content.lock().to_string() // Acquire lock, convert Rope to String
     }
 
+    // Calls 'f' with the entire text borrowed straight from the rope if it is stored in a single
+    // chunk (true of small buffers, up to a few KB), or with None otherwise, in which case the
+    // caller falls back to 'get_text'. Saves the allocation for small documents. The buffer is
+    // locked while 'f' runs, so 'f' must not edit it.
+    pub fn as_str_if_contiguous<R>(&self, f: impl FnOnce(Option<&str>) -> R) -> R {
+        let rope = self.content.lock();
+        f(rope.slice(..).as_str())
+    }
+
+    // Returns the entire text together with the version it corresponds to, read under one lock
+    // so no edit can land in between (calling 'get_text' and then 'version' can tear). For
+    // observers rebuilding their state after lagging behind: events with a version up to the
+    // returned one are already reflected in the text.
+    pub fn resync(&self) -> (String, u64) {
+        let rope = self.content.lock();
+        (rope.to_string(), self.version())
+    }
+
+
+    // Runs 'f' on the rope if the lock can be acquired within 'timeout', or returns None.
+    // Lets a renderer keep its previous frame instead of freezing while a long edit (e.g. a big
+    // replace) holds the lock.
+    pub fn try_read_for<R>(&self, timeout: Duration, f: impl FnOnce(&Rope) -> R) -> Option<R> {
+        self.content.try_lock_for(timeout).map(|rope| f(&rope))
+    }
+
+    // Like 'get_text', but gives up after 'timeout' if the buffer is locked.
+    pub fn try_get_text_timeout(&self, timeout: Duration) -> Option<String> {
+        self.try_read_for(timeout, |rope| rope.to_string())
+    }
+
 
     // Retrieves a substring within the given byte range.
     pub fn get_range(&self, start_byte_idx: usize, end_byte_idx: usize) -> String {
         self.content.lock()
-            .slice(start_byte_idx..end_byte_idx)
+            .byte_slice(start_byte_idx..end_byte_idx)
             .to_string()
     }
 
+    // Like 'get_range' for 'range', together with the text's length in chars and its number of
+    // lines (line breaks + 1, so a text ending in a break counts the empty line after it). The
+    // counts come from the rope's tree metadata rather than a second scan of the text.
+    pub fn get_range_with_meta(&self, range: TextRange) -> RangeText {
+        let rope = self.content.lock();
+        let slice = rope.byte_slice(range.start..range.end);
+        RangeText { text: slice.to_string(), char_len: slice.len_chars(), line_count: slice.len_lines() }
+    }
+
+
+    // Returns '(start_byte, chunk_text)' for each rope chunk overlapping 'range', with the first
+    // and last chunks trimmed to the range. Chunks follow the rope's leaf nodes, so incremental
+    // tokenizers can re-process only the chunks an edit touched.
+    // A range that is out of bounds or splits a char yields no chunks.
+    pub fn chunks_in_range(&self, range: TextRange) -> Vec<(usize, String)> {
+        let rope = self.content.lock();
+        let Some(slice) = rope.get_byte_slice(range.start..range.end) else {
+            return Vec::new();
+        };
+        let mut start_byte = range.start;
+        slice.chunks()
+            .map(|chunk| {
+                let entry = (start_byte, chunk.to_string());
+                start_byte += chunk.len();
+                entry
+            })
+            .collect()
+    }
+
 
     // Returns the total length of the text in bytes.
     pub fn len_bytes(&self) -> usize {
@@ -131,42 +834,440 @@ impl TextBuffer {
     }
 
 
-    // Returns the total number of lines.
+    // Returns the total number of lines, as ropey counts them: text ending in a line break has
+    // an extra empty line after it ("a\nb\n" has 3). Line indices run '0..len_lines()', so this
+    // is the bound to use with 'line_to_byte' and friends.
     pub fn len_lines(&self) -> usize {
         self.content.lock().len_lines()
     }
 
+    // Returns the number of lines as a user counts them: a trailing line without a break counts,
+    // the empty line after a final line break doesn't ("a\nb\nc" and "a\nb\nc\n" both have 3).
+    // An empty buffer has 1 line, the one the cursor is on. Use this for the gutter and for
+    // "go to line N".
+    pub fn line_count(&self) -> usize {
+        rope_utils::user_line_count(&self.content.lock())
+    }
+
+    // Returns the start byte of 'line', or None if the buffer has no such line.
+    pub fn line_to_byte(&self, line: usize) -> Option<usize> {
+        let rope = self.content.lock();
+        (line < rope.len_lines()).then(|| rope.line_to_byte(line))
+    }
+
+    // Returns the line containing 'byte_idx'. Offsets past the end are clamped to the last line.
+    pub fn byte_to_line(&self, byte_idx: usize) -> usize {
+        let rope = self.content.lock();
+        rope.byte_to_line(byte_idx.min(rope.len_bytes()))
+    }
+
+    // Returns true if 'a' and 'b' are on the same line, looked up under one lock so an edit
+    // can't land between the two lookups. Offsets past the end are clamped like 'byte_to_line'.
+    pub fn same_line(&self, a: usize, b: usize) -> bool {
+        let rope = self.content.lock();
+        let line_of = |byte_idx: usize| rope.byte_to_line(byte_idx.min(rope.len_bytes()));
+        line_of(a) == line_of(b)
+    }
+
+    // Returns the 0-based char column of 'byte_idx' within its line (add 1 for a "Col 34"
+    // display), read straight from the rope without copying the line. An offset inside a char
+    // counts as that char's start; offsets past the end are clamped to the end.
+    pub fn column_of_byte(&self, byte_idx: usize) -> usize {
+        let rope = self.content.lock();
+        rope_utils::line_col(&rope, byte_idx.min(rope.len_bytes())).1
+    }
+
+    // Like 'column_of_byte', but with tabs expanded to the next multiple of 'tab_width' (as
+    // 'Indentation::visual_column' counts), for editors that show the visual column.
+    pub fn visual_column_of_byte(&self, byte_idx: usize, tab_width: usize) -> usize {
+        let rope = self.content.lock();
+        let char_idx = rope.byte_to_char(byte_idx.min(rope.len_bytes()));
+        let line_start = rope.line_to_char(rope.char_to_line(char_idx));
+        let width = tab_width.max(1);
+        rope.slice(line_start..char_idx).chars().fold(0, |column, c| match c {
+            '\t' => (column / width + 1) * width,
+            _ => column + 1,
+        })
+    }
+
+    // Returns the char boundary after the char at 'byte_idx', for stepping the cursor right one
+    // codepoint (use 'delete_forward' and friends for grapheme steps). An offset inside a char
+    // steps to that char's end; the end of the text stays put. None if 'byte_idx' is past the end.
+    pub fn next_char_boundary(&self, byte_idx: usize) -> Option<usize> {
+        let rope = self.content.lock();
+        match byte_idx.cmp(&rope.len_bytes()) {
+            std::cmp::Ordering::Less => Some(rope.char_to_byte(rope.byte_to_char(byte_idx) + 1)),
+            std::cmp::Ordering::Equal => Some(byte_idx),
+            std::cmp::Ordering::Greater => None,
+        }
+    }
+
+    // Returns the char boundary before 'byte_idx', for stepping the cursor left one codepoint. An
+    // offset inside a char steps to that char's start; 0 stays put. None if 'byte_idx' is past the
+    // end.
+    pub fn prev_char_boundary(&self, byte_idx: usize) -> Option<usize> {
+        let rope = self.content.lock();
+        if byte_idx > rope.len_bytes() {
+            return None;
+        }
+        Some(byte_idx.checked_sub(1).map_or(0, |last| rope.char_to_byte(rope.byte_to_char(last))))
+    }
+
+    // Returns the position at the start of 'line' ("go to line"), or None if there is no such line.
+    pub fn position_at_line_start(&self, line: usize) -> Option<TextPosition> {
+        self.line_to_byte(line).map(|byte_idx| TextPosition { byte_idx })
+    }
+
+    // Returns the position at the end of 'line', just before its line break (the end of the text
+    // for the last line, which has none), or None if there is no such line.
+    pub fn position_at_line_end(&self, line: usize) -> Option<TextPosition> {
+        let rope = self.content.lock();
+        (line < rope.len_lines()).then(|| TextPosition {
+            byte_idx: rope.line_to_byte(line) + rope_utils::line_content_len(&rope, line),
+        })
+    }
+
+    // Returns the position of the first non-whitespace char of 'line' (the "smart Home" target),
+    // or None if there is no such line. On a blank line that is the end of its whitespace.
+    pub fn first_non_whitespace(&self, line: usize) -> Option<TextPosition> {
+        let rope = self.content.lock();
+        let slice = rope.get_line(line)?;
+        let content_len = rope_utils::line_content_len(&rope, line);
+        let leading: usize = slice.chars().take_while(|c| c.is_whitespace()).map(char::len_utf8).sum();
+        Some(TextPosition { byte_idx: rope.line_to_byte(line) + leading.min(content_len) })
+    }
+
+    // Returns the position just after the last non-whitespace char of 'line', before trailing
+    // whitespace and the line break (the "smart End" target), or None if there is no such line.
+    // On a blank line that is the line start.
+    pub fn line_content_end(&self, line: usize) -> Option<TextPosition> {
+        let rope = self.content.lock();
+        let slice = rope.get_line(line)?;
+        let line_start = rope.line_to_byte(line);
+        let content = slice.byte_slice(..rope_utils::line_content_len(&rope, line)).to_string();
+        Some(TextPosition { byte_idx: line_start + content.trim_end().len() })
+    }
+
+    // Returns the position of char column 'col' on 'line' (both 0-based), e.g. for a click, or
+    // None if there is no such line or the column lies past the line's content.
+    pub fn position_at(&self, line: usize, col: usize) -> Option<TextPosition> {
+        let rope = self.content.lock();
+        if line >= rope.len_lines() {
+            return None;
+        }
+        let line_start_char = rope.line_to_char(line);
+        let content_end = rope.line_to_byte(line) + rope_utils::line_content_len(&rope, line);
+        (col <= rope.byte_to_char(content_end) - line_start_char)
+            .then(|| TextPosition { byte_idx: rope.char_to_byte(line_start_char + col) })
+    }
+
+    // Returns the position of char column 'col' on 'line' (both 0-based), clamped to the text: a
+    // line past the end lands on the last line (as 'line_count' counts them), a column past the
+    // line's end at the end of its content.
+    pub fn clamped_position(&self, line: usize, col: usize) -> TextPosition {
+        let rope = self.content.lock();
+        let line = line.min(rope_utils::user_line_count(&rope) - 1);
+        let line_start_char = rope.line_to_char(line);
+        let content_end = rope.line_to_byte(line) + rope_utils::line_content_len(&rope, line);
+        let col = col.min(rope.byte_to_char(content_end) - line_start_char);
+        TextPosition { byte_idx: rope.char_to_byte(line_start_char + col) }
+    }
+
+    // Returns the length of 'line' in bytes without its line break, or None if there is no such
+    // line. Cheap even for a multi-megabyte line, so callers can check before fetching text.
+    pub fn line_len_bytes(&self, line: usize) -> Option<usize> {
+        let rope = self.content.lock();
+        (line < rope.len_lines()).then(|| rope_utils::line_content_len(&rope, line))
+    }
+
+    // Returns the text of 'line' without its line break ("\n", "\r\n" or any other break ropey
+    // recognizes), or None if there is no such line. The last line, which has no break, is
+    // returned as is.
+    pub fn line_trimmed(&self, line: usize) -> Option<String> {
+        let rope = self.content.lock();
+        let slice = rope.get_line(line)?;
+        Some(slice.byte_slice(..rope_utils::line_content_len(&rope, line)).to_string())
+    }
+
+    // Returns 'line_trimmed' for each line in 'lines', read under one lock so they all come from
+    // the same version of the text. Lines past the end are left out.
+    pub fn lines_trimmed_in_range(&self, lines: Range<usize>) -> Vec<String> {
+        let rope = self.content.lock();
+        (lines.start..lines.end.min(rope.len_lines()))
+            .map(|line| rope.line(line).byte_slice(..rope_utils::line_content_len(&rope, line)).to_string())
+            .collect()
+    }
+
+    // Returns the part of 'line' covered by 'byte_range', given relative to the line start, e.g.
+    // the horizontally visible window of a very long line. The range is clamped to the line's
+    // content (never including the line break) and widened to whole chars. Empty if the line
+    // doesn't exist.
+    pub fn line_slice(&self, line: usize, byte_range: Range<usize>) -> String {
+        let rope = self.content.lock();
+        if line >= rope.len_lines() {
+            return String::new();
+        }
+        let line_start = rope.line_to_byte(line);
+        let content_end = line_start + rope_utils::line_content_len(&rope, line);
+        let start = (line_start + byte_range.start).min(content_end);
+        let end = (line_start + byte_range.end).clamp(start, content_end);
+
+        let start = rope.char_to_byte(rope.byte_to_char(start));
+        let end_char = rope.byte_to_char(end);
+        let end = if rope.char_to_byte(end_char) < end { rope.char_to_byte(end_char + 1) } else { end };
+        rope.byte_slice(start..end).to_string()
+    }
+
+    // Returns true if 'line' holds only whitespace (or nothing). False if the line doesn't exist.
+    // Reads the rope in place instead of copying the line out.
+    pub fn line_is_blank(&self, line: usize) -> bool {
+        let rope = self.content.lock();
+        rope.get_line(line).is_some_and(|slice| slice.chars().all(char::is_whitespace))
+    }
+
+    // Returns true if 'line' starts with 'prefix'. False if the line doesn't exist. Only the first
+    // 'prefix.len()' bytes of the line are read.
+    pub fn line_starts_with(&self, line: usize, prefix: &str) -> bool {
+        let rope = self.content.lock();
+        rope.get_line(line).is_some_and(|slice| {
+            slice.len_bytes() >= prefix.len() && slice.bytes().zip(prefix.bytes()).all(|(a, b)| a == b)
+        })
+    }
+
     // Observer Management
 
-    // Adds a new observer. The observer should provide a channel sender.
+    // Adds a new observer with the default priority. The observer should provide a channel sender.
     // This allows decoupling the observer's implementation from the TextBuffer.
-    pub fn add_observer(&self, sender: mpsc::Sender<TextBufferChangedEvent>) {
-        self.observers.lock().push(sender);
+    pub fn add_observer(&self, sender: mpsc::Sender<TextBufferChangedEvent>) -> ObserverId {
+        self.add_observer_with_priority(sender, Self::DEFAULT_OBSERVER_PRIORITY)
     }
 
-    // Internal helper to notify all registered observers.
-    async fn notify_observers(&self, event: TextBufferChangedEvent) {
-        let observers = self.observers.lock();
-        // Iterate through senders and attempt to send the event.
-        // Remove disconnected channels to clean up.
-        let mut disconnected_senders = Vec::new();
-        for (i, sender) in observers.iter().enumerate() {
-            if sender.send(event.clone()).await.is_err() {
-                // If send fails, it means the receiver part of the channel is dropped.
-                // mark this sender for removal.
-                disconnected_senders.push(i);
+    // Adds a new observer with an explicit delivery priority.
+    // Delivery order is deterministic: every event is sent to observers with a higher priority
+    // before observers with a lower one, and observers sharing a priority receive it in
+    // registration order. Use this when observers depend on each other (e.g. a highlighter
+    // that must see an edit before a diagnostics pruner).
+    pub fn add_observer_with_priority(&self, sender: mpsc::Sender<TextBufferChangedEvent>, priority: i32) -> ObserverId {
+        register_observer(&self.observers, sender, priority)
+    }
+
+    // Creates a channel holding up to 'buffer_size' undelivered events, registers its sender with
+    // the default priority and returns the receiving end.
+    pub fn register(&self, buffer_size: usize) -> (mpsc::Receiver<TextBufferChangedEvent>, ObserverId) {
+        let (sender, receiver) = mpsc::channel(buffer_size);
+        (receiver, self.add_observer(sender))
+    }
+
+    // Like 'register', but the subscription lasts exactly as long as the returned guard.
+    pub fn register_guarded(&self, buffer_size: usize) -> (mpsc::Receiver<TextBufferChangedEvent>, ObserverGuard) {
+        let (receiver, id) = self.register(buffer_size);
+        (receiver, ObserverGuard { id, observers: Arc::downgrade(&self.observers) })
+    }
+
+    // Adds an observer that is called synchronously on every change (see
+    // 'ISyncTextBufferObserver'). Remove it with 'remove_observer'.
+    pub fn add_sync_observer(&self, observer: Arc<dyn ISyncTextBufferObserver>) -> ObserverId {
+        let id = ObserverId::next();
+        self.sync_observers.lock().push((id, observer));
+        id
+    }
+
+    // Unregisters an observer of either kind. Returns false if it was not registered (or already
+    // pruned).
+    pub fn remove_observer(&self, id: ObserverId) -> bool {
+        let mut sync_observers = self.sync_observers.lock();
+        if let Some(idx) = sync_observers.iter().position(|(entry_id, _)| *entry_id == id) {
+            sync_observers.remove(idx);
+            return true;
+        }
+        drop(sync_observers);
+        let mut pre_edit_observers = self.pre_edit_observers.lock();
+        if let Some(idx) = pre_edit_observers.iter().position(|(entry_id, _)| *entry_id == id) {
+            pre_edit_observers.remove(idx);
+            return true;
+        }
+        drop(pre_edit_observers);
+        unregister_observer(&self.observers, id)
+    }
+
+    // Returns the number of registered observers of all kinds. Disconnected channels are only
+    // pruned on the next notification, so they may still be counted.
+    pub fn observer_count(&self) -> usize {
+        self.observers.lock().len() + self.sync_observers.lock().len() + self.pre_edit_observers.lock().len()
+    }
+
+    // Unregisters all channel observers and returns them in delivery order, so a whole-document
+    // rebuild can run without sending any events. Sync and pre-edit observers stay registered.
+    // Hand the result to 'reattach_observers' afterwards, then call 'resync_observers' so the
+    // observers re-read everything they missed.
+    pub fn detach_observers(&self) -> Vec<(ObserverId, mpsc::Sender<TextBufferChangedEvent>)> {
+        self.observers.lock().drain(..).map(|entry| (entry.id, entry.sender)).collect()
+    }
+
+    // Registers observers returned by 'detach_observers' again under their old ids, after any
+    // observers added in the meantime and in the order given. Priorities are not kept: they are
+    // reattached with the default priority.
+    pub fn reattach_observers(&self, saved: Vec<(ObserverId, mpsc::Sender<TextBufferChangedEvent>)>) {
+        let mut observers = self.observers.lock();
+        for (id, sender) in saved {
+            let idx = observers.partition_point(|entry| entry.priority >= Self::DEFAULT_OBSERVER_PRIORITY);
+            observers.insert(idx, ObserverEntry { id, sender, priority: Self::DEFAULT_OBSERVER_PRIORITY });
+        }
+    }
+
+    // Emits a 'Reloaded' for the current content without changing it, so observers re-read the
+    // whole buffer. Its version is the current one, as no edit is applied.
+    pub async fn resync_observers(&self) {
+        let event = {
+            let rope = self.content.lock();
+            TextBufferChangedEvent::Reloaded {
+                len_bytes: rope.len_bytes(),
+                len_lines: rope.len_lines(),
+                origin: EditOrigin::User,
+                version: self.version(),
             }
+        };
+        self.notify_observers(event).await;
+    }
+
+    // Internal helper to notify all registered observers: the synchronous ones first, then the
+    // channels (see 'notify_all').
+    async fn notify_observers(&self, event: TextBufferChangedEvent) {
+        let _in_progress = self.notifications.read().await;
+        // Cloned out so an observer registering another one can't deadlock.
+        let sync_observers: Vec<_> = self.sync_observers.lock().iter().map(|(_, observer)| Arc::clone(observer)).collect();
+        for observer in sync_observers {
+            observer.on_buffer_changed(&event);
         }
+        notify_all(&self.observers, event).await;
+    }
 
-        // Remove disconnected senders in reverse order to avoid index shifting issues.
-        let mut observers_mut = observers.into_mut(); // Gain mutable access to Vec inside the Mutex
-        for &idx in disconnected_senders.iter().rev() {
-            observers_mut.remove(idx);
+    // Waits until every notification already in progress has been delivered: sync observers
+    // have returned and each event has been sent into its observer channels (a full channel is
+    // waited on). Edits running on other tasks are covered once they have started notifying.
+    // It doesn't wait for receivers to process the events. For tests that assert on observer
+    // state, and for draining at shutdown.
+    pub async fn flush_notifications(&self) {
+        drop(self.notifications.write().await);
+    }
+
+}
+
+
+// Shared Edit Helpers
+// Rope edits and observer bookkeeping shared by 'TextBuffer' and 'AsyncTextBuffer', so both emit
+// identical events.
+
+// Inserts 'text' at 'byte_idx' and builds the matching 'Inserted' event.
+fn insert_into_rope(rope: &mut Rope, byte_idx: usize, text: &str, origin: EditOrigin, version: u64) -> TextBufferChangedEvent {
+    // ropey edits by char index; convert once under the lock.
+    let char_idx = rope.byte_to_char(byte_idx);
+    rope.insert(char_idx, text);
+    inserted_event(rope, byte_idx, text, origin, version)
+}
+
+// Builds the 'Inserted' event for 'text', which 'rope' already holds at 'byte_idx'.
+fn inserted_event(rope: &Rope, byte_idx: usize, text: &str, origin: EditOrigin, version: u64) -> TextBufferChangedEvent {
+    let start_line = rope.byte_to_line(byte_idx);
+    let end_line = rope.byte_to_line(byte_idx + text.len());
+    TextBufferChangedEvent::Inserted {
+        start_byte_idx: byte_idx,
+        start_line_col: rope_utils::line_col(rope, byte_idx),
+        len_bytes: text.len(),
+        text: Arc::from(text),
+        dirty_line_range: start_line..end_line + 1,
+        affects_line_count: end_line != start_line,
+        origin,
+        kind: EditKind::classify(text, || rope_utils::line_prefix(rope, byte_idx)),
+        version,
+    }
+}
+
+// Removes 'len_bytes' starting at 'byte_idx' and builds the matching 'Removed' event.
+fn remove_from_rope(rope: &mut Rope, byte_idx: usize, len_bytes: usize, origin: EditOrigin, version: u64) -> TextBufferChangedEvent {
+    let end_byte_idx = byte_idx + len_bytes;
+    // Line numbers are taken before the removal, while the removed span still exists.
+    let start_line = rope.byte_to_line(byte_idx);
+    let end_line = rope.byte_to_line(end_byte_idx);
+    let start_line_col = rope_utils::line_col(rope, byte_idx);
+
+    let removed_text: Arc<str> = Arc::from(rope.byte_slice(byte_idx..end_byte_idx).to_string());
+    let kind = EditKind::classify(&removed_text, || rope_utils::line_prefix(rope, byte_idx));
+
+    let start_char = rope.byte_to_char(byte_idx);
+    let end_char = rope.byte_to_char(end_byte_idx);
+    rope.remove(start_char..end_char);
+
+    TextBufferChangedEvent::Removed {
+        start_byte_idx: byte_idx,
+        start_line_col,
+        len_bytes,
+        text: removed_text,
+        dirty_line_range: start_line..end_line + 1,
+        affects_line_count: end_line != start_line,
+        origin,
+        kind,
+        version,
+    }
+}
+
+// Inserts an observer keeping the list in delivery order.
+fn register_observer(observers: &Mutex<Vec<ObserverEntry>>, sender: mpsc::Sender<TextBufferChangedEvent>, priority: i32) -> ObserverId {
+    let id = ObserverId::next();
+    let mut observers = observers.lock();
+    // Insert after every entry with a priority >= ours, so ties keep registration order.
+    let idx = observers.partition_point(|entry| entry.priority >= priority);
+    observers.insert(idx, ObserverEntry { id, sender, priority });
+    id
+}
+
+fn unregister_observer(observers: &Mutex<Vec<ObserverEntry>>, id: ObserverId) -> bool {
+    let mut observers = observers.lock();
+    let before = observers.len();
+    observers.retain(|entry| entry.id != id);
+    observers.len() != before
+}
+
+// Events are delivered sequentially in the documented priority order; each send is awaited
+// before the next observer is served, so a higher-priority observer always has the event
+// queued first.
+async fn notify_all(observers: &Mutex<Vec<ObserverEntry>>, event: TextBufferChangedEvent) {
+    // Deliver to a snapshot so the lock isn't held across the awaits: a slow observer must not
+    // block (or deadlock) registration, and observers added meanwhile get the next event.
+    let targets: Vec<(ObserverId, mpsc::Sender<TextBufferChangedEvent>)> = observers.lock().iter()
+        .map(|entry| (entry.id, entry.sender.clone()))
+        .collect();
+    let mut disconnected = Vec::new();
+    for (id, sender) in &targets {
+        if sender.send(event.clone()).await.is_err() {
+            // The receiver part of the channel was dropped.
+            disconnected.push(*id);
         }
     }
 
+    // Prune by id, not by position: the list may have changed while the event was delivered.
+    // 'retain' keeps the remaining entries in priority order.
+    if !disconnected.is_empty() {
+        observers.lock().retain(|entry| !disconnected.contains(&entry.id));
+    }
 }
 
+// Document Event
+// Document-level changes (as opposed to text changes, which the TextBuffer reports).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DocumentEvent {
+    // The language id changed, e.g. after 'save_as' with a new extension. Language servers should
+    // be switched from 'old' to 'new'.
+    LanguageChanged { old: String, new: String },
+    // The document was written to 'path' (by 'save_with' and everything built on it, including
+    // auto-save).
+    Saved { path: String },
+    // An edit made the text empty (true) or non-empty again (false). Sent only on those
+    // transitions; see 'emptiness' for how it is delivered.
+    EmptinessChanged(bool),
+}
+
+
 // Document Entity
 // Represent a single file or document open in the IDE.
 // Adheres to SRP by managing document-level properties, not the raw text content (which is textBuffer's job).
@@ -174,18 +1275,79 @@ pub struct Document {
     pub file_path: Option<String>, // Path to file, None for unsaved new documents
     pub text_buffer: Arc<TextBuffer>, // Shared reference to the associated text buffer
     is_dirty: Mutex<bool>, // Indicated if the document has unsaved changes
-    pub language_id: String, // e.g., "rust", "cpp", "plaintext"
+    track_dirty: Mutex<bool>, // Cleared for ephemeral buffers that never have unsaved changes
+    language_id: Mutex<String>, // e.g., "rust", "cpp", "plaintext"; changes are reported as events
+    observers: Arc<Mutex<Vec<mpsc::Sender<DocumentEvent>>>>, // Receivers of 'DocumentEvent's
+    encoding: Mutex<EncodingDetection>, // How the text was decoded (see 'encoding')
+    saved_hash: Mutex<Option<u64>>, // 'content_hash' of the text on disk, when known
+    settings: Mutex<DocumentSettings>, // Per-file editing behavior (see 'settings')
+    read_only: bool, // Set by 'DocumentBuilder::read_only'; the buffer then rejects edits
+    memory_name: Option<String>, // Name of a view of external content (see 'from_memory')
+    metrics: Arc<Mutex<DocMetrics>>, // Kept up to date by a sync observer (see 'metrics')
 }
 
 impl Document {
     // Creates a new Document, optionally from an existing file path and initial content.
     pub fn new(file_path: Option<String>, initial_content: &str, language_id: String) -> Self {
+        let text_buffer = Arc::new(TextBuffer::new(initial_content));
+        let observers = Arc::new(Mutex::new(Vec::new()));
+        EmptinessWatcher::watch(&text_buffer, Arc::clone(&observers));
+        let metrics = Arc::new(Mutex::new(DocMetrics::default()));
+        MetricsWatcher::watch(&text_buffer, Arc::clone(&metrics));
         Self {
             file_path,
-            text_buffer: Arc::new(TextBuffer::new(initial_content)),
+            text_buffer,
             is_dirty: Mutex::new(false), // New documents are initially clean until modified
-            language_id,
+            track_dirty: Mutex::new(true),
+            language_id: Mutex::new(language_id),
+            observers,
+            encoding: Mutex::new(EncodingDetection::certain(Encoding::Utf8)),
+            saved_hash: Mutex::new(None),
+            settings: Mutex::new(DocumentSettings::default()),
+            read_only: false,
+            memory_name: None,
+            metrics,
+        }
+    }
+
+    // Returns the document's language id.
+    pub fn language_id(&self) -> String {
+        self.language_id.lock().clone()
+    }
+
+    // Changes the language id and emits 'DocumentEvent::LanguageChanged' if it actually changed.
+    pub async fn set_language(&self, id: String) {
+        let old = {
+            let mut language_id = self.language_id.lock();
+            if *language_id == id {
+                return;
+            }
+            std::mem::replace(&mut *language_id, id.clone())
+        };
+        self.notify_observers(DocumentEvent::LanguageChanged { old, new: id }).await;
+    }
+
+    // Adds an observer for document-level events.
+    pub fn add_observer(&self, sender: mpsc::Sender<DocumentEvent>) {
+        self.observers.lock().push(sender);
+    }
+
+    // Sends 'event' to every observer in registration order, dropping disconnected ones.
+    // The senders are cloned out first so the lock isn't held across the awaits.
+    async fn notify_observers(&self, event: DocumentEvent) {
+        let senders: Vec<_> = self.observers.lock().clone();
+        let mut disconnected = false;
+        for sender in &senders {
+            disconnected |= sender.send(event.clone()).await.is_err();
         }
+        if disconnected {
+            self.observers.lock().retain(|sender| !sender.is_closed());
+        }
+    }
+
+    // Returns true if the document was built read-only (see 'DocumentBuilder').
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
     }
 
     // Returns true if the document has unsaved changes.
@@ -193,9 +1355,34 @@ impl Document {
         *self.is_dirty.lock()
     }
 
-    // Sets the dirty state of the document.
+    // Sets the dirty state of the document. Ignored while dirty tracking is off.
     pub fn set_dirty(&self, dirty: bool) {
-        *self.is_dirty.lock() = dirty;
+        if *self.track_dirty.lock() {
+            *self.is_dirty.lock() = dirty;
+        }
+    }
+
+    // Turns dirty tracking on or off. With it off the document is never dirty, so ephemeral
+    // buffers (search inputs, REPL scratch) stay out of save prompts and 'save_all' while
+    // remaining editable, unlike read-only documents. Turning it off clears the flag, and
+    // turning it back on starts clean.
+    pub fn set_track_dirty(&self, track: bool) {
+        let mut track_dirty = self.track_dirty.lock();
+        if *track_dirty != track {
+            *track_dirty = track;
+            *self.is_dirty.lock() = false;
+        }
+    }
+
+    // Returns false if dirty tracking was turned off (see 'set_track_dirty').
+    pub fn tracks_dirty(&self) -> bool {
+        *self.track_dirty.lock()
+    }
+
+    // Records that 'text' is what is on disk (after loading or saving), as decoded from the file
+    // rather than read back from the buffer, which may have moved on.
+    pub(crate) fn mark_saved(&self, text: &str) {
+        *self.saved_hash.lock() = Some(bytes::text_hash(text));
     }
 
     // Returns a reference to the underlying textBuffer.
@@ -206,10 +1393,30 @@ impl Document {
     // Helper to get the filename from the path, or "Untitled" for new docs.
     pub fn file_name(&self) -> String {
         self.file_path.as_ref()
-            .and_then(|p| std::Path::new(p).file_name())
+            .and_then(|p| std::path::Path::new(p).file_name())
             .and_then(|os_str| os_str.to_str())
             .map_or_else(|| "Untitled".to_string(), |s| s.to_string())
     }
+
+    // Returns the name to show for the document: the file name, the name given to
+    // 'from_memory', or "Untitled" for new docs.
+    pub fn display_name(&self) -> String {
+        match (&self.file_path, &self.memory_name) {
+            (None, Some(name)) => name.clone(),
+            _ => self.file_name(),
+        }
+    }
+
+    // Returns 'display_name' shortened to 'max_cols' cells with 'truncate_display', using the
+    // document's tab width.
+    pub fn display_name_truncated(&self, max_cols: usize) -> String {
+        truncate_display(&self.display_name(), max_cols, self.settings().indentation.tab_width)
+    }
+
+    // Returns true if the document is a view of external content made by 'from_memory'.
+    pub fn is_in_memory(&self) -> bool {
+        self.memory_name.is_some()
+    }
 }
 
 
@@ -0,0 +1,114 @@
+// Line Endings
+// Detection and normalization of the line ending style ("\n", "\r\n" or "\r") used by a buffer.
+// Scans stream over the rope's chunks, so large files are never copied into one string.
+use ropey::Rope;
+
+use crate::{EditKind, TextBuffer};
+
+
+// Line Ending Enum
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LineEnding {
+    Lf,
+    CrLf,
+    Cr,
+}
+
+impl LineEnding {
+    // Returns the characters of this line ending.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LineEnding::Lf => "\n",
+            LineEnding::CrLf => "\r\n",
+            LineEnding::Cr => "\r",
+        }
+    }
+}
+
+
+// Calls 'on_ending' with the start byte and style of every line ending, in order.
+// A "\r\n" split across two chunks is still reported once, as 'CrLf'.
+pub(crate) fn scan_line_endings(rope: &Rope, mut on_ending: impl FnMut(usize, LineEnding)) {
+    let mut pending_cr: Option<usize> = None;
+    let mut chunk_start = 0;
+    for chunk in rope.chunks() {
+        for (offset, byte) in chunk.bytes().enumerate() {
+            let byte_idx = chunk_start + offset;
+            if let Some(cr_idx) = pending_cr.take() {
+                if byte == b'\n' {
+                    on_ending(cr_idx, LineEnding::CrLf);
+                    continue;
+                }
+                on_ending(cr_idx, LineEnding::Cr);
+            }
+            match byte {
+                b'\r' => pending_cr = Some(byte_idx),
+                b'\n' => on_ending(byte_idx, LineEnding::Lf),
+                _ => {}
+            }
+        }
+        chunk_start += chunk.len();
+    }
+    if let Some(cr_idx) = pending_cr {
+        on_ending(cr_idx, LineEnding::Cr);
+    }
+}
+
+// Returns the most frequent line ending, or None if there are none. Ties prefer Lf, then CrLf.
+pub(crate) fn dominant_line_ending(rope: &Rope) -> Option<LineEnding> {
+    let mut counts = [0usize; 3];
+    scan_line_endings(rope, |_, ending| counts[ending as usize] += 1);
+    [LineEnding::Lf, LineEnding::CrLf, LineEnding::Cr].into_iter()
+        .filter(|ending| counts[*ending as usize] > 0)
+        .max_by_key(|ending| (counts[*ending as usize], std::cmp::Reverse(*ending as usize)))
+}
+
+
+impl TextBuffer {
+    // Line Endings
+
+    // Returns the dominant line ending style, or None if the text has no line breaks.
+    pub fn detect_line_ending(&self) -> Option<LineEnding> {
+        dominant_line_ending(&self.content.lock())
+    }
+
+    // Returns the start bytes of the line endings that differ from the dominant style, so an
+    // editor can flag them and offer to normalize. Empty if the endings are consistent.
+    pub fn mixed_line_ending_locations(&self) -> Vec<usize> {
+        let rope = self.content.lock();
+        let Some(dominant) = dominant_line_ending(&rope) else {
+            return Vec::new();
+        };
+        let mut locations = Vec::new();
+        scan_line_endings(&rope, |byte_idx, ending| {
+            if ending != dominant {
+                locations.push(byte_idx);
+            }
+        });
+        locations
+    }
+    // Rewrites every line ending that isn't 'ending', as one 'Batch' of 'WhitespaceOnly' edits.
+    // Returns false if there was nothing to change or it was rejected (vetoed, or touching a
+    // protected region).
+    pub async fn normalize_line_endings(&self, ending: LineEnding) -> bool {
+        let mut rope = self.content.lock();
+        let mut edits = Vec::new();
+        scan_line_endings(&rope, |byte_idx, found| {
+            if found != ending {
+                edits.push((byte_idx, byte_idx + found.as_str().len(), ending.as_str().to_string()));
+            }
+        });
+        if edits.is_empty() {
+            return false;
+        }
+        let Ok((events, _)) = self.apply_edits_locked(&mut rope, edits) else {
+            return false;
+        };
+        let mut event = self.batch(&rope, events);
+        event.set_kind(EditKind::WhitespaceOnly);
+        drop(rope);
+
+        self.notify_observers(event).await;
+        true
+    }
+}
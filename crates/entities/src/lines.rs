@@ -0,0 +1,387 @@
+// Line Operations
+// Editor commands that work on whole lines: delete, duplicate, move, toggle comments, prefixes
+// and suffixes, and whitespace cleanup.
+//
+// Locking invariant: every command locks the rope exactly once, computes all line -> byte offsets
+// from that locked rope, applies its edits and only notifies observers after releasing the lock.
+// Looking up a line's offsets under one lock and editing under another would let a concurrent
+// edit slip in between, so the command would delete or move the wrong text.
+// Commands that need several edits apply them back to front, so offsets computed up front stay
+// valid, and the events are delivered in the order the edits were applied.
+use std::ops::Range;
+
+use ropey::Rope;
+
+use crate::edits::check_range;
+use crate::rope_utils::{line_content_len, split_line_ending};
+use crate::{EditError, EditKind, EditOrigin, LangSyntax, LineEnding, TextBuffer, TextBufferChangedEvent, TextPosition, TextRange};
+
+
+// Returns the byte span of 'line' including its terminator, or None if the line doesn't exist.
+fn line_span(rope: &Rope, line: usize) -> Option<Range<usize>> {
+    if line >= rope.len_lines() {
+        return None;
+    }
+    Some(rope.line_to_byte(line)..rope.line_to_byte(line + 1))
+}
+
+fn line_text(rope: &Rope, line: usize) -> String {
+    rope.line(line).to_string()
+}
+
+
+impl TextBuffer {
+    // Line Operations
+
+    // Deletes 'line' together with its line break. Deleting the last line removes the break
+    // before it instead, so no empty line is left behind. Returns false if the line doesn't exist
+    // or the deletion was rejected (vetoed, or touching a protected region).
+    pub async fn delete_line(&self, line: usize) -> bool {
+        let mut rope = self.content.lock();
+        let Some(span) = line_span(&rope, line) else {
+            return false;
+        };
+        let start = if line + 1 == rope.len_lines() && line > 0 {
+            // The preceding line's terminator; the last line has none of its own.
+            rope.line_to_byte(line - 1) + split_line_ending(&line_text(&rope, line - 1)).0.len()
+        } else {
+            span.start
+        };
+        if start == span.end {
+            return false;
+        }
+        let Ok(Some(event)) = self.apply_remove(&mut rope, start, span.end - start, EditOrigin::User) else {
+            return false;
+        };
+        drop(rope);
+
+        self.notify_observers(event).await;
+        true
+    }
+
+    // Deletes 'count' lines from 'start_line' on (fewer if the buffer ends first) with their line
+    // breaks, like 'delete_line' for the last line. Returns where 'cursor' ends up: it keeps its
+    // place when outside the deleted lines, and otherwise lands on the line that takes their
+    // place (or the new last line) at the same char column, clamped to that line's length.
+    // Fails if 'start_line' or 'cursor' is out of bounds, or if the edit is vetoed or touches
+    // a protected region. Deleting no lines leaves everything as it is.
+    pub async fn delete_lines(&self, start_line: usize, count: usize, cursor: TextPosition) -> Result<TextPosition, EditError> {
+        let (event, cursor) = {
+            let mut rope = self.content.lock();
+            check_range(&rope, cursor.byte_idx, cursor.byte_idx)?;
+            let len_lines = rope.len_lines();
+            if start_line >= len_lines {
+                return Err(EditError::LineOutOfBounds { line: start_line, len_lines });
+            }
+            let end_line = start_line.saturating_add(count).min(len_lines);
+            let (start, end) = if end_line < len_lines {
+                (rope.line_to_byte(start_line), rope.line_to_byte(end_line))
+            } else if start_line > 0 {
+                // Up to the end, so the break before the first deleted line goes instead.
+                (rope.line_to_byte(start_line - 1) + line_content_len(&rope, start_line - 1), rope.len_bytes())
+            } else {
+                (0, rope.len_bytes())
+            };
+            if start == end {
+                return Ok(cursor);
+            }
+            let cursor_line = rope.byte_to_line(cursor.byte_idx);
+            let col = rope.byte_to_char(cursor.byte_idx) - rope.line_to_char(cursor_line);
+            let event = self.apply_remove(&mut rope, start, end - start, EditOrigin::User)?;
+            let byte_idx = if cursor_line < start_line {
+                cursor.byte_idx.min(start)
+            } else if cursor_line >= end_line {
+                cursor.byte_idx - (end - start)
+            } else {
+                let line = start_line.min(rope.len_lines() - 1);
+                let line_start = rope.line_to_char(line);
+                let content_chars = rope.byte_to_char(rope.line_to_byte(line) + line_content_len(&rope, line)) - line_start;
+                rope.char_to_byte(line_start + col.min(content_chars))
+            };
+            (event, TextPosition { byte_idx })
+        };
+        if let Some(event) = event {
+            self.notify_observers(event).await;
+        }
+        Ok(cursor)
+    }
+
+    // Replaces the content of 'line' (everything before its line break, which is kept) with
+    // 'new_content' as one edit, like 'replace', and returns the position right after the new
+    // content. Fails if the line doesn't exist, or for the reasons 'replace' fails.
+    pub async fn replace_line(&self, line: usize, new_content: &str) -> Result<TextPosition, EditError> {
+        let new_content = &*self.normalization().apply(new_content);
+        let (event, end) = {
+            let mut rope = self.content.lock();
+            let len_lines = rope.len_lines();
+            if line >= len_lines {
+                return Err(EditError::LineOutOfBounds { line, len_lines });
+            }
+            let start = rope.line_to_byte(line);
+            let range = TextRange::new(start, start + line_content_len(&rope, line));
+            let end = TextPosition { byte_idx: start + new_content.len() };
+            if range.is_empty() && new_content.is_empty() {
+                return Ok(end);
+            }
+            self.check_edit(&rope, range, new_content, EditOrigin::User)?;
+            let mut events = Vec::with_capacity(2);
+            if !range.is_empty() {
+                events.push(self.apply_remove_unchecked(&mut rope, range.start, range.len(), EditOrigin::User));
+            }
+            if !new_content.is_empty() {
+                events.push(self.apply_insert_unchecked(&mut rope, range.start, new_content, EditOrigin::User));
+            }
+            (if events.len() == 1 { events.remove(0) } else { self.batch(&rope, events) }, end)
+        };
+        self.notify_observers(event).await;
+        Ok(end)
+    }
+
+    // Inserts a copy of 'line' directly below it. Returns false if the line doesn't exist or the
+    // insertion was rejected (vetoed, or inside a protected region).
+    pub async fn duplicate_line(&self, line: usize) -> bool {
+        let mut rope = self.content.lock();
+        let Some(span) = line_span(&rope, line) else {
+            return false;
+        };
+        let text = line_text(&rope, line);
+        let (content, ending) = split_line_ending(&text);
+        let copy = if ending.is_empty() {
+            // The last line: break it and append the copy after the break.
+            let ending = line.checked_sub(1)
+                .map(|prev| split_line_ending(&line_text(&rope, prev)).1.to_string())
+                .unwrap_or_else(|| "\n".to_string());
+            format!("{ending}{content}")
+        } else {
+            text.clone()
+        };
+        let Ok(Some(event)) = self.apply_insert(&mut rope, span.end, &copy, EditOrigin::User) else {
+            return false;
+        };
+        drop(rope);
+
+        self.notify_observers(event).await;
+        true
+    }
+
+    // Moves line 'from' so that it ends up at index 'to', shifting the lines in between by one.
+    // Line breaks stay where they are and only the line contents move, so moving the last line
+    // (which has no break) up works as expected. Pre-edit observers see one replacement of the
+    // affected lines, and observers are sent one 'Batch' of the removal and the insertion.
+    // Returns false if either line doesn't exist or the move was rejected (vetoed, or touching a
+    // protected region).
+    pub async fn move_line(&self, from: usize, to: usize) -> bool {
+        let mut rope = self.content.lock();
+        let line_count = rope.len_lines();
+        if from >= line_count || to >= line_count {
+            return false;
+        }
+        if from == to {
+            return true;
+        }
+
+        let (first, last) = (from.min(to), from.max(to));
+        let start = rope.line_to_byte(first);
+        let end = rope.line_to_byte(last + 1);
+        let lines: Vec<String> = (first..=last).map(|line| line_text(&rope, line)).collect();
+        let (mut contents, endings): (Vec<&str>, Vec<&str>) = lines.iter().map(|line| split_line_ending(line)).unzip();
+        let moved = contents.remove(from - first);
+        contents.insert(to - first, moved);
+        let replacement: String = contents.iter().zip(&endings).flat_map(|(content, ending)| [*content, *ending]).collect();
+
+        if self.check_edit(&rope, TextRange::new(start, end), &replacement, EditOrigin::User).is_err() {
+            return false;
+        }
+        let removed = self.apply_remove_unchecked(&mut rope, start, end - start, EditOrigin::User);
+        let inserted = self.apply_insert_unchecked(&mut rope, start, &replacement, EditOrigin::User);
+        let event = self.batch(&rope, vec![removed, inserted]);
+        drop(rope);
+
+        self.notify_observers(event).await;
+        true
+    }
+
+    // Inserts 'text' at char column 'col' of 'line' and returns the position just after it.
+    // If 'col' lies past the end of the line (virtual space), the line is padded with spaces up to
+    // 'col' when 'pad' is set, otherwise the text goes at the end of the line. A 'line' past the
+    // end of the buffer means the last line. Fails, changing nothing, if the insertion is inside
+    // a protected region or is vetoed.
+    pub async fn insert_at_line_col(&self, line: usize, col: usize, text: &str, pad: bool) -> Result<TextPosition, EditError> {
+        let mut rope = self.content.lock();
+        let line = line.min(rope.len_lines() - 1);
+        let line_start = rope.line_to_byte(line);
+        let line_text = line_text(&rope, line);
+        let content = split_line_ending(&line_text).0;
+
+        let (byte_idx, padding) = match content.char_indices().nth(col) {
+            Some((offset, _)) => (line_start + offset, 0),
+            None => (line_start + content.len(), if pad { col - content.chars().count() } else { 0 }),
+        };
+        let inserted = format!("{}{text}", " ".repeat(padding));
+        let event = self.apply_insert(&mut rope, byte_idx, &inserted, EditOrigin::User)?;
+        drop(rope);
+
+        if let Some(event) = event {
+            self.notify_observers(event).await;
+        }
+        Ok(TextPosition { byte_idx: byte_idx + inserted.len() })
+    }
+
+    // Comments out the lines in 'lines' with the language's line comment token, or uncomments
+    // them if every non-blank line already starts with it. The token is inserted at the smallest
+    // indentation of the block, followed by a space. Returns false if nothing changed (no
+    // line comment token, only blank lines, or one of the edits was rejected: then none is
+    // applied). Observers are sent one event: a 'Batch' when several lines change.
+    pub async fn toggle_line_comment(&self, lines: Range<usize>, syntax: &LangSyntax) -> bool {
+        let Some(token) = syntax.line_comment.as_deref() else {
+            return false;
+        };
+        let mut rope = self.content.lock();
+        let end_line = lines.end.min(rope.len_lines());
+
+        // (line start byte, line content) for each non-blank line.
+        let targets: Vec<(usize, String)> = (lines.start..end_line)
+            .map(|line| (rope.line_to_byte(line), split_line_ending(&line_text(&rope, line)).0.to_string()))
+            .filter(|(_, content)| !content.trim().is_empty())
+            .collect();
+        if targets.is_empty() {
+            return false;
+        }
+
+        let indent_len = |content: &str| content.len() - content.trim_start().len();
+        let uncomment = targets.iter().all(|(_, content)| content.trim_start().starts_with(token));
+        let min_indent = targets.iter().map(|(_, content)| indent_len(content)).min().unwrap_or(0);
+
+        // (range, text) for each line, all checked before any is applied.
+        let inserted = format!("{token} ");
+        let edits: Vec<(TextRange, &str)> = targets.iter()
+            .map(|(line_start, content)| if uncomment {
+                let token_start = line_start + indent_len(content);
+                let after_token = &content[indent_len(content) + token.len()..];
+                let len = token.len() + usize::from(after_token.starts_with(' '));
+                (TextRange::new(token_start, token_start + len), "")
+            } else {
+                (TextRange::new(line_start + min_indent, line_start + min_indent), inserted.as_str())
+            })
+            .collect();
+        if edits.iter().any(|(range, text)| self.check_edit(&rope, *range, text, EditOrigin::User).is_err()) {
+            return false;
+        }
+
+        // Back to front, so the start offsets computed above stay valid.
+        let mut events: Vec<TextBufferChangedEvent> = Vec::with_capacity(edits.len());
+        for (range, text) in edits.iter().rev() {
+            if text.is_empty() {
+                events.push(self.apply_remove_unchecked(&mut rope, range.start, range.len(), EditOrigin::User));
+            } else {
+                events.push(self.apply_insert_unchecked(&mut rope, range.start, text, EditOrigin::User));
+            }
+        }
+        let event = if events.len() == 1 { events.remove(0) } else { self.batch(&rope, events) };
+        drop(rope);
+
+        self.notify_observers(event).await;
+        true
+    }
+
+    // Inserts 'prefix' at the start of every line in 'lines' ("indent selection", "quote with
+    // '> '"), as one edit: a single event, or a 'Batch' for several lines. Blank lines are left
+    // alone when 'skip_blank' is set. Returns the number of bytes inserted, so cursors after the
+    // block can be shifted; 0 if no line was changed or the edit was rejected (vetoed, or
+    // touching a protected region).
+    pub async fn prefix_lines(&self, lines: Range<usize>, prefix: &str, skip_blank: bool) -> usize {
+        self.affix_lines(lines, prefix, skip_blank, false).await
+    }
+
+    // Like 'prefix_lines', but appends 'suffix' to the end of each line's content, before its
+    // line break.
+    pub async fn suffix_lines(&self, lines: Range<usize>, suffix: &str, skip_blank: bool) -> usize {
+        self.affix_lines(lines, suffix, skip_blank, true).await
+    }
+
+    async fn affix_lines(&self, lines: Range<usize>, text: &str, skip_blank: bool, at_end: bool) -> usize {
+        if text.is_empty() {
+            return 0;
+        }
+        let (event, inserted) = {
+            let mut rope = self.content.lock();
+            let end_line = lines.end.min(rope.len_lines());
+            let edits: Vec<(usize, usize, String)> = (lines.start..end_line)
+                .filter_map(|line| {
+                    let line_start = rope.line_to_byte(line);
+                    let content_len = line_content_len(&rope, line);
+                    if skip_blank && rope.line(line).byte_slice(..content_len).chars().all(char::is_whitespace) {
+                        return None;
+                    }
+                    let at = if at_end { line_start + content_len } else { line_start };
+                    Some((at, at, text.to_string()))
+                })
+                .collect();
+            let inserted = edits.len() * text.len();
+            let Ok((mut events, _)) = self.apply_edits_locked(&mut rope, edits) else {
+                return 0;
+            };
+            match events.len() {
+                0 => return 0,
+                1 => (events.remove(0), inserted),
+                _ => (self.batch(&rope, events), inserted),
+            }
+        };
+        self.notify_observers(event).await;
+        inserted
+    }
+
+    // Removes spaces and tabs at the end of every line, as one 'Batch'. Returns false if there
+    // was nothing to trim or it was rejected (vetoed, or touching a protected region).
+    pub async fn trim_trailing_whitespace(&self) -> bool {
+        let event = {
+            let mut rope = self.content.lock();
+            let edits: Vec<(usize, usize, String)> = (0..rope.len_lines())
+                .filter_map(|line| {
+                    let text = line_text(&rope, line);
+                    let content = split_line_ending(&text).0;
+                    let trimmed = content.trim_end_matches([' ', '\t']);
+                    let start = rope.line_to_byte(line);
+                    (trimmed.len() < content.len()).then(|| (start + trimmed.len(), start + content.len(), String::new()))
+                })
+                .collect();
+            if edits.is_empty() {
+                return false;
+            }
+            // The trimmed ranges lie within their lines, so this only fails if they are vetoed or
+            // touch a protected region.
+            let Ok((events, _)) = self.apply_edits_locked(&mut rope, edits) else {
+                return false;
+            };
+            let mut event = self.batch(&rope, events);
+            // A trimmed blank line would otherwise be classified as an indentation change.
+            event.set_kind(EditKind::WhitespaceOnly);
+            event
+        };
+
+        self.notify_observers(event).await;
+        true
+    }
+
+    // Appends 'ending' unless the text is empty or already ends with a line break. Returns false
+    // if nothing was added, including when the insertion was rejected (vetoed, or inside a
+    // protected region).
+    pub async fn ensure_final_newline(&self, ending: LineEnding) -> bool {
+        let event = {
+            let mut rope = self.content.lock();
+            let len = rope.len_bytes();
+            let last_line = rope.len_lines() - 1;
+            // The phantom last line is empty exactly when the text ends with a break.
+            if len == 0 || rope.line(last_line).len_bytes() == 0 {
+                return false;
+            }
+            let Ok(Some(event)) = self.apply_insert(&mut rope, len, ending.as_str(), EditOrigin::User) else {
+                return false;
+            };
+            event
+        };
+
+        self.notify_observers(event).await;
+        true
+    }
+}
@@ -0,0 +1,90 @@
+// Log Buffer
+// A buffer for tool output and log tailing: text is only ever appended as whole lines, and the
+// oldest lines are dropped once a line cap is reached. Appends are queued and applied together by
+// 'flush' (e.g. once per frame), so a burst of output costs one edit and one compact 'Appended'
+// event instead of one 'Inserted' copy of the text per line.
+use std::sync::Arc;
+
+use parking_lot::Mutex;
+use ropey::Rope;
+
+use crate::rope_utils::user_line_count;
+use crate::{EditOrigin, TextBuffer, TextBufferChangedEvent};
+
+
+// LogBuffer Struct
+// The underlying buffer is shared for reading and observing; edit it only through the LogBuffer,
+// which relies on every line ending with a break. The log owns its text, so appending and
+// trimming ignore pre-edit observers and protected regions: a log can't be made read-only to
+// itself, and a vetoed trim would let it grow past its cap.
+pub struct LogBuffer {
+    buffer: Arc<TextBuffer>,
+    max_lines: usize,
+    // Appended lines not yet flushed, each with its "\n".
+    pending: Mutex<String>,
+}
+
+impl LogBuffer {
+    // Creates an empty log keeping at most 'max_lines' lines (at least one).
+    pub fn new(max_lines: usize) -> Self {
+        Self { buffer: Arc::new(TextBuffer::new("")), max_lines: max_lines.max(1), pending: Mutex::new(String::new()) }
+    }
+
+    // Returns the buffer holding the flushed lines.
+    pub fn buffer(&self) -> &Arc<TextBuffer> {
+        &self.buffer
+    }
+
+    // Queues 'line' (without its break) for the next 'flush'. Cheap: nothing is locked but the queue.
+    pub fn append_line(&self, line: &str) {
+        let mut pending = self.pending.lock();
+        pending.push_str(line);
+        pending.push('\n');
+    }
+
+    // Appends all queued lines as one 'Appended' event, then, if the log is over its cap, removes
+    // the oldest lines as one 'Removed' event. Returns false if nothing was queued.
+    pub async fn flush(&self) -> bool {
+        let text = std::mem::take(&mut *self.pending.lock());
+        if text.is_empty() {
+            return false;
+        }
+        let (appended, trimmed) = {
+            let mut rope = self.buffer.content.lock();
+            let appended = self.buffer.apply_append(&mut rope, &text);
+            let excess = user_line_count(&rope).saturating_sub(self.max_lines);
+            let trimmed = (excess > 0).then(|| {
+                let len = rope.line_to_byte(excess);
+                self.buffer.apply_remove_unchecked(&mut rope, 0, len, EditOrigin::User)
+            });
+            (appended, trimmed)
+        };
+        self.buffer.notify_observers(appended).await;
+        if let Some(trimmed) = trimmed {
+            self.buffer.notify_observers(trimmed).await;
+        }
+        true
+    }
+}
+
+
+impl TextBuffer {
+    // Appends 'text' (whole lines) at the end and builds the matching 'Appended' event.
+    fn apply_append(&self, rope: &mut Rope, text: &str) -> TextBufferChangedEvent {
+        let start_byte_idx = rope.len_bytes();
+        let start_line = rope.byte_to_line(start_byte_idx);
+        let len_lines_before = rope.len_lines();
+        rope.insert(rope.len_chars(), text);
+        let event = TextBufferChangedEvent::Appended {
+            start_byte_idx,
+            len_bytes: text.len(),
+            line_count: rope.len_lines() - len_lines_before,
+            dirty_line_range: start_line..rope.len_lines(),
+            version: self.bump_version(),
+        };
+        self.changes.lock().record(&event);
+        self.count_inserted_words(rope, start_byte_idx, rope.len_bytes());
+        self.anchors.lock().shift_for_insert(start_byte_idx, text.len());
+        event
+    }
+}
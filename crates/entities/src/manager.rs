@@ -0,0 +1,289 @@
+// Document Manager
+// The set of documents open in the editor, and commands that act on all of them at once.
+use std::collections::HashMap;
+use std::fmt;
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::Poll;
+use std::time::Duration;
+
+use parking_lot::Mutex;
+use tokio::task::JoinHandle;
+
+use crate::store::{DocumentStore, LocalFsStore};
+use crate::{Document, EditError};
+
+
+// Workspace Edit Error
+// Why 'apply_workspace_edit' failed, with the path of the file at fault.
+#[derive(Debug)]
+pub enum WorkspaceEditError {
+    // The file isn't open and couldn't be read.
+    Io { path: String, error: io::Error },
+    // The file's edits were rejected by its buffer.
+    Edit { path: String, error: EditError },
+}
+
+impl WorkspaceEditError {
+    // Returns the path of the file that failed.
+    pub fn path(&self) -> &str {
+        match self {
+            WorkspaceEditError::Io { path, .. } | WorkspaceEditError::Edit { path, .. } => path,
+        }
+    }
+}
+
+impl fmt::Display for WorkspaceEditError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WorkspaceEditError::Io { path, error } => write!(f, "{path}: {error}"),
+            WorkspaceEditError::Edit { path, error } => write!(f, "{path}: {error}"),
+        }
+    }
+}
+
+impl std::error::Error for WorkspaceEditError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            WorkspaceEditError::Io { error, .. } => Some(error),
+            WorkspaceEditError::Edit { error, .. } => Some(error),
+        }
+    }
+}
+
+
+// Doc Order
+// Orders 'documents_sorted' can list the open documents in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DocOrder {
+    // By file path; untitled documents last.
+    ByPath,
+    // Most recently focused (see 'touch') first; documents never focused last.
+    ByRecency,
+    // Documents with unsaved changes first.
+    ByDirty,
+}
+
+
+// Document Manager Struct
+// Documents are shared, so a command can keep working on one after it was closed.
+#[derive(Default)]
+pub struct DocumentManager {
+    documents: Arc<Mutex<Vec<Arc<Document>>>>,
+    // The running auto-save task (see 'enable_autosave').
+    autosave: Mutex<Option<JoinHandle<()>>>,
+    // When each path was last focused, as a tick that grows with every 'touch'.
+    focused: Mutex<HashMap<String, u64>>,
+    focus_tick: Mutex<u64>,
+}
+
+impl DocumentManager {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Adds an open document and returns the shared handle to it.
+    pub fn add(&self, document: Document) -> Arc<Document> {
+        let document = Arc::new(document);
+        self.documents.lock().push(Arc::clone(&document));
+        document
+    }
+
+    // Removes a document. Returns false if it wasn't managed here.
+    pub fn remove(&self, document: &Arc<Document>) -> bool {
+        let mut documents = self.documents.lock();
+        let Some(idx) = documents.iter().position(|entry| Arc::ptr_eq(entry, document)) else {
+            return false;
+        };
+        documents.remove(idx);
+        drop(documents);
+        if let Some(path) = &document.file_path {
+            self.focused.lock().remove(path);
+        }
+        true
+    }
+
+    // Returns the open documents in the order they were added.
+    pub fn documents(&self) -> Vec<Arc<Document>> {
+        self.documents.lock().clone()
+    }
+
+    // Returns the open documents in 'order'. Documents that compare equal stay in the order they
+    // were added.
+    pub fn documents_sorted(&self, order: DocOrder) -> Vec<Arc<Document>> {
+        let mut documents = self.documents();
+        match order {
+            DocOrder::ByPath => documents.sort_by(|a, b| match (&a.file_path, &b.file_path) {
+                (Some(a), Some(b)) => a.cmp(b),
+                (a, b) => b.is_some().cmp(&a.is_some()),
+            }),
+            DocOrder::ByRecency => {
+                let focused = self.focused.lock();
+                documents.sort_by_key(|document| {
+                    let tick = document.file_path.as_ref().and_then(|path| focused.get(path));
+                    std::cmp::Reverse(tick.copied())
+                });
+            }
+            DocOrder::ByDirty => documents.sort_by_key(|document| !document.is_dirty()),
+        }
+        documents
+    }
+
+    // Records that the document at 'path' was just focused, for 'DocOrder::ByRecency'.
+    pub fn touch(&self, path: &str) {
+        let mut tick = self.focus_tick.lock();
+        *tick += 1;
+        self.focused.lock().insert(path.to_string(), *tick);
+    }
+
+    // Saves every dirty document to the local filesystem (see 'save_all_with').
+    pub async fn save_all(&self) -> Vec<(String, io::Result<()>)> {
+        self.save_all_with(&LocalFsStore).await
+    }
+
+    // Saves every dirty document to 'store' concurrently and returns each one's path with its
+    // result, in document order. Clean documents are skipped. Untitled documents are reported as
+    // "untitled" with the error 'save_with' gives for a missing path.
+    pub async fn save_all_with<S: DocumentStore>(&self, store: &S) -> Vec<(String, io::Result<()>)> {
+        let dirty: Vec<_> = self.documents().into_iter().filter(|document| document.is_dirty()).collect();
+        let results = join_all(dirty.iter().map(|document| document.save_with(store)).collect()).await;
+        dirty.iter()
+            .map(|document| document.file_path.clone().unwrap_or_else(|| "untitled".to_string()))
+            .zip(results)
+            .collect()
+    }
+
+    // Returns the open document whose path is 'path', if any.
+    pub fn find(&self, path: &str) -> Option<Arc<Document>> {
+        self.documents.lock().iter().find(|document| document.file_path.as_deref() == Some(path)).cloned()
+    }
+
+    // Applies an LSP workspace edit, opening files that aren't open from the local filesystem
+    // (see 'apply_workspace_edit_with').
+    pub async fn apply_workspace_edit(&self, edits: HashMap<String, Vec<(usize, usize, String)>>) -> Result<(), WorkspaceEditError> {
+        self.apply_workspace_edit_with(&LocalFsStore, edits).await
+    }
+
+    // Applies each file's edits (as for 'TextBuffer::apply_edits') to the document open at that
+    // path, or to one opened from 'store' (as plaintext) and added once everything succeeded,
+    // e.g. for a rename across files. All or nothing: if some file can't be read or its edits
+    // are invalid, the files already edited are restored to their previous text and dirty
+    // state, and the error names the failing file. Files are processed in path order. Edited
+    // documents are marked dirty; nothing is saved. Edits made by other tasks in the meantime
+    // are not guarded against, and a rollback is reported to observers like any other edit.
+    pub async fn apply_workspace_edit_with<S: DocumentStore>(
+        &self,
+        store: &S,
+        edits: HashMap<String, Vec<(usize, usize, String)>>,
+    ) -> Result<(), WorkspaceEditError> {
+        let mut files: Vec<_> = edits.into_iter().collect();
+        files.sort_by(|a, b| a.0.cmp(&b.0));
+
+        // The documents edited so far, whether they were opened here, and how to restore them.
+        let mut applied: Vec<(Arc<Document>, bool, String, bool)> = Vec::new();
+        let mut failure = None;
+        for (path, file_edits) in files {
+            let (document, opened) = match self.find(&path) {
+                Some(document) => (document, false),
+                None => match Document::open_with(store, &path, "plaintext".to_string()).await {
+                    Ok(document) => (Arc::new(document), true),
+                    Err(error) => {
+                        failure = Some(WorkspaceEditError::Io { path, error });
+                        break;
+                    }
+                },
+            };
+            let (old_text, was_dirty) = (document.text_buffer.get_text(), document.is_dirty());
+            if let Err(error) = document.text_buffer.apply_edits(file_edits).await {
+                failure = Some(WorkspaceEditError::Edit { path, error });
+                break;
+            }
+            document.set_dirty(true);
+            applied.push((document, opened, old_text, was_dirty));
+        }
+
+        if let Some(error) = failure {
+            for (document, opened, old_text, was_dirty) in applied.into_iter().rev() {
+                if opened {
+                    continue;
+                }
+                let buffer = &document.text_buffer;
+                buffer.set_contents_diffed(&old_text).await;
+                // A pre-edit observer may veto the restore; it must happen regardless.
+                if buffer.get_text() != old_text {
+                    buffer.reload(&old_text).await;
+                }
+                document.set_dirty(was_dirty);
+            }
+            return Err(error);
+        }
+        let mut documents = self.documents.lock();
+        documents.extend(applied.into_iter().filter(|(_, opened, _, _)| *opened).map(|(document, ..)| document));
+        Ok(())
+    }
+
+    // Auto-saves to the local filesystem every 'interval' (see 'enable_autosave_with').
+    pub fn enable_autosave(&self, interval: Duration) {
+        self.enable_autosave_with(Arc::new(LocalFsStore), interval);
+    }
+
+    // Starts a task that saves, every 'interval', the dirty documents that have a path and
+    // aren't read-only, to 'store'. Documents added later are picked up. Each save goes through
+    // 'save_checked_with', so a document whose file changed on disk is left alone (and stays
+    // dirty) until the conflict is resolved; failures are otherwise ignored, as the next
+    // attempt comes soon. Successful saves emit 'DocumentEvent::Saved' like any save.
+    // Replaces a task already running. Must be called from within a Tokio runtime.
+    pub fn enable_autosave_with<S: DocumentStore + 'static>(&self, store: Arc<S>, interval: Duration) {
+        let documents = Arc::clone(&self.documents);
+        let task = tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(interval).await;
+                let due: Vec<_> = documents.lock().iter()
+                    .filter(|document| document.is_dirty() && document.file_path.is_some() && !document.is_read_only())
+                    .cloned()
+                    .collect();
+                join_all(due.iter().map(|document| document.save_checked_with(&*store)).collect()).await;
+            }
+        });
+        if let Some(previous) = self.autosave.lock().replace(task) {
+            previous.abort();
+        }
+    }
+
+    // Stops auto-saving. A save already in progress may still finish.
+    pub fn disable_autosave(&self) {
+        if let Some(task) = self.autosave.lock().take() {
+            task.abort();
+        }
+    }
+}
+
+impl Drop for DocumentManager {
+    fn drop(&mut self) {
+        self.disable_autosave();
+    }
+}
+
+// Polls all 'futures' until each has finished and returns their outputs in order.
+async fn join_all<F: Future>(futures: Vec<F>) -> Vec<F::Output> {
+    let mut futures: Vec<Pin<Box<F>>> = futures.into_iter().map(Box::pin).collect();
+    let mut outputs: Vec<Option<F::Output>> = futures.iter().map(|_| None).collect();
+    std::future::poll_fn(move |cx| {
+        let mut pending = false;
+        for (future, output) in futures.iter_mut().zip(outputs.iter_mut()) {
+            if output.is_none() {
+                match future.as_mut().poll(cx) {
+                    Poll::Ready(value) => *output = Some(value),
+                    Poll::Pending => pending = true,
+                }
+            }
+        }
+        if pending {
+            return Poll::Pending;
+        }
+        Poll::Ready(outputs.iter_mut().map(|output| output.take().expect("future finished")).collect())
+    })
+    .await
+}
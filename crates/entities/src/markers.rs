@@ -0,0 +1,112 @@
+// Markers
+// Values attached to ranges of the text (diagnostics, bookmarks) that follow it through edits.
+// Each marker is backed by an anchored range, so navigation commands ("next error", "next
+// bookmark") can walk them in their current document order.
+use std::sync::Arc;
+
+use crate::{AnchorBias, AnchoredRange, TextBuffer, TextRange};
+
+
+// Diagnostic Severity
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum DiagnosticSeverity {
+    Error,
+    Warning,
+    Information,
+    Hint,
+}
+
+
+// Diagnostic Struct
+// A message from a compiler or linter about a range of the text.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub severity: DiagnosticSeverity,
+    pub message: String,
+}
+
+
+// Bookmark Struct
+// A user-placed mark, usually an empty range at a cursor position.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Bookmark {
+    pub label: Option<String>,
+}
+
+
+// Markers Struct
+// A set of values keyed by range, tracking one TextBuffer.
+// A range grows with text typed at its edges; an empty range is a single point that stays in front
+// of text typed at it. Text removed around a marker collapses its range rather than dropping it.
+pub struct Markers<T> {
+    buffer: Arc<TextBuffer>,
+    entries: Vec<(AnchoredRange, T)>,
+}
+
+pub type Diagnostics = Markers<Diagnostic>;
+pub type Bookmarks = Markers<Bookmark>;
+
+impl<T> Markers<T> {
+    // Creates an empty marker set for 'buffer'.
+    pub fn new(buffer: Arc<TextBuffer>) -> Self {
+        Self { buffer, entries: Vec::new() }
+    }
+
+    // Marks 'range' with 'value'. Returns false if the range goes past the end of the buffer.
+    pub fn add(&mut self, range: TextRange, value: T) -> bool {
+        let rope = self.buffer.content.lock();
+        if range.end > rope.len_bytes() {
+            return false;
+        }
+        let mut anchors = self.buffer.anchors.lock();
+        let anchored = if range.is_empty() {
+            let point = anchors.create(range.start, AnchorBias::Left);
+            AnchoredRange { start: point, end: point }
+        } else {
+            anchors.create_range(range)
+        };
+        self.entries.push((anchored, value));
+        true
+    }
+
+    // Returns the number of markers.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    // Returns true if there are no markers.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    // Returns every marker with its current range, ordered by start (then end). All ranges are
+    // resolved under one lock, so they agree with each other even while other tasks edit.
+    pub fn iter_sorted(&self) -> impl Iterator<Item = (TextRange, &T)> {
+        let mut resolved: Vec<(TextRange, &T)> = {
+            let _rope = self.buffer.content.lock();
+            let anchors = self.buffer.anchors.lock();
+            self.entries.iter()
+                .filter_map(|(range, value)| {
+                    Some((TextRange::new(anchors.position(range.start)?, anchors.position(range.end)?), value))
+                })
+                .collect()
+        };
+        resolved.sort_by_key(|(range, _)| (range.start, range.end));
+        resolved.into_iter()
+    }
+
+    // Removes all markers.
+    pub fn clear(&mut self) {
+        let mut anchors = self.buffer.anchors.lock();
+        for (range, _) in self.entries.drain(..) {
+            anchors.remove(range.start);
+            anchors.remove(range.end);
+        }
+    }
+}
+
+impl<T> Drop for Markers<T> {
+    fn drop(&mut self) {
+        self.clear();
+    }
+}
@@ -0,0 +1,59 @@
+// Document Metrics
+// Size and line counts of a document, cached so lists of open files can show them on every UI
+// tick without locking each buffer's content. A sync observer re-reads the counts after each
+// change, under the cache's own lock, so concurrent edits can't leave a stale value behind.
+use std::sync::{Arc, Weak};
+
+use parking_lot::Mutex;
+
+use crate::{Document, ISyncTextBufferObserver, TextBuffer, TextBufferChangedEvent};
+
+
+// Doc Metrics Struct
+// 'lines' counts as 'TextBuffer::line_count' does.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DocMetrics {
+    pub bytes: usize,
+    pub chars: usize,
+    pub lines: usize,
+}
+
+impl DocMetrics {
+    fn of(buffer: &TextBuffer) -> Self {
+        let stats = buffer.stats();
+        DocMetrics { bytes: stats.bytes, chars: stats.chars, lines: stats.lines }
+    }
+}
+
+
+pub(crate) struct MetricsWatcher {
+    // Weak, as the buffer owns this observer.
+    buffer: Weak<TextBuffer>,
+    metrics: Arc<Mutex<DocMetrics>>,
+}
+
+impl MetricsWatcher {
+    // Starts keeping 'metrics' up to date with 'buffer', filling it in right away.
+    pub(crate) fn watch(buffer: &Arc<TextBuffer>, metrics: Arc<Mutex<DocMetrics>>) {
+        *metrics.lock() = DocMetrics::of(buffer);
+        buffer.add_sync_observer(Arc::new(MetricsWatcher { buffer: Arc::downgrade(buffer), metrics }));
+    }
+}
+
+impl ISyncTextBufferObserver for MetricsWatcher {
+    fn on_buffer_changed(&self, _event: &TextBufferChangedEvent) {
+        let Some(buffer) = self.buffer.upgrade() else {
+            return;
+        };
+        let mut metrics = self.metrics.lock();
+        *metrics = DocMetrics::of(&buffer);
+    }
+}
+
+
+impl Document {
+    // Returns the cached size and line counts of the text. Never locks the buffer's content.
+    pub fn metrics(&self) -> DocMetrics {
+        *self.metrics.lock()
+    }
+}
@@ -0,0 +1,46 @@
+// Unicode Normalization
+// Optional normalization of inserted text, so text pasted in decomposed form (NFD, as some macOS
+// sources produce) compares and searches equal to the precomposed text typed elsewhere.
+use std::borrow::Cow;
+
+use unicode_normalization::{is_nfc, UnicodeNormalization};
+
+use crate::TextBuffer;
+
+
+// Normalization
+// The form 'insert' and 'replace' convert their text to. 'None', the default, inserts text as is.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Normalization {
+    #[default]
+    None,
+    Nfc,
+}
+
+impl Normalization {
+    // Returns 'text' in this form, borrowing it when it already is.
+    pub(crate) fn apply(self, text: &str) -> Cow<'_, str> {
+        match self {
+            Normalization::Nfc if !is_nfc(text) => Cow::Owned(text.nfc().collect()),
+            _ => Cow::Borrowed(text),
+        }
+    }
+}
+
+
+impl TextBuffer {
+    // Sets the form user insertions ('insert', 'replace') are normalized to before they are
+    // applied. The text is normalized on its own: a combining mark inserted right after a base
+    // char already in the buffer is not composed with it. Text already in the buffer and
+    // undo/redo replays are left alone, so undo restores exactly what was there.
+    // With normalization enabled the inserted text can be shorter than the input: events, the
+    // returned positions and anchors all reflect the normalized bytes, not the input length.
+    pub fn set_normalization(&self, normalization: Normalization) {
+        *self.normalization.lock() = normalization;
+    }
+
+    // Returns the current normalization setting.
+    pub fn normalization(&self) -> Normalization {
+        *self.normalization.lock()
+    }
+}
@@ -0,0 +1,124 @@
+// Auto-Pairing
+// Bracket and quote pairing decisions for typing: "(" inserts "()" with the cursor in between,
+// and typing ")" right before an existing ")" steps over it instead of inserting another.
+use ropey::Rope;
+
+use crate::edits::check_range;
+use crate::rope_utils::{char_at, char_before, line_prefix};
+use crate::{EditError, EditOrigin, LangSyntax, TextBuffer, TextPosition, TextRange};
+
+
+// Pairs handled by auto-pairing, as (opening, closing).
+const PAIRS: [(char, char); 6] = [('(', ')'), ('[', ']'), ('{', '}'), ('"', '"'), ('\'', '\''), ('`', '`')];
+
+
+// Returns the closing char for an opening char, if it is one we pair.
+fn closing_for(opening: char) -> Option<char> {
+    PAIRS.iter().find(|(open, _)| *open == opening).map(|(_, close)| *close)
+}
+
+fn is_quote(c: char) -> bool {
+    matches!(c, '"' | '\'' | '`')
+}
+
+fn should_auto_close_in(rope: &Rope, byte_idx: usize, opening: char) -> bool {
+    if closing_for(opening).is_none() {
+        return false;
+    }
+    // Only pair when the cursor isn't glued to following text ("(|foo" should stay "(foo").
+    let next_ok = match char_at(rope, byte_idx) {
+        None => true,
+        Some(next) => next.is_whitespace() || matches!(next, ')' | ']' | '}' | ',' | ';' | ':' | '.'),
+    };
+    if !next_ok {
+        return false;
+    }
+    // "don't" and 'a'' shouldn't get an extra closing quote.
+    if is_quote(opening)
+        && let Some(prev) = char_before(rope, byte_idx)
+        && (prev.is_alphanumeric() || prev == '_' || prev == opening)
+    {
+        return false;
+    }
+    true
+}
+
+fn should_skip_over_in(rope: &Rope, byte_idx: usize, closing: char) -> bool {
+    let is_closer = PAIRS.iter().any(|(_, close)| *close == closing);
+    is_closer && char_at(rope, byte_idx) == Some(closing)
+}
+
+
+impl TextBuffer {
+    // Returns true if typing 'opening' at 'byte_idx' should also insert its closing counterpart.
+    pub fn should_auto_close(&self, byte_idx: usize, opening: char) -> bool {
+        should_auto_close_in(&self.content.lock(), byte_idx, opening)
+    }
+
+    // Returns true if typing 'closing' at 'byte_idx' should just move past the identical char
+    // already there.
+    pub fn should_skip_over(&self, byte_idx: usize, closing: char) -> bool {
+        should_skip_over_in(&self.content.lock(), byte_idx, closing)
+    }
+
+    // Handles a typed char with auto-pairing and returns the new cursor position.
+    // Skips over an existing closer, inserts a pair for an opener, or inserts the char as typed.
+    // When 'syntax' is given, no pair is inserted inside a string or comment on the current line.
+    // The decision and the edit happen under one lock so they can't disagree. Fails, changing
+    // nothing, if 'position' is invalid, inside a protected region or the insertion is vetoed.
+    pub async fn auto_pair_insert(&self, position: TextPosition, typed: char, syntax: Option<&LangSyntax>) -> Result<TextPosition, EditError> {
+        let byte_idx = position.byte_idx;
+        let mut rope = self.content.lock();
+        check_range(&rope, byte_idx, byte_idx)?;
+
+        if should_skip_over_in(&rope, byte_idx, typed) {
+            return Ok(TextPosition { byte_idx: byte_idx + typed.len_utf8() });
+        }
+
+        let in_string_or_comment = syntax
+            .is_some_and(|syntax| syntax.line_prefix_in_string_or_comment(&line_prefix(&rope, byte_idx)));
+        let mut text = String::from(typed);
+        if !in_string_or_comment && should_auto_close_in(&rope, byte_idx, typed) {
+            // 'should_auto_close_in' only returns true for chars with a closing counterpart.
+            text.extend(closing_for(typed));
+        }
+        let event = self.apply_insert(&mut rope, byte_idx, &text, EditOrigin::User)?;
+        drop(rope);
+
+        if let Some(event) = event {
+            self.notify_observers(event).await;
+        }
+        Ok(TextPosition { byte_idx: byte_idx + typed.len_utf8() })
+    }
+
+    // Wraps 'range' in 'open' and 'close' ("Surround with quotes/brackets") and returns the range
+    // of the wrapped text including both delimiters. 'close' is inserted first, so 'range.start'
+    // is still valid when 'open' goes in. Observers get one 'Batch' holding both inserts.
+    pub async fn surround(&self, range: TextRange, open: &str, close: &str) -> Result<TextRange, EditError> {
+        let normalization = self.normalization();
+        let (open, close) = (normalization.apply(open), normalization.apply(close));
+        let wrapped = TextRange::new(range.start, range.end + open.len() + close.len());
+        let event = {
+            let mut rope = self.content.lock();
+            check_range(&rope, range.start, range.end)?;
+            self.check_edit(&rope, TextRange::new(range.end, range.end), &close, EditOrigin::User)?;
+            self.check_edit(&rope, TextRange::new(range.start, range.start), &open, EditOrigin::User)?;
+            let mut events = Vec::with_capacity(2);
+            if !close.is_empty() {
+                events.push(self.apply_insert_unchecked(&mut rope, range.end, &close, EditOrigin::User));
+            }
+            if !open.is_empty() {
+                events.push(self.apply_insert_unchecked(&mut rope, range.start, &open, EditOrigin::User));
+            }
+            match events.len() {
+                0 => None,
+                1 => events.pop(),
+                _ => Some(self.batch(&rope, events)),
+            }
+        };
+        if let Some(event) = event {
+            self.notify_observers(event).await;
+        }
+        Ok(wrapped)
+    }
+}
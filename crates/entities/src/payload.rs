@@ -0,0 +1,63 @@
+// Event Payload Limit
+// Caps the text an 'Inserted' event carries, so pasting a huge blob reaches observers that work
+// incrementally (indexers, language servers) as a run of bounded events instead of one event
+// holding all of it. The rope is still edited once; only the reporting is split.
+use ropey::Rope;
+
+use crate::{inserted_event, EditOrigin, TextBuffer, TextBufferChangedEvent};
+
+
+impl TextBuffer {
+    // Event Payload Limit
+
+    // Makes 'insert' report text longer than 'max_bytes' as several contiguous 'Inserted' events
+    // of at most 'max_bytes' each (a single char longer than that gets an event of its own),
+    // delivered in order. Each event counts as an edit for 'version', and each describes the
+    // text as if the chunks before it had been inserted on their own, so observers replaying
+    // them end up with the same text. The content changes at once: reading the buffer while the
+    // events are delivered already shows all of it. An insert that 'set_reload_threshold'
+    // reports as 'Reloaded' stays one event. 'None', the default, never splits. Edits grouped
+    // by 'apply_edits' or 'transaction' are not split either.
+    pub fn set_max_event_payload(&self, max_bytes: Option<usize>) {
+        *self.max_event_payload.lock() = max_bytes.map(|max_bytes| max_bytes.max(1));
+    }
+
+    // Returns the limit set by 'set_max_event_payload'.
+    pub fn max_event_payload(&self) -> Option<usize> {
+        *self.max_event_payload.lock()
+    }
+
+    // Like 'apply_insert_unchecked', but returns one event per chunk when the payload limit applies.
+    pub(crate) fn apply_insert_chunked(&self, rope: &mut Rope, byte_idx: usize, text: &str, origin: EditOrigin) -> Vec<TextBufferChangedEvent> {
+        let Some(max_bytes) = self.max_event_payload().filter(|max_bytes| text.len() > *max_bytes) else {
+            return vec![self.apply_insert_unchecked(rope, byte_idx, text, origin)];
+        };
+        if self.exceeds_reload_threshold(text.len(), rope.len_bytes() + text.len()) {
+            return vec![self.apply_insert_unchecked(rope, byte_idx, text, origin)];
+        }
+
+        rope.insert(rope.byte_to_char(byte_idx), text);
+        let mut events = Vec::new();
+        let mut offset = 0;
+        while offset < text.len() {
+            let mut end = (offset + max_bytes).min(text.len());
+            while !text.is_char_boundary(end) {
+                end -= 1;
+            }
+            if end == offset {
+                end = offset + text[offset..].chars().next().map_or(1, char::len_utf8);
+            }
+            let event = inserted_event(rope, byte_idx + offset, &text[offset..end], origin, self.bump_version());
+            self.changes.lock().record(&event);
+            events.push(event);
+            offset = end;
+        }
+        self.count_inserted_words(rope, byte_idx, byte_idx + text.len());
+        {
+            let mut anchors = self.anchors.lock();
+            anchors.shift_for_insert(byte_idx, text.len());
+            self.edit_locations.lock().record(&mut anchors, rope, byte_idx + text.len());
+        }
+        events
+    }
+}
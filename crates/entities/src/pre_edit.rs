@@ -0,0 +1,124 @@
+// Pre-Edit Observers
+// Hooks consulted before an edit is applied, so they can veto it (read-only guards, input
+// validation) or capture the text about to change. They run while the buffer is locked and see
+// exactly the state the edit will apply to.
+use std::sync::Arc;
+
+use ropey::Rope;
+
+use crate::edits::check_range;
+use crate::{EditError, EditOrigin, ObserverId, TextBuffer, TextPosition, TextRange};
+
+
+// Edit Intent
+// An edit about to be applied: 'range' is replaced with 'text'. An insertion has an empty range,
+// a removal an empty text. 'old_text' is the text currently in 'range'.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EditIntent<'a> {
+    pub range: TextRange,
+    pub text: &'a str,
+    pub old_text: &'a str,
+    pub origin: EditOrigin,
+}
+
+
+// Edit Decision
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EditDecision {
+    Allow,
+    Veto,
+}
+
+
+// PreEditObserver Trait
+// Consulted by every edit command ('insert', 'replace', the line, indent and deletion commands,
+// 'BufferWriter', transactions, and so on) before it changes anything, in registration order;
+// the first veto wins. A command made of several edits checks them all before applying any.
+// Only 'reload' and log buffers skip it. It is called with the buffer locked, so it must be
+// quick and must not call back into the buffer.
+pub trait PreEditObserver: Send + Sync {
+    fn before_edit(&self, intent: &EditIntent<'_>) -> EditDecision;
+}
+
+
+impl TextBuffer {
+    // Pre-Edit Observers
+
+    // Adds an observer consulted before edits. Remove it with 'remove_observer'.
+    pub fn add_pre_edit_observer(&self, observer: Arc<dyn PreEditObserver>) -> ObserverId {
+        let id = ObserverId::next();
+        self.pre_edit_observers.lock().push((id, observer));
+        id
+    }
+
+    // Checks whether 'range' of the locked 'rope' may be replaced with 'text': it must not touch
+    // a protected region, and no pre-edit observer may veto it. The range must be valid.
+    pub(crate) fn check_edit(&self, rope: &Rope, range: TextRange, text: &str, origin: EditOrigin) -> Result<(), EditError> {
+        if self.touches_protected_region(range) {
+            return Err(EditError::ProtectedRegion);
+        }
+        let observers: Vec<_> = self.pre_edit_observers.lock().iter().map(|(_, observer)| Arc::clone(observer)).collect();
+        if observers.is_empty() {
+            return Ok(());
+        }
+        let old_text = rope.byte_slice(range.start..range.end).to_string();
+        let intent = EditIntent { range, text, old_text: &old_text, origin };
+        if observers.iter().all(|observer| observer.before_edit(&intent) == EditDecision::Allow) {
+            Ok(())
+        } else {
+            Err(EditError::Vetoed)
+        }
+    }
+
+    // Replaces 'range' with 'text' as one edit: observers get a single event (a 'Batch' of the
+    // removal and the insertion when both are non-empty). Fails if the range is invalid, touches a
+    // protected region or a pre-edit observer vetoes it, leaving the buffer untouched. The text is
+    // normalized first if 'set_normalization' asks for it.
+    pub async fn replace(&self, range: TextRange, text: &str) -> Result<(), EditError> {
+        let text = &*self.normalization().apply(text);
+        let event = {
+            let mut rope = self.content.lock();
+            check_range(&rope, range.start, range.end)?;
+            if range.is_empty() && text.is_empty() {
+                return Ok(());
+            }
+            self.check_edit(&rope, range, text, EditOrigin::User)?;
+            let mut events = Vec::with_capacity(2);
+            if !range.is_empty() {
+                events.push(self.apply_remove_unchecked(&mut rope, range.start, range.len(), EditOrigin::User));
+            }
+            if !text.is_empty() {
+                events.push(self.apply_insert_unchecked(&mut rope, range.start, text, EditOrigin::User));
+            }
+            if events.len() == 1 { events.remove(0) } else { self.batch(&rope, events) }
+        };
+        self.notify_observers(event).await;
+        Ok(())
+    }
+
+    // Returns whether inserting 'text' at 'position' would succeed, running the checks 'replace'
+    // does (bounds, char boundaries, protected regions, pre-edit observers, and so read-only
+    // documents) without changing the buffer or notifying anyone. For graying out a quick fix
+    // that can't be applied. Another edit may of course land before the real one. Pre-edit
+    // observers are asked as for a real edit, so one that captures text sees an edit that may
+    // never happen.
+    pub fn can_insert(&self, position: TextPosition, text: &str) -> Result<(), EditError> {
+        self.can_replace(TextRange::new(position.byte_idx, position.byte_idx), text)
+    }
+
+    // Like 'can_insert', for removing 'len_bytes' at 'position'.
+    pub fn can_remove(&self, position: TextPosition, len_bytes: usize) -> Result<(), EditError> {
+        let end = position.byte_idx.saturating_add(len_bytes);
+        self.can_replace(TextRange { start: position.byte_idx, end }, "")
+    }
+
+    fn can_replace(&self, range: TextRange, text: &str) -> Result<(), EditError> {
+        let text = &*self.normalization().apply(text);
+        let rope = self.content.lock();
+        check_range(&rope, range.start, range.end)?;
+        if range.is_empty() && text.is_empty() {
+            return Ok(());
+        }
+        self.check_edit(&rope, range, text, EditOrigin::User)
+    }
+}
@@ -0,0 +1,77 @@
+// Protected Regions
+// Read-only spans inside an otherwise editable buffer, e.g. a REPL's prompt and earlier output
+// with only the input line editable. A region is a pair of anchors, so it moves with the edits
+// around it. Its edges are the reverse of an 'AnchoredRange': text typed right at either edge
+// ends up outside the region and stays editable.
+use crate::anchor::AnchorSet;
+use crate::{AnchorBias, AnchoredRange, TextBuffer, TextRange};
+
+
+// Region Identifier
+// Returned by 'add_protected_region'; pass it to 'remove_protected_region' to lift the protection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct RegionId(AnchoredRange);
+
+
+impl TextBuffer {
+    // Protected Regions
+
+    // Protects 'range' from edits until the region is removed. Edits that remove or replace any
+    // of its text, or insert strictly inside it, fail with 'EditError::ProtectedRegion' (or
+    // return false, for the commands that report a bool), whichever edit command they come from.
+    pub fn add_protected_region(&self, range: TextRange) -> RegionId {
+        let _rope = self.content.lock(); // Hold the content lock so the range can't go stale
+        let mut anchors = self.anchors.lock();
+        let region = AnchoredRange {
+            start: anchors.create(range.start, AnchorBias::Right),
+            end: anchors.create(range.end, AnchorBias::Left),
+        };
+        self.protected_regions.lock().push(region);
+        RegionId(region)
+    }
+
+    // Lifts the protection of a region. Returns false if it was already removed.
+    pub fn remove_protected_region(&self, id: RegionId) -> bool {
+        let mut anchors = self.anchors.lock();
+        let mut regions = self.protected_regions.lock();
+        let Some(idx) = regions.iter().position(|region| *region == id.0) else {
+            return false;
+        };
+        regions.remove(idx);
+        anchors.remove(id.0.start);
+        anchors.remove(id.0.end);
+        true
+    }
+
+    // Returns the current range of a protected region, or None if it was removed. A region whose
+    // text was replaced as a whole (e.g. by 'reload') may have become empty.
+    pub fn protected_region_range(&self, id: RegionId) -> Option<TextRange> {
+        let anchors = self.anchors.lock();
+        let regions = self.protected_regions.lock();
+        if !regions.contains(&id.0) {
+            return None;
+        }
+        region_range(&anchors, &id.0)
+    }
+
+    // Returns true if replacing 'range' would touch a protected region. Must be called with
+    // 'content' locked.
+    pub(crate) fn touches_protected_region(&self, range: TextRange) -> bool {
+        let anchors = self.anchors.lock();
+        let regions = self.protected_regions.lock();
+        regions.iter().filter_map(|region| region_range(&anchors, region)).any(|region| {
+            if range.is_empty() {
+                region.start < range.start && range.start < region.end
+            } else {
+                range.start < region.end && region.start < range.end
+            }
+        })
+    }
+}
+
+// Resolves a region's anchors. An insertion into an empty region pushes its right-biased start
+// past its end; such a region is empty at its end.
+fn region_range(anchors: &AnchorSet, region: &AnchoredRange) -> Option<TextRange> {
+    let (start, end) = (anchors.position(region.start)?, anchors.position(region.end)?);
+    Some(TextRange { start: start.min(end), end })
+}
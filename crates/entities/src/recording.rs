@@ -0,0 +1,172 @@
+// Event Recording
+// Captures a buffer's change events into a recording that can be saved, loaded and replayed on a
+// fresh buffer, to turn a reported corruption into a deterministic regression test. Attach a
+// 'RecordingObserver' before the session, 'finish' it afterwards, and 'replay_recording' checks
+// that re-applying the events reproduces the recorded final text and version.
+// Recordings are saved as JSON, so a user's file can be read (and trimmed down) by hand.
+use std::fmt;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+
+use crate::{EditError, ISyncTextBufferObserver, ObserverId, TextBuffer, TextBufferChangedEvent, TextRange};
+
+
+// Recorded Event
+// An event in the order it was observed ('seq', from 0) and the version it leads to.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecordedEvent {
+    pub seq: u64,
+    pub version: u64,
+    pub event: TextBufferChangedEvent,
+}
+
+
+// Recording Struct
+// A buffer's text and version before the first recorded event and after the last one, and the
+// events in between.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Recording {
+    pub initial_text: String,
+    pub initial_version: u64,
+    pub events: Vec<RecordedEvent>,
+    pub final_text: String,
+    pub final_version: u64,
+}
+
+
+// Recording Observer
+// A sync observer appending every event to a recording. Attach it while no edit is in flight,
+// so the initial snapshot and the first event line up.
+pub struct RecordingObserver {
+    id: Mutex<Option<ObserverId>>,
+    recording: Mutex<Recording>,
+}
+
+impl RecordingObserver {
+    // Snapshots 'buffer' and starts recording its events.
+    pub fn attach(buffer: &TextBuffer) -> Arc<Self> {
+        let (initial_text, initial_version) = buffer.resync();
+        let observer = Arc::new(Self {
+            id: Mutex::new(None),
+            recording: Mutex::new(Recording { initial_text, initial_version, ..Recording::default() }),
+        });
+        *observer.id.lock() = Some(buffer.add_sync_observer(Arc::clone(&observer) as Arc<dyn ISyncTextBufferObserver>));
+        observer
+    }
+
+    // Returns the number of events recorded so far.
+    pub fn len(&self) -> usize {
+        self.recording.lock().events.len()
+    }
+
+    // Returns true if no event was recorded yet.
+    pub fn is_empty(&self) -> bool {
+        self.recording.lock().events.is_empty()
+    }
+
+    // Stops recording 'buffer' (the buffer it was attached to) and returns the recording, ending
+    // with the buffer's current text and version.
+    pub fn finish(&self, buffer: &TextBuffer) -> Recording {
+        if let Some(id) = self.id.lock().take() {
+            buffer.remove_observer(id);
+        }
+        let mut recording = std::mem::take(&mut *self.recording.lock());
+        (recording.final_text, recording.final_version) = buffer.resync();
+        recording
+    }
+}
+
+impl ISyncTextBufferObserver for RecordingObserver {
+    fn on_buffer_changed(&self, event: &TextBufferChangedEvent) {
+        let mut recording = self.recording.lock();
+        let seq = recording.events.len() as u64;
+        recording.events.push(RecordedEvent { seq, version: event.version(), event: event.clone() });
+    }
+}
+
+
+// Replay Error
+// Why 'replay_recording' failed. 'seq' identifies the event at fault.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReplayError {
+    // The buffer doesn't hold the recording's initial text.
+    InitialTextMismatch,
+    // The event was rejected by the buffer.
+    Edit { seq: u64, error: EditError },
+    // The event carries no text to re-apply ('Reloaded', 'Appended'); record with
+    // 'set_reload_threshold(None)' and without a 'LogBuffer'.
+    NotReplayable { seq: u64 },
+    // The replay ended with a different text, or a different number of edits.
+    FinalStateMismatch,
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReplayError::InitialTextMismatch => write!(f, "the buffer does not hold the recording's initial text"),
+            ReplayError::Edit { seq, error } => write!(f, "event {seq} could not be applied: {error}"),
+            ReplayError::NotReplayable { seq } => write!(f, "event {seq} carries no text to replay"),
+            ReplayError::FinalStateMismatch => write!(f, "the replay did not reproduce the recorded final state"),
+        }
+    }
+}
+
+impl std::error::Error for ReplayError {}
+
+
+// Re-applies the events of 'recording' to 'buffer', which must hold its initial text (usually a
+// fresh 'TextBuffer::new(&recording.initial_text)'), then checks that the text matches the
+// recorded final text and that as many edits were applied as were recorded. Removals are
+// checked against the text they say they removed.
+pub async fn replay_recording(buffer: &TextBuffer, recording: &Recording) -> Result<(), ReplayError> {
+    if buffer.get_text() != recording.initial_text {
+        return Err(ReplayError::InitialTextMismatch);
+    }
+    let start_version = buffer.version();
+    for recorded in &recording.events {
+        for event in recorded.event.events() {
+            replay_event(buffer, event).await.map_err(|error| match error {
+                Some(error) => ReplayError::Edit { seq: recorded.seq, error },
+                None => ReplayError::NotReplayable { seq: recorded.seq },
+            })?;
+        }
+    }
+    let replayed_edits = buffer.version() - start_version;
+    if buffer.get_text() != recording.final_text || replayed_edits != recording.final_version - recording.initial_version {
+        return Err(ReplayError::FinalStateMismatch);
+    }
+    Ok(())
+}
+
+// Applies one non-batch event. Err(None) means it isn't replayable.
+async fn replay_event(buffer: &TextBuffer, event: &TextBufferChangedEvent) -> Result<(), Option<EditError>> {
+    match event {
+        TextBufferChangedEvent::Inserted { start_byte_idx, text, .. } => {
+            buffer.replace(TextRange::new(*start_byte_idx, *start_byte_idx), text).await.map_err(Some)
+        }
+        TextBufferChangedEvent::Removed { start_byte_idx, len_bytes, text, .. } => {
+            let range = TextRange::new(*start_byte_idx, start_byte_idx + len_bytes);
+            if buffer.get_range(range.start, range.end) != **text {
+                return Err(Some(EditError::TextMismatch));
+            }
+            buffer.replace(range, "").await.map_err(Some)
+        }
+        _ => Err(None),
+    }
+}
+
+
+// Writes 'recording' to 'path' as JSON.
+pub fn save_recording(recording: &Recording, path: impl AsRef<Path>) -> io::Result<()> {
+    std::fs::write(path, serde_json::to_string_pretty(recording)?)
+}
+
+// Reads a recording saved by 'save_recording'. Fails with 'InvalidData' if the file isn't one,
+// or 'UnexpectedEof' if it was cut short.
+pub fn load_recording(path: impl AsRef<Path>) -> io::Result<Recording> {
+    Ok(serde_json::from_str(&std::fs::read_to_string(path)?)?)
+}
@@ -0,0 +1,101 @@
+// Registers
+// Copy/cut and named text registers (vim-style registers, a simple clipboard). Keeping them next
+// to the buffer makes every command copy and cut the same way, including linewise copies.
+use std::collections::HashMap;
+use std::ops::Range;
+
+use crate::edits::check_range;
+use crate::line_ending::dominant_line_ending;
+use crate::rope_utils::split_line_ending;
+use crate::{EditError, EditOrigin, LineEnding, TextBuffer, TextRange};
+
+
+// Registers Entity
+// Text registers addressed by char. Every write also updates the unnamed register
+// ('Registers::UNNAMED'), which is what a plain paste reads.
+#[derive(Debug, Clone, Default)]
+pub struct Registers {
+    registers: HashMap<char, String>,
+}
+
+impl Registers {
+    // The default register, as in vim.
+    pub const UNNAMED: char = '"';
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Stores 'text' in register 'name' and in the unnamed register.
+    pub fn set(&mut self, name: char, text: String) {
+        if name != Self::UNNAMED {
+            self.registers.insert(Self::UNNAMED, text.clone());
+        }
+        self.registers.insert(name, text);
+    }
+
+    // Returns the content of register 'name', or None if it was never set.
+    pub fn get(&self, name: char) -> Option<&str> {
+        self.registers.get(&name).map(String::as_str)
+    }
+
+    // Copies 'range' of 'buffer' into register 'name' (the unnamed register for None).
+    pub fn copy_from(&mut self, buffer: &TextBuffer, range: TextRange, name: Option<char>) -> Result<(), EditError> {
+        let text = buffer.copy(range)?;
+        self.set(name.unwrap_or(Self::UNNAMED), text);
+        Ok(())
+    }
+
+    // Cuts 'range' out of 'buffer' into register 'name' (the unnamed register for None).
+    pub async fn cut_from(&mut self, buffer: &TextBuffer, range: TextRange, name: Option<char>) -> Result<(), EditError> {
+        let text = buffer.cut(range).await?;
+        self.set(name.unwrap_or(Self::UNNAMED), text);
+        Ok(())
+    }
+}
+
+
+impl TextBuffer {
+    // Copy And Cut
+
+    // Returns the text of 'range'.
+    pub fn copy(&self, range: TextRange) -> Result<String, EditError> {
+        let rope = self.content.lock();
+        check_range(&rope, range.start, range.end)?;
+        Ok(rope.byte_slice(range.start..range.end).to_string())
+    }
+
+    // Removes 'range' and returns the removed text. Fails, changing nothing, if the range is
+    // invalid, touches a protected region or the removal is vetoed.
+    pub async fn cut(&self, range: TextRange) -> Result<String, EditError> {
+        let mut rope = self.content.lock();
+        check_range(&rope, range.start, range.end)?;
+        if range.is_empty() {
+            return Ok(String::new());
+        }
+        let text = rope.byte_slice(range.start..range.end).to_string();
+        let event = self.apply_remove(&mut rope, range.start, range.len(), EditOrigin::User)?;
+        drop(rope);
+
+        if let Some(event) = event {
+            self.notify_observers(event).await;
+        }
+        Ok(text)
+    }
+
+    // Returns the text of 'lines' for a linewise copy: it always ends with a line break, so
+    // pasting it at a line start inserts whole lines. A last line without a terminator gets the
+    // buffer's dominant line ending ("\n" if it has none). Lines past the end are ignored.
+    pub fn copy_lines(&self, lines: Range<usize>) -> String {
+        let rope = self.content.lock();
+        let end = lines.end.min(rope.len_lines());
+        if lines.start >= end {
+            return String::new();
+        }
+        let mut text = rope.byte_slice(rope.line_to_byte(lines.start)..rope.line_to_byte(end)).to_string();
+        if split_line_ending(&text).1.is_empty() {
+            text.push_str(dominant_line_ending(&rope).unwrap_or(LineEnding::Lf).as_str());
+        }
+        text
+    }
+}
@@ -0,0 +1,100 @@
+// Rope Helpers
+// Small read-only queries on an already-locked rope, shared by the feature modules so they can
+// combine several lookups under a single lock.
+use ropey::Rope;
+use unicode_segmentation::UnicodeSegmentation;
+
+
+// Returns the char starting at 'byte_idx', or None at (or past) the end of the buffer.
+pub(crate) fn char_at(rope: &Rope, byte_idx: usize) -> Option<char> {
+    if byte_idx >= rope.len_bytes() {
+        return None;
+    }
+    rope.get_char(rope.byte_to_char(byte_idx))
+}
+
+// Returns the char ending at 'byte_idx', or None at the start of the buffer.
+pub(crate) fn char_before(rope: &Rope, byte_idx: usize) -> Option<char> {
+    if byte_idx == 0 || byte_idx > rope.len_bytes() {
+        return None;
+    }
+    rope.get_char(rope.byte_to_char(byte_idx) - 1)
+}
+
+// Returns the text of the line containing 'byte_idx', from the line start up to 'byte_idx'.
+pub(crate) fn line_prefix(rope: &Rope, byte_idx: usize) -> String {
+    let line_start = rope.line_to_byte(rope.byte_to_line(byte_idx));
+    rope.byte_slice(line_start..byte_idx).to_string()
+}
+
+// Returns the number of lines as a user counts them (see 'TextBuffer::line_count').
+pub(crate) fn user_line_count(rope: &Rope) -> usize {
+    let len_lines = rope.len_lines();
+    if len_lines > 1 && rope.line(len_lines - 1).len_bytes() == 0 {
+        len_lines - 1
+    } else {
+        len_lines
+    }
+}
+
+// Returns the '(line, column)' of 'byte_idx', the column counted in chars from the line start.
+pub(crate) fn line_col(rope: &Rope, byte_idx: usize) -> (usize, usize) {
+    let line = rope.byte_to_line(byte_idx);
+    (line, rope.byte_to_char(byte_idx) - rope.line_to_char(line))
+}
+
+// Returns the byte length of 'line' without its line break, reading only its last two chars.
+// The line must exist.
+pub(crate) fn line_content_len(rope: &Rope, line: usize) -> usize {
+    let slice = rope.line(line);
+    let len_chars = slice.len_chars();
+    let last = (len_chars > 0).then(|| slice.char(len_chars - 1));
+    let ending_len = match last {
+        Some('\n') if len_chars > 1 && slice.char(len_chars - 2) == '\r' => 2,
+        Some(c @ ('\n' | '\r' | '\u{0B}' | '\u{0C}' | '\u{85}' | '\u{2028}' | '\u{2029}')) => c.len_utf8(),
+        _ => 0,
+    };
+    slice.len_bytes() - ending_len
+}
+
+// Splits a line's text into its content and its line terminator (empty on the last line).
+// Recognizes the same breaks as ropey: "\r\n", "\n", "\r" and the Unicode separators.
+pub(crate) fn split_line_ending(line: &str) -> (&str, &str) {
+    if let Some(content) = line.strip_suffix("\r\n") {
+        return (content, &line[content.len()..]);
+    }
+    match line.chars().next_back() {
+        Some(c @ ('\n' | '\r' | '\u{0B}' | '\u{0C}' | '\u{85}' | '\u{2028}' | '\u{2029}')) => {
+            line.split_at(line.len() - c.len_utf8())
+        }
+        _ => (line, ""),
+    }
+}
+
+// Returns the byte length of the grapheme cluster ending at 'byte_idx' (0 at the start).
+// Clusters never span a line break except "\r\n", which is a cluster of its own, so only the
+// current line needs to be segmented.
+pub(crate) fn grapheme_len_before(rope: &Rope, byte_idx: usize) -> usize {
+    if byte_idx == 0 {
+        return 0;
+    }
+    let line_start = rope.line_to_byte(rope.byte_to_line(byte_idx));
+    if line_start == byte_idx {
+        // Right after a line break.
+        let previous_line = rope.line(rope.byte_to_line(byte_idx - 1)).to_string();
+        return split_line_ending(&previous_line).1.len();
+    }
+    let prefix = rope.byte_slice(line_start..byte_idx).to_string();
+    prefix.graphemes(true).next_back().map_or(0, str::len)
+}
+
+// Returns the byte length of the grapheme cluster starting at 'byte_idx' (0 at the end).
+pub(crate) fn grapheme_len_after(rope: &Rope, byte_idx: usize) -> usize {
+    if byte_idx >= rope.len_bytes() {
+        return 0;
+    }
+    let line = rope.byte_to_line(byte_idx);
+    let line_end = rope.line_to_byte(line + 1);
+    let rest = rope.byte_slice(byte_idx..line_end).to_string();
+    rest.graphemes(true).next().map_or(0, str::len)
+}
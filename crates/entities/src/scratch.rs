@@ -0,0 +1,27 @@
+// Scratch Buffers
+// Small throwaway buffers for inline input widgets (LSP rename, find/replace fields) that share
+// nothing with any document.
+use crate::rope_utils::split_line_ending;
+use crate::TextBuffer;
+
+
+impl TextBuffer {
+    // Scratch Buffers
+
+    // Creates a small, unshared buffer holding 'initial'. It is an ordinary 'TextBuffer'; the
+    // name only documents the intent (no document, no undo history, typically one observer).
+    pub fn scratch(initial: &str) -> TextBuffer {
+        TextBuffer::new(initial)
+    }
+
+    // Returns the text with every line break removed, e.g. the value of a single-line field
+    // after the user pasted several lines into it.
+    pub fn as_single_line(&self) -> String {
+        let rope = self.content.lock();
+        let mut text = String::with_capacity(rope.len_bytes());
+        for line in rope.lines() {
+            text.push_str(split_line_ending(&line.to_string()).0);
+        }
+        text
+    }
+}
@@ -0,0 +1,337 @@
+// Search
+// Streaming text search over the rope's chunks. Matches may span chunk boundaries: the scanner
+// keeps a short tail of the previous chunk (one byte less than the needle) instead of
+// materializing the whole buffer, so memory use is bounded by chunk size + needle length.
+use ropey::Rope;
+
+use crate::rope_utils::{char_at, char_before};
+use crate::{TextBuffer, TextRange};
+
+
+// Search Options
+// How 'find_all_opts' and friends compare text.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SearchOptions {
+    // Compare using Unicode case folding rather than exact chars.
+    pub case_insensitive: bool,
+    // Only report matches that don't start or end in the middle of a word.
+    pub whole_word: bool,
+}
+
+
+// Returns the full case folding of 'c' when it expands to several chars (Unicode CaseFolding.txt
+// status F), e.g. 'ß' -> "ss", so "strasse" finds "straße". Chars whose lowercase form already
+// expands ('İ' -> "i̇") are left to lowercasing.
+fn full_fold(c: char) -> Option<&'static str> {
+    Some(match c {
+        'ß' | 'ẞ' => "ss",
+        'ŉ' => "ʼn",
+        'ǰ' => "j\u{30C}",
+        '\u{390}' | '\u{1FD3}' => "ι\u{308}\u{301}",
+        '\u{3B0}' | '\u{1FE3}' => "υ\u{308}\u{301}",
+        'և' => "եւ",
+        'ẖ' => "h\u{331}",
+        'ẗ' => "t\u{308}",
+        'ẘ' => "w\u{30A}",
+        'ẙ' => "y\u{30A}",
+        'ẚ' => "aʾ",
+        'ﬀ' => "ff",
+        'ﬁ' => "fi",
+        'ﬂ' => "fl",
+        'ﬃ' => "ffi",
+        'ﬄ' => "ffl",
+        'ﬅ' | 'ﬆ' => "st",
+        'ﬓ' => "մն",
+        'ﬔ' => "մե",
+        'ﬕ' => "մի",
+        'ﬖ' => "վն",
+        'ﬗ' => "մխ",
+        _ => return None,
+    })
+}
+
+// Folds a char for case-insensitive comparison. The expansion may be longer than the char.
+// Besides the full foldings above, lowercasing covers almost all of Unicode simple case folding;
+// the exceptions are chars that are already lowercase but fold to another form (final sigma,
+// long s, Greek symbol variants).
+fn fold_char(c: char, out: &mut String) {
+    if let Some(expansion) = full_fold(c) {
+        out.push_str(expansion);
+        return;
+    }
+    let folded = match c {
+        'ς' => 'σ',
+        'ſ' => 's',
+        'ϐ' => 'β',
+        'ϑ' => 'θ',
+        'ϕ' => 'φ',
+        'ϖ' => 'π',
+        'ϰ' => 'κ',
+        'ϱ' => 'ρ',
+        'ϵ' => 'ε',
+        '\u{0345}' | '\u{1FBE}' => 'ι', // combining ypogegrammeni, Greek prosgegrammeni
+        'ẛ' => 'ṡ',
+        _ => {
+            out.extend(c.to_lowercase());
+            return;
+        }
+    };
+    out.push(folded);
+}
+
+fn fold_str(s: &str) -> String {
+    let mut folded = String::with_capacity(s.len());
+    for c in s.chars() {
+        fold_char(c, &mut folded);
+    }
+    folded
+}
+
+// Chars that make up words for whole-word matching.
+pub(crate) fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+
+// Returns true if window byte 'idx' begins the (possibly folded) expansion of a source char.
+fn is_expansion_start(origins: &[usize], idx: usize) -> bool {
+    idx == 0 || idx >= origins.len() || origins[idx] != origins[idx - 1]
+}
+
+fn next_expansion_start(origins: &[usize], mut idx: usize) -> usize {
+    while !is_expansion_start(origins, idx) {
+        idx += 1;
+    }
+    idx
+}
+
+
+// Outcome of one search pass over the window.
+enum SearchState {
+    // No further match in the window.
+    Exhausted,
+    // A candidate match touches the end of the window and needs the next chunk to be decided.
+    Deferred,
+    // The callback asked to stop.
+    Stopped,
+}
+
+
+// Match Scanner
+// Incremental matcher fed one chunk at a time. Reports non-overlapping occurrences of the needle
+// as byte ranges in the original text.
+// With 'case_insensitive', both sides are case-folded and a match must start and end on whole
+// source chars, so offsets always map back exactly even when folding changes lengths.
+pub(crate) struct MatchScanner {
+    needle: String,
+    opts: SearchOptions,
+    // The (folded) text being searched, and for each of its bytes the source offset of the char
+    // it came from.
+    window: String,
+    origins: Vec<usize>,
+    // Source offset just past the last fed byte.
+    source_end: usize,
+    // Window byte where the search resumes.
+    from: usize,
+    // Last (folded) char dropped from the front of the window, for whole-word checks.
+    // Seeded with the char before the scanned text when searching a sub-range.
+    char_before_window: Option<char>,
+    // Char following the scanned text, if any (set when finishing a sub-range search).
+    char_after_input: Option<char>,
+    // Set once the callback asked to stop.
+    stopped: bool,
+}
+
+impl MatchScanner {
+    // Creates a scanner whose first fed byte is at buffer offset 'base'.
+    pub(crate) fn new(needle: &str, opts: SearchOptions, base: usize) -> Self {
+        Self {
+            needle: if opts.case_insensitive { fold_str(needle) } else { needle.to_string() },
+            opts,
+            window: String::new(),
+            origins: Vec::new(),
+            source_end: base,
+            from: 0,
+            char_before_window: None,
+            char_after_input: None,
+            stopped: false,
+        }
+    }
+
+    // Sets the char immediately before the scanned text, for whole-word checks at its start.
+    pub(crate) fn with_char_before(mut self, before: Option<char>) -> Self {
+        self.char_before_window = before;
+        self
+    }
+
+    // Feeds the next chunk. 'on_match' returns false to stop; returns false once stopped.
+    pub(crate) fn feed(&mut self, chunk: &str, on_match: &mut impl FnMut(TextRange) -> bool) -> bool {
+        if self.stopped || self.needle.is_empty() {
+            return false;
+        }
+        for (offset, c) in chunk.char_indices() {
+            if self.opts.case_insensitive {
+                fold_char(c, &mut self.window);
+            } else {
+                self.window.push(c);
+            }
+            self.origins.resize(self.window.len(), self.source_end + offset);
+        }
+        self.source_end += chunk.len();
+
+        let searched_to = match self.search(false, on_match) {
+            SearchState::Stopped => return false,
+            // A match is waiting at 'from'; keep it.
+            SearchState::Deferred => self.from,
+            // Everything before the last 'needle.len() - 1' bytes has been fully searched.
+            SearchState::Exhausted => self.window.len().saturating_sub(self.needle.len() - 1),
+        };
+
+        let mut keep_from = searched_to.max(self.from);
+        while keep_from > self.from && !is_expansion_start(&self.origins, keep_from) {
+            keep_from -= 1;
+        }
+        if keep_from > 0 {
+            self.char_before_window = self.window[..keep_from].chars().next_back();
+        }
+        self.window.drain(..keep_from);
+        self.origins.drain(..keep_from);
+        self.from = 0;
+        true
+    }
+
+    // Reports matches that were waiting on text after them. 'after' is the char following the
+    // scanned text (None at the end of the buffer).
+    pub(crate) fn finish(&mut self, after: Option<char>, on_match: &mut impl FnMut(TextRange) -> bool) {
+        if !self.stopped && !self.needle.is_empty() {
+            self.char_after_input = after;
+            self.search(true, on_match);
+        }
+    }
+
+    fn search(&mut self, at_end: bool, on_match: &mut impl FnMut(TextRange) -> bool) -> SearchState {
+        while let Some(found) = self.window[self.from..].find(self.needle.as_str()) {
+            let start = self.from + found;
+            let end = start + self.needle.len();
+            if end == self.window.len() && !at_end {
+                // The char after the match isn't known yet; decide once more text arrives.
+                self.from = start;
+                return SearchState::Deferred;
+            }
+            if self.accepts(start, end) {
+                let source_end = self.origins.get(end).copied().unwrap_or(self.source_end);
+                if !on_match(TextRange::new(self.origins[start], source_end)) {
+                    self.stopped = true;
+                    return SearchState::Stopped;
+                }
+                self.from = end;
+            } else {
+                self.from = next_expansion_start(&self.origins, start + 1);
+            }
+        }
+        SearchState::Exhausted
+    }
+
+    fn accepts(&self, start: usize, end: usize) -> bool {
+        if !is_expansion_start(&self.origins, start) || !is_expansion_start(&self.origins, end) {
+            return false;
+        }
+        if self.opts.whole_word {
+            let matched = &self.window[start..end];
+            let before = self.window[..start].chars().next_back().or(self.char_before_window);
+            let after = self.window[end..].chars().next().or(self.char_after_input);
+            let splits_word = |outside: Option<char>, edge: Option<char>| {
+                outside.is_some_and(is_word_char) && edge.is_some_and(is_word_char)
+            };
+            if splits_word(before, matched.chars().next()) || splits_word(after, matched.chars().next_back()) {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+// Runs a scanner over the bytes of 'range' in 'rope'. Matches must lie fully inside the range,
+// but whole-word checks still see the chars just outside it.
+pub(crate) fn scan_matches(
+    rope: &Rope,
+    range: TextRange,
+    needle: &str,
+    opts: SearchOptions,
+    mut on_match: impl FnMut(TextRange) -> bool,
+) {
+    let Some(slice) = rope.get_byte_slice(range.start..range.end) else {
+        return;
+    };
+    let mut scanner = MatchScanner::new(needle, opts, range.start)
+        .with_char_before(char_before(rope, range.start));
+    for chunk in slice.chunks() {
+        if !scanner.feed(chunk, &mut on_match) {
+            return;
+        }
+    }
+    scanner.finish(char_at(rope, range.end), &mut on_match);
+}
+
+
+impl TextBuffer {
+    // Search
+
+    // Returns the byte ranges of all non-overlapping, exact (case-sensitive) occurrences of 'needle'.
+    pub fn find_all(&self, needle: &str) -> Vec<TextRange> {
+        self.find_all_opts(needle, SearchOptions::default())
+    }
+
+    // Returns the byte ranges of all non-overlapping occurrences of 'needle' under 'opts'.
+    pub fn find_all_opts(&self, needle: &str, opts: SearchOptions) -> Vec<TextRange> {
+        let rope = self.content.lock();
+        let whole = TextRange::new(0, rope.len_bytes());
+        let mut matches = Vec::new();
+        scan_matches(&rope, whole, needle, opts, |range| {
+            matches.push(range);
+            true
+        });
+        matches
+    }
+
+    // Like 'find_all_opts' but only reports matches fully contained in 'range' ("find in
+    // selection"). A range that is out of bounds or splits a char yields no matches.
+    pub fn find_all_in_range(&self, needle: &str, range: TextRange, opts: SearchOptions) -> Vec<TextRange> {
+        let rope = self.content.lock();
+        let mut matches = Vec::new();
+        scan_matches(&rope, range, needle, opts, |found| {
+            matches.push(found);
+            true
+        });
+        matches
+    }
+
+    // Counts the non-overlapping occurrences of 'needle' without collecting their ranges.
+    // Uses the same chunk-streaming scan as 'find_all', so the counts always agree.
+    pub fn count_matches(&self, needle: &str, case_sensitive: bool) -> usize {
+        let rope = self.content.lock();
+        let opts = SearchOptions { case_insensitive: !case_sensitive, ..SearchOptions::default() };
+        let whole = TextRange::new(0, rope.len_bytes());
+        let mut count = 0;
+        scan_matches(&rope, whole, needle, opts, |_| {
+            count += 1;
+            true
+        });
+        count
+    }
+
+    // Returns true if 'needle' occurs anywhere, i.e. 'count_matches(needle, case_sensitive) > 0',
+    // but stops streaming at the first match and allocates nothing per match. For filtering many
+    // buffers by content.
+    pub fn contains(&self, needle: &str, case_sensitive: bool) -> bool {
+        let rope = self.content.lock();
+        let opts = SearchOptions { case_insensitive: !case_sensitive, ..SearchOptions::default() };
+        let whole = TextRange::new(0, rope.len_bytes());
+        let mut found = false;
+        scan_matches(&rope, whole, needle, opts, |_| {
+            found = true;
+            false
+        });
+        found
+    }
+}
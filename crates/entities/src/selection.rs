@@ -0,0 +1,132 @@
+// Expand Selection
+// Ctrl+W style selection growing and its inverse, built on the existing notions of words, lines
+// and indentation blocks. Each step moves to the next larger unit around the selection:
+// word -> lines -> enclosing indentation blocks (innermost first) -> whole buffer.
+// Also home to 'merge_selections', which keeps multi-cursor selections disjoint.
+use ropey::Rope;
+
+use crate::indent::indent_block;
+use crate::rope_utils::{char_at, char_before};
+use crate::search::is_word_char;
+use crate::{TextBuffer, TextRange};
+
+
+// Returns the word (run of word chars) containing or touching 'byte_idx', if any.
+fn word_range(rope: &Rope, byte_idx: usize) -> Option<TextRange> {
+    let (mut start, mut end) = (byte_idx, byte_idx);
+    while let Some(c) = char_before(rope, start).filter(|c| is_word_char(*c)) {
+        start -= c.len_utf8();
+    }
+    while let Some(c) = char_at(rope, end).filter(|c| is_word_char(*c)) {
+        end += c.len_utf8();
+    }
+    (start < end).then(|| TextRange::new(start, end))
+}
+
+// Returns the start of the char containing 'byte_idx' (the end of the buffer stays put).
+fn floor_char_boundary(rope: &Rope, byte_idx: usize) -> usize {
+    rope.char_to_byte(rope.byte_to_char(byte_idx))
+}
+
+// Clamps 'range' to the buffer, moving each end back to a char boundary.
+fn clamp(rope: &Rope, range: TextRange) -> TextRange {
+    let bound = |byte_idx: usize| floor_char_boundary(rope, byte_idx.min(rope.len_bytes()));
+    TextRange::new(bound(range.start), bound(range.end))
+}
+
+fn contains(outer: TextRange, inner: TextRange) -> bool {
+    outer.start <= inner.start && inner.end <= outer.end
+}
+
+// Returns the units around 'range' that contain it, from the smallest to the whole buffer.
+fn selection_levels(rope: &Rope, range: TextRange) -> Vec<TextRange> {
+    let mut levels = Vec::new();
+    if let Some(word) = word_range(rope, range.start) {
+        levels.push(word);
+    }
+    // The lines the range spans, with their terminators. A range ending right at a line start
+    // doesn't span that line.
+    let first_line = rope.byte_to_line(range.start);
+    let mut last_line = rope.byte_to_line(range.end);
+    if last_line > first_line && rope.line_to_byte(last_line) == range.end {
+        last_line -= 1;
+    }
+    levels.push(TextRange::new(rope.line_to_byte(first_line), rope.line_to_byte(last_line + 1)));
+    // The block of the first line, then the blocks of the less indented lines above it.
+    let mut block = indent_block(rope, first_line);
+    levels.push(block);
+    let mut line = rope.byte_to_line(block.start);
+    while line > 0 {
+        line -= 1;
+        if rope.line(line).chars().all(char::is_whitespace) {
+            continue;
+        }
+        let parent = indent_block(rope, line);
+        block = TextRange::new(parent.start.min(block.start), parent.end.max(block.end));
+        levels.push(block);
+        line = rope.byte_to_line(block.start);
+    }
+    levels.push(TextRange::new(0, rope.len_bytes()));
+    levels.retain(|level| contains(*level, range));
+    levels
+}
+
+
+// Sorts 'ranges' by start and merges those that overlap or touch (so duplicates and cursors at
+// the edge of a selection collapse too), leaving disjoint, non-adjacent ranges that are safe to
+// edit together. 'primary' is the index of the primary selection before merging; returns the
+// index of the range that now contains it (0 if 'ranges' is empty, the last range if 'primary'
+// is out of bounds). A pure function over ranges, to run before every multi-cursor edit.
+pub fn merge_selections(ranges: &mut Vec<TextRange>, primary: usize) -> usize {
+    let Some(primary_range) = ranges.get(primary).or(ranges.last()).copied() else {
+        return 0;
+    };
+    ranges.sort_by_key(|range| (range.start, range.end));
+    let mut merged: Vec<TextRange> = Vec::with_capacity(ranges.len());
+    for range in ranges.drain(..) {
+        match merged.last_mut() {
+            Some(last) if range.start <= last.end => last.end = last.end.max(range.end),
+            _ => merged.push(range),
+        }
+    }
+    *ranges = merged;
+    ranges.iter().position(|range| contains(*range, primary_range)).unwrap_or(0)
+}
+
+
+impl TextBuffer {
+    // Selection
+
+    // Returns the word containing or touching 'byte_idx' (a cursor right after a word selects
+    // it), or None if there is no word char on either side or 'byte_idx' is past the end.
+    pub fn word_at(&self, byte_idx: usize) -> Option<TextRange> {
+        let rope = self.content.lock();
+        if byte_idx > rope.len_bytes() {
+            return None;
+        }
+        word_range(&rope, floor_char_boundary(&rope, byte_idx))
+    }
+
+    // Returns the next larger unit strictly containing 'current' (see the module comment), so
+    // calling it repeatedly grows the selection one level at a time. Returns the whole buffer
+    // once it is reached. An invalid range is clamped to the buffer first.
+    pub fn expand_selection(&self, current: TextRange) -> TextRange {
+        let rope = self.content.lock();
+        let current = clamp(&rope, current);
+        selection_levels(&rope, current).into_iter().find(|level| *level != current).unwrap_or(current)
+    }
+
+    // The inverse of 'expand_selection': returns the largest unit around the selection's start
+    // that lies strictly inside 'current', down to an empty selection at its start. The units
+    // are those around the start alone, so an editor that must retrace exactly the selections it
+    // expanded through should remember them instead.
+    pub fn shrink_selection(&self, current: TextRange) -> TextRange {
+        let rope = self.content.lock();
+        let current = clamp(&rope, current);
+        let cursor = TextRange::new(current.start, current.start);
+        selection_levels(&rope, cursor).into_iter()
+            .rev()
+            .find(|level| *level != current && contains(current, *level))
+            .unwrap_or(cursor)
+    }
+}
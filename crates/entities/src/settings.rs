@@ -0,0 +1,78 @@
+// Document Settings
+// Per-file editing behavior kept in one place on the 'Document', instead of being threaded
+// through every call site. Partly detected from the text when a file is opened.
+use crate::{Document, EditError, Indentation, LineEnding, TextPosition};
+
+
+// DocumentSettings Struct
+// 'wrap_columns' of None disables soft wrap. The save options are applied by 'save_with'.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DocumentSettings {
+    pub indentation: Indentation,
+    // Used for newly inserted line breaks, e.g. by 'ensure_final_newline'.
+    pub line_ending: LineEnding,
+    pub wrap_columns: Option<usize>,
+    pub trim_trailing_whitespace_on_save: bool,
+    pub ensure_final_newline_on_save: bool,
+}
+
+impl Default for DocumentSettings {
+    fn default() -> Self {
+        Self {
+            indentation: Indentation::default(),
+            line_ending: LineEnding::Lf,
+            wrap_columns: None,
+            trim_trailing_whitespace_on_save: false,
+            ensure_final_newline_on_save: false,
+        }
+    }
+}
+
+
+impl Document {
+    // Returns the document's settings.
+    pub fn settings(&self) -> DocumentSettings {
+        self.settings.lock().clone()
+    }
+
+    // Replaces the document's settings.
+    pub fn set_settings(&self, settings: DocumentSettings) {
+        *self.settings.lock() = settings;
+    }
+
+    // Sets the indentation and line ending from what the text uses, keeping the current values
+    // where the text gives no hint. Called when a file is opened.
+    pub fn detect_settings(&self) {
+        let indentation = self.text_buffer.detect_indentation();
+        let line_ending = self.text_buffer.detect_line_ending();
+        let mut settings = self.settings.lock();
+        settings.indentation = indentation.unwrap_or(settings.indentation);
+        settings.line_ending = line_ending.unwrap_or(settings.line_ending);
+    }
+
+    // Tab key with the document's indentation (see 'TextBuffer::insert_tab').
+    pub async fn insert_tab(&self, position: TextPosition) -> Result<TextPosition, EditError> {
+        let indentation = self.settings.lock().indentation;
+        self.text_buffer.insert_tab(position, &indentation).await
+    }
+
+    // Shift-Tab on 'line' with the document's indentation (see 'TextBuffer::dedent_line').
+    pub async fn dedent_line(&self, line: usize) -> Result<usize, EditError> {
+        let indentation = self.settings.lock().indentation;
+        self.text_buffer.dedent_line(line, &indentation).await
+    }
+
+    // Applies the on-save cleanups the settings ask for. A read-only document is saved as it is.
+    pub(crate) async fn apply_save_settings(&self) {
+        if self.is_read_only() {
+            return;
+        }
+        let settings = self.settings();
+        if settings.trim_trailing_whitespace_on_save {
+            self.text_buffer.trim_trailing_whitespace().await;
+        }
+        if settings.ensure_final_newline_on_save {
+            self.text_buffer.ensure_final_newline(settings.line_ending).await;
+        }
+    }
+}
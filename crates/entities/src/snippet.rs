@@ -0,0 +1,339 @@
+// Snippets
+// Parses LSP snippet syntax ('$1', '${1}', '${1:default}', '${1|a,b|}', '$0', variables and '\'
+// escapes) and inserts the expanded text, returning anchor-backed ranges for tab navigation.
+// 'SnippetSession' keeps the "jump to the next placeholder" state on top of them.
+use std::sync::Arc;
+
+use crate::edits::check_range;
+use crate::{AnchoredRange, EditError, EditOrigin, TextBuffer, TextPosition, TextRange};
+
+
+// Parsed Snippet
+// The expanded text plus the tab stops, as byte ranges relative to the start of 'text',
+// already ordered for navigation ('$1', '$2', ... and finally '$0').
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct ParsedSnippet {
+    pub(crate) text: String,
+    pub(crate) tab_stops: Vec<TextRange>,
+}
+
+
+// Recursive-descent parser over the snippet's chars.
+struct SnippetParser {
+    chars: Vec<char>,
+    pos: usize,
+    out: String,
+    // (tab stop number, range) for the first occurrence of each number.
+    stops: Vec<(u32, TextRange)>,
+}
+
+impl SnippetParser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn peek_at(&self, offset: usize) -> Option<char> {
+        self.chars.get(self.pos + offset).copied()
+    }
+
+    // Parses text until the end of input or, when 'nested', until the '}' closing the enclosing
+    // placeholder (which is consumed).
+    fn parse_any(&mut self, nested: bool) {
+        while let Some(c) = self.peek() {
+            match c {
+                '\\' if matches!(self.peek_at(1), Some('$' | '}' | '\\')) => {
+                    self.out.push(self.chars[self.pos + 1]);
+                    self.pos += 2;
+                }
+                '}' if nested => {
+                    self.pos += 1;
+                    return;
+                }
+                '$' => {
+                    if !self.parse_dollar() {
+                        self.out.push('$');
+                        self.pos += 1;
+                    }
+                }
+                _ => {
+                    self.out.push(c);
+                    self.pos += 1;
+                }
+            }
+        }
+    }
+
+    // Parses a construct starting at '$'. Returns false (consuming nothing) if it isn't valid
+    // snippet syntax, in which case the '$' is literal text.
+    fn parse_dollar(&mut self) -> bool {
+        let start = self.pos;
+        self.pos += 1; // '$'
+
+        if let Some(index) = self.parse_int() {
+            self.push_tab_stop(index, None);
+            return true;
+        }
+        if let Some(name) = self.parse_var_name() {
+            self.out.push_str(&name);
+            return true;
+        }
+        if self.peek() != Some('{') {
+            self.pos = start;
+            return false;
+        }
+        self.pos += 1; // '{'
+
+        if let Some(index) = self.parse_int() {
+            match self.peek() {
+                Some('}') => {
+                    self.pos += 1;
+                    self.push_tab_stop(index, None);
+                    return true;
+                }
+                Some(':') => {
+                    self.pos += 1;
+                    let stop_start = self.out.len();
+                    self.parse_any(true);
+                    self.push_tab_stop(index, Some(stop_start));
+                    return true;
+                }
+                Some('|') => {
+                    self.pos += 1;
+                    if let Some(first_choice) = self.parse_choice() {
+                        let stop_start = self.out.len();
+                        self.out.push_str(&first_choice);
+                        self.push_tab_stop(index, Some(stop_start));
+                        return true;
+                    }
+                }
+                _ => {}
+            }
+        } else if let Some(name) = self.parse_var_name() {
+            match self.peek() {
+                Some('}') => {
+                    self.pos += 1;
+                    self.out.push_str(&name);
+                    return true;
+                }
+                Some(':') => {
+                    // Variables are not resolved here; fall back to the default text.
+                    self.pos += 1;
+                    self.parse_any(true);
+                    return true;
+                }
+                _ => {}
+            }
+        }
+
+        self.pos = start;
+        false
+    }
+
+    fn parse_int(&mut self) -> Option<u32> {
+        let start = self.pos;
+        while self.peek().is_some_and(|c| c.is_ascii_digit()) {
+            self.pos += 1;
+        }
+        if self.pos == start {
+            return None;
+        }
+        let digits: String = self.chars[start..self.pos].iter().collect();
+        digits.parse().ok().or_else(|| {
+            self.pos = start;
+            None
+        })
+    }
+
+    fn parse_var_name(&mut self) -> Option<String> {
+        if !self.peek().is_some_and(|c| c == '_' || c.is_ascii_alphabetic()) {
+            return None;
+        }
+        let start = self.pos;
+        while self.peek().is_some_and(|c| c == '_' || c.is_ascii_alphanumeric()) {
+            self.pos += 1;
+        }
+        Some(self.chars[start..self.pos].iter().collect())
+    }
+
+    // Parses 'a,b,c|}' (after the opening '|') and returns the first choice.
+    fn parse_choice(&mut self) -> Option<String> {
+        let start = self.pos;
+        let mut first = None;
+        let mut current = String::new();
+        while let Some(c) = self.peek() {
+            match c {
+                '\\' if matches!(self.peek_at(1), Some('$' | '}' | '\\' | ',' | '|')) => {
+                    current.push(self.chars[self.pos + 1]);
+                    self.pos += 2;
+                }
+                ',' => {
+                    first.get_or_insert(std::mem::take(&mut current));
+                    self.pos += 1;
+                }
+                '|' if self.peek_at(1) == Some('}') => {
+                    self.pos += 2;
+                    return Some(first.unwrap_or(current));
+                }
+                _ => {
+                    current.push(c);
+                    self.pos += 1;
+                }
+            }
+        }
+        self.pos = start;
+        None
+    }
+
+    // Records a tab stop ending at the current output position. 'start' is None for a bare
+    // tab stop; a repeated bare tab stop mirrors the text of the first occurrence.
+    fn push_tab_stop(&mut self, index: u32, start: Option<usize>) {
+        let start = match start {
+            Some(start) => start,
+            None => {
+                let start = self.out.len();
+                if let Some((_, first)) = self.stops.iter().find(|(i, _)| *i == index) {
+                    let mirrored = self.out[first.start..first.end].to_string();
+                    self.out.push_str(&mirrored);
+                }
+                start
+            }
+        };
+        if !self.stops.iter().any(|(i, _)| *i == index) {
+            self.stops.push((index, TextRange::new(start, self.out.len())));
+        }
+    }
+}
+
+
+// Parses a snippet into its expanded text and ordered tab stops.
+// If the snippet has no '$0', an implicit final tab stop is placed at the end of the text.
+pub(crate) fn parse_snippet(snippet: &str) -> ParsedSnippet {
+    let mut parser = SnippetParser {
+        chars: snippet.chars().collect(),
+        pos: 0,
+        out: String::with_capacity(snippet.len()),
+        stops: Vec::new(),
+    };
+    parser.parse_any(false);
+
+    let mut stops = parser.stops;
+    if !stops.iter().any(|(index, _)| *index == 0) {
+        let end = parser.out.len();
+        stops.push((0, TextRange::new(end, end)));
+    }
+    // '$0' is always visited last; everything else in ascending order.
+    stops.sort_by_key(|(index, _)| if *index == 0 { u32::MAX } else { *index });
+
+    ParsedSnippet {
+        text: parser.out,
+        tab_stops: stops.into_iter().map(|(_, range)| range).collect(),
+    }
+}
+
+
+impl TextBuffer {
+    // Inserts a snippet at 'position' and returns anchor-backed ranges for each tab stop in
+    // navigation order, ending with the final cursor ('$0'). Resolve them with 'resolve_range';
+    // they keep tracking the text as the user fills in placeholders.
+    // The caller owns the returned anchors and should release them with 'remove_anchored_range'.
+    // Fails, inserting nothing, if 'position' is invalid, inside a protected region or the
+    // insertion is vetoed.
+    pub async fn insert_snippet(&self, position: TextPosition, snippet: &str) -> Result<Vec<AnchoredRange>, EditError> {
+        let parsed = parse_snippet(snippet);
+
+        let mut rope = self.content.lock();
+        check_range(&rope, position.byte_idx, position.byte_idx)?;
+        let event = self.apply_insert(&mut rope, position.byte_idx, &parsed.text, EditOrigin::User)?;
+        // Anchors are created under the same lock so no edit can slip in between.
+        let ranges = {
+            let mut anchors = self.anchors.lock();
+            parsed.tab_stops.iter()
+                .map(|range| anchors.create_range(TextRange::new(
+                    position.byte_idx + range.start,
+                    position.byte_idx + range.end,
+                )))
+                .collect()
+        };
+        drop(rope);
+
+        if let Some(event) = event {
+            self.notify_observers(event).await;
+        }
+        Ok(ranges)
+    }
+}
+
+
+// Snippet Session
+// Tab-stop navigation after a snippet was inserted. The session starts at the first tab stop
+// and ends once the final one ('$0') is reached. The stops are anchored ranges, so edits inside
+// a placeholder (or anywhere else) move the later stops along.
+pub struct SnippetSession {
+    buffer: Arc<TextBuffer>,
+    // The tab stops in navigation order, ending with '$0'.
+    tab_stops: Vec<AnchoredRange>,
+    current: usize,
+}
+
+impl SnippetSession {
+    // Inserts 'snippet' at 'position' (see 'TextBuffer::insert_snippet') and starts a session
+    // on its tab stops. Fails like 'insert_snippet'.
+    pub async fn insert(buffer: Arc<TextBuffer>, position: TextPosition, snippet: &str) -> Result<Self, EditError> {
+        let tab_stops = buffer.insert_snippet(position, snippet).await?;
+        Ok(Self::new(buffer, tab_stops))
+    }
+
+    // Starts a session on tab stops returned by 'insert_snippet', taking ownership of their
+    // anchors: they are released when the session is dropped.
+    pub fn new(buffer: Arc<TextBuffer>, tab_stops: Vec<AnchoredRange>) -> Self {
+        Self { buffer, tab_stops, current: 0 }
+    }
+
+    // Returns the current range of the active tab stop, or None once the session is complete.
+    pub fn current(&self) -> Option<TextRange> {
+        if self.is_complete() {
+            return None;
+        }
+        self.buffer.resolve_range(&self.tab_stops[self.current])
+    }
+
+    // Moves back to the previous tab stop and returns its current range, or None (staying put)
+    // at the first one or once the session is complete.
+    pub fn prev(&mut self) -> Option<TextRange> {
+        if self.is_complete() || self.current == 0 {
+            return None;
+        }
+        self.current -= 1;
+        self.current()
+    }
+
+    // Returns true once the final tab stop was reached (right away for a snippet without
+    // placeholders).
+    pub fn is_complete(&self) -> bool {
+        self.current + 1 >= self.tab_stops.len()
+    }
+}
+
+// Iterating moves through the remaining tab stops.
+impl Iterator for SnippetSession {
+    type Item = TextRange;
+
+    // Moves to the next tab stop and returns its current range. Moving onto the final tab stop
+    // completes the session (its range is still returned, to place the cursor); after that
+    // there is nothing left and None is returned.
+    fn next(&mut self) -> Option<TextRange> {
+        if self.is_complete() {
+            return None;
+        }
+        self.current += 1;
+        self.buffer.resolve_range(self.tab_stops.get(self.current)?)
+    }
+}
+
+impl Drop for SnippetSession {
+    fn drop(&mut self) {
+        for range in &self.tab_stops {
+            self.buffer.remove_anchored_range(range);
+        }
+    }
+}
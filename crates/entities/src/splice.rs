@@ -0,0 +1,108 @@
+// Streaming Splice
+// Replacing a range with text read from a stream, for pasting or generating very large blocks.
+// The replacement is inserted chunk by chunk as it is read, and neither the removed nor the new
+// text is ever copied out, so peak memory stays at one read buffer on top of the rope.
+use std::io::{self, Read};
+
+use ropey::Rope;
+
+use crate::edits::check_range;
+use crate::{EditOrigin, TextBuffer, TextBufferChangedEvent, TextRange};
+
+
+// Bytes read from the stream at a time.
+const SPLICE_CHUNK_BYTES: usize = 64 * 1024;
+
+
+// Splits off the longest prefix of 'bytes' that is valid UTF-8. An incomplete char at the end is
+// left for the next read to complete; the flag is set if the bytes after the prefix can never
+// become valid.
+fn utf8_prefix(bytes: &[u8]) -> (&str, bool) {
+    match std::str::from_utf8(bytes) {
+        Ok(text) => (text, false),
+        // The prefix up to 'valid_up_to' is valid by definition.
+        Err(error) => (std::str::from_utf8(&bytes[..error.valid_up_to()]).unwrap_or_default(), error.error_len().is_some()),
+    }
+}
+
+
+impl TextBuffer {
+    // Replaces 'range' with the UTF-8 text read from 'reader' and returns the range the new text
+    // covers. Observers get a single 'Reloaded', since an event carrying the text would double
+    // the memory this is meant to save (so, like any 'Reloaded', it can't be undone).
+    // The buffer stays locked while 'reader' is drained, so the reader should be fast (a file,
+    // not a socket). Pre-edit observers are asked about the removal only, with an empty text, as
+    // the replacement isn't known yet. An invalid range, a protected region or a veto fails
+    // with 'InvalidInput' before anything changes. A read error or invalid UTF-8 stops the
+    // splice: the error is returned, but the removal and the text read so far stay applied and
+    // are reported to observers.
+    pub async fn splice<R: Read>(&self, range: TextRange, reader: R) -> io::Result<TextRange> {
+        let (event, result) = {
+            let mut reader = reader;
+            let mut rope = self.content.lock();
+            let rejected = |error| io::Error::new(io::ErrorKind::InvalidInput, error);
+            check_range(&rope, range.start, range.end).map_err(rejected)?;
+            self.check_edit(&rope, range, "", EditOrigin::User).map_err(rejected)?;
+            if !range.is_empty() {
+                self.count_removed_words(&rope, range.start, range.end);
+                let mut anchors = self.anchors.lock();
+                anchors.drop_removed_lines(&rope, range.start, range.len());
+                let (start_char, end_char) = (rope.byte_to_char(range.start), rope.byte_to_char(range.end));
+                rope.remove(start_char..end_char);
+                anchors.shift_for_remove(range.start, range.len());
+            }
+            let mut end = range.start;
+            let result = self.stream_into(&mut rope, &mut end, &mut reader);
+            if range.is_empty() && end == range.start {
+                return result.map(|()| range);
+            }
+            {
+                let mut anchors = self.anchors.lock();
+                self.edit_locations.lock().record(&mut anchors, &rope, end);
+            }
+            let event = TextBufferChangedEvent::Reloaded {
+                len_bytes: rope.len_bytes(),
+                len_lines: rope.len_lines(),
+                origin: EditOrigin::User,
+                version: self.bump_version(),
+            };
+            self.changes.lock().record(&event);
+            (event, result.map(|()| TextRange::new(range.start, end)))
+        };
+        self.notify_observers(event).await;
+        result
+    }
+
+    // Inserts the text read from 'reader' at '*position', advancing it past each inserted chunk.
+    fn stream_into(&self, rope: &mut Rope, position: &mut usize, reader: &mut impl Read) -> io::Result<()> {
+        let mut buf = vec![0; SPLICE_CHUNK_BYTES];
+        // Bytes of an incomplete char carried over to the front of 'buf'.
+        let mut pending = 0;
+        loop {
+            let read = match reader.read(&mut buf[pending..]) {
+                Ok(0) => break,
+                Ok(read) => read,
+                Err(error) if error.kind() == io::ErrorKind::Interrupted => continue,
+                Err(error) => return Err(error),
+            };
+            let filled = pending + read;
+            let (text, invalid) = utf8_prefix(&buf[..filled]);
+            if !text.is_empty() {
+                rope.insert(rope.byte_to_char(*position), text);
+                self.anchors.lock().shift_for_insert(*position, text.len());
+                self.count_inserted_words(rope, *position, *position + text.len());
+                *position += text.len();
+            }
+            if invalid {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "stream did not contain valid UTF-8"));
+            }
+            let valid = text.len();
+            buf.copy_within(valid..filled, 0);
+            pending = filled - valid;
+        }
+        if pending > 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "stream ended inside a UTF-8 sequence"));
+        }
+        Ok(())
+    }
+}
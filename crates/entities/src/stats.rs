@@ -0,0 +1,81 @@
+// Text Statistics
+// Byte, char, line and word counts for a live status bar display. The rope already keeps byte,
+// char and line counts in its tree; the word count is kept up to date by every edit, which only
+// scans the changed text and the chars around it, so 'stats' is O(1).
+use std::sync::atomic::Ordering;
+
+use ropey::Rope;
+
+use crate::rope_utils::{char_at, char_before, user_line_count};
+use crate::TextBuffer;
+
+
+// Text Stats Struct
+// 'lines' counts as 'TextBuffer::line_count' does. A word is a run of non-whitespace chars.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TextStats {
+    pub bytes: usize,
+    pub chars: usize,
+    pub lines: usize,
+    pub words: usize,
+}
+
+
+pub(crate) fn count_words(text: &str) -> usize {
+    text.split_whitespace().count()
+}
+
+// Returns how many words the text in '[start, end)' contributes, given its neighbours: the words
+// of "before + text + after" minus those of "before + after". Whether a word starts at a char only
+// depends on the char before it, so everything outside that window counts the same either way.
+// That covers a word split by an inserted space, and two words joined by the span's text, which
+// makes the result negative.
+pub(crate) fn words_in_span(rope: &Rope, start: usize, end: usize) -> isize {
+    let mut without = String::new();
+    let mut with = String::new();
+    if let Some(before) = char_before(rope, start) {
+        without.push(before);
+        with.push(before);
+    }
+    for chunk in rope.byte_slice(start..end).chunks() {
+        with.push_str(chunk);
+    }
+    if let Some(after) = char_at(rope, end) {
+        without.push(after);
+        with.push(after);
+    }
+    count_words(&with) as isize - count_words(&without) as isize
+}
+
+
+impl TextBuffer {
+    // Returns the current counts without rescanning the text.
+    pub fn stats(&self) -> TextStats {
+        let rope = self.content.lock();
+        TextStats {
+            bytes: rope.len_bytes(),
+            chars: rope.len_chars(),
+            lines: user_line_count(&rope),
+            words: self.word_count.load(Ordering::SeqCst),
+        }
+    }
+
+    // Accounts for the text just inserted at '[start, end)'. Must be called with 'content' locked.
+    pub(crate) fn count_inserted_words(&self, rope: &Rope, start: usize, end: usize) {
+        self.adjust_word_count(words_in_span(rope, start, end));
+    }
+
+    // Accounts for the text about to be removed from '[start, end)'. Must be called with
+    // 'content' locked.
+    pub(crate) fn count_removed_words(&self, rope: &Rope, start: usize, end: usize) {
+        self.adjust_word_count(-words_in_span(rope, start, end));
+    }
+
+    fn adjust_word_count(&self, delta: isize) {
+        if delta >= 0 {
+            self.word_count.fetch_add(delta.unsigned_abs(), Ordering::SeqCst);
+        } else {
+            self.word_count.fetch_sub(delta.unsigned_abs(), Ordering::SeqCst);
+        }
+    }
+}
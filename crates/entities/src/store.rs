@@ -0,0 +1,173 @@
+// Document Storage
+// Where documents are loaded from and saved to. 'Document::open_with'/'save_with' go through a
+// 'DocumentStore', so documents can live somewhere other than the local filesystem (SSH, object
+// storage) without the rest of the crate knowing.
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use async_trait::async_trait;
+
+use crate::bytes::text_hash;
+use crate::encoding::detect_encoding;
+use crate::{Document, DocumentEvent};
+
+
+// Document Error
+// Errors of the document operations that can fail for reasons other than I/O.
+#[derive(Debug)]
+pub enum DocumentError {
+    Io(io::Error),
+    // The file changed on disk since the document was loaded or last saved (see 'save_checked').
+    ConflictingExternalChange,
+}
+
+impl fmt::Display for DocumentError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DocumentError::Io(error) => write!(f, "{error}"),
+            DocumentError::ConflictingExternalChange => write!(f, "the file was changed on disk by another program"),
+        }
+    }
+}
+
+impl std::error::Error for DocumentError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            DocumentError::Io(error) => Some(error),
+            DocumentError::ConflictingExternalChange => None,
+        }
+    }
+}
+
+impl From<io::Error> for DocumentError {
+    fn from(error: io::Error) -> Self {
+        DocumentError::Io(error)
+    }
+}
+
+
+// DocumentStore Trait
+// Reads and writes whole files as bytes. Paths are passed through untouched, so a store may
+// interpret them any way it likes (e.g. as URLs).
+#[async_trait]
+pub trait DocumentStore: Send + Sync {
+    async fn read(&self, path: &str) -> io::Result<Vec<u8>>;
+    async fn write(&self, path: &str, bytes: &[u8]) -> io::Result<()>;
+}
+
+
+// LocalFsStore
+// The local filesystem. Writes go to a temporary file next to the target which is then renamed
+// over it, so a crash mid-save never leaves a truncated file behind. Each write gets its own
+// temporary file, so concurrent saves of one path (an autosave racing a manual save) can't mix
+// their bytes. The blocking file calls run on Tokio's blocking pool, keeping the runtime's workers
+// free and letting the saves of 'save_all' overlap.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LocalFsStore;
+
+// Numbers the temporary files of this process' writes.
+static NEXT_TEMP_ID: AtomicU64 = AtomicU64::new(0);
+
+#[async_trait]
+impl DocumentStore for LocalFsStore {
+    async fn read(&self, path: &str) -> io::Result<Vec<u8>> {
+        let path = path.to_string();
+        run_blocking(move || std::fs::read(path)).await
+    }
+
+    async fn write(&self, path: &str, bytes: &[u8]) -> io::Result<()> {
+        let target = PathBuf::from(path);
+        let file_name = target.file_name().and_then(|name| name.to_str()).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "path has no file name")
+        })?;
+        let temp_id = NEXT_TEMP_ID.fetch_add(1, Ordering::Relaxed);
+        let temp = target.with_file_name(format!(".{file_name}.{}.{temp_id}.tmp", std::process::id()));
+        let bytes = bytes.to_vec();
+        run_blocking(move || {
+            std::fs::write(&temp, bytes)?;
+            std::fs::rename(&temp, &target).inspect_err(|_| {
+                let _ = std::fs::remove_file(&temp);
+            })
+        }).await
+    }
+}
+
+// Runs the blocking file operation 'f' on Tokio's blocking pool.
+async fn run_blocking<R: Send + 'static>(f: impl FnOnce() -> io::Result<R> + Send + 'static) -> io::Result<R> {
+    tokio::task::spawn_blocking(f).await.map_err(io::Error::other)?
+}
+
+
+impl Document {
+    // Loads the document at 'path' from 'store', guessing its encoding like 'open'.
+    pub async fn open_with<S: DocumentStore>(store: &S, path: &str, language_id: String) -> io::Result<Self> {
+        let bytes = store.read(path).await?;
+        Ok(Self::from_bytes(path, &bytes, language_id))
+    }
+
+    // Builds a clean document from the file content 'bytes' read from 'path'.
+    pub(crate) fn from_bytes(path: &str, bytes: &[u8], language_id: String) -> Self {
+        let detection = detect_encoding(bytes);
+        let text = detection.encoding.decode(bytes);
+        let document = Document::new(Some(path.to_string()), &text, language_id);
+        *document.encoding.lock() = detection;
+        document.detect_settings();
+        document.mark_saved(&text);
+        document
+    }
+
+    // Saves the text to the document's path on the local filesystem (see 'save_with').
+    pub async fn save(&self) -> io::Result<()> {
+        self.save_with(&LocalFsStore).await
+    }
+
+    // Saves the text to the document's path in 'store', in the encoding it was opened with (and
+    // with its BOM, if it had one), after the cleanups 'settings' asks for (trimming trailing
+    // whitespace, adding a final newline). The document is clean afterwards unless it was edited
+    // while the store was writing, and observers get 'DocumentEvent::Saved'. Fails if the
+    // document has no path, with 'InvalidData' if the text can't be represented in its encoding
+    // (re-open it with another one, or save it elsewhere), or if the store fails to write.
+    pub async fn save_with<S: DocumentStore>(&self, store: &S) -> io::Result<()> {
+        let path = self.file_path.as_deref().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "document has no file path")
+        })?;
+        self.apply_save_settings().await;
+        let (text, version) = self.text_buffer.resync();
+        let detection = self.encoding();
+        let bytes = detection.encoding.encode(&text, detection.had_bom)?;
+        store.write(path, &bytes).await?;
+        // Edits made during the write aren't on disk, so they keep the document dirty.
+        if self.text_buffer.version() == version {
+            self.set_dirty(false);
+        }
+        self.mark_saved(&detection.encoding.decode(&bytes));
+        self.notify_observers(DocumentEvent::Saved { path: path.to_string() }).await;
+        Ok(())
+    }
+
+    // Like 'save', but refuses to overwrite a file that changed on disk (see 'save_checked_with').
+    pub async fn save_checked(&self) -> Result<(), DocumentError> {
+        self.save_checked_with(&LocalFsStore).await
+    }
+
+    // Like 'save_with', but first re-reads the file and fails with 'ConflictingExternalChange'
+    // if its content differs from what was loaded or last saved (including when it was deleted),
+    // so the caller can offer to merge or overwrite. A document that was never loaded or saved
+    // has nothing to compare against and is saved as usual.
+    pub async fn save_checked_with<S: DocumentStore>(&self, store: &S) -> Result<(), DocumentError> {
+        let baseline = *self.saved_hash.lock();
+        if let (Some(baseline), Some(path)) = (baseline, self.file_path.as_deref()) {
+            let on_disk = match store.read(path).await {
+                Ok(bytes) => Some(text_hash(&self.encoding().encoding.decode(&bytes))),
+                Err(error) if error.kind() == io::ErrorKind::NotFound => None,
+                Err(error) => return Err(error.into()),
+            };
+            if on_disk != Some(baseline) {
+                return Err(DocumentError::ConflictingExternalChange);
+            }
+        }
+        Ok(self.save_with(store).await?)
+    }
+}
@@ -0,0 +1,154 @@
+// Language Syntax Hints
+// A minimal description of a language's string and comment delimiters. It's not a parser; it's
+// enough for heuristics such as "don't auto-pair brackets inside a string or comment".
+use crate::TextBuffer;
+
+
+// Position Class
+// What a position lies in, as far as 'TextBuffer::classify_position' can tell.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PositionClass {
+    Code,
+    String,
+    LineComment,
+    BlockComment,
+}
+
+
+// LangSyntax Struct
+// Describes the delimiters a language uses. Build one with a preset ('rust', 'python', ...) or
+// directly for languages the crate doesn't know.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LangSyntax {
+    // Token that starts a comment running to the end of the line, e.g. "//" or "#".
+    pub line_comment: Option<String>,
+    // Opening and closing tokens of a block comment, e.g. ("/*", "*/").
+    pub block_comment: Option<(String, String)>,
+    // Characters that open and close string literals. A backslash escapes the next char.
+    pub string_delimiters: Vec<char>,
+}
+
+impl LangSyntax {
+    // Rust, C, C++, Java, JavaScript and friends.
+    pub fn c_like() -> Self {
+        Self {
+            line_comment: Some("//".to_string()),
+            block_comment: Some(("/*".to_string(), "*/".to_string())),
+            string_delimiters: vec!['"', '\''],
+        }
+    }
+
+    pub fn rust() -> Self {
+        // Rust's '\'' also marks lifetimes, so only double quotes are treated as strings.
+        Self { string_delimiters: vec!['"'], ..Self::c_like() }
+    }
+
+    pub fn python() -> Self {
+        Self {
+            line_comment: Some("#".to_string()),
+            block_comment: None,
+            string_delimiters: vec!['"', '\''],
+        }
+    }
+
+    // Picks a preset for a Document 'language_id', if one is known.
+    pub fn for_language_id(language_id: &str) -> Option<Self> {
+        match language_id {
+            "rust" => Some(Self::rust()),
+            "c" | "cpp" | "java" | "javascript" | "typescript" | "go" | "csharp" => Some(Self::c_like()),
+            "python" | "shellscript" | "toml" | "yaml" => Some(Self::python()),
+            _ => None,
+        }
+    }
+
+    // Returns true if the end of 'line_prefix' (the text of a line before some position) lies
+    // inside a string literal or comment. Only the line itself is scanned.
+    pub(crate) fn line_prefix_in_string_or_comment(&self, line_prefix: &str) -> bool {
+        self.classify_end(line_prefix) != PositionClass::Code
+    }
+
+    // Returns what the end of 'text' lies in, scanning it from the start in code. Line comments
+    // end at a line break, and so do strings unless the break is escaped: an unterminated quote
+    // can't swallow the rest of the file (the price is that multi-line raw strings aren't
+    // recognized).
+    fn classify_end(&self, text: &str) -> PositionClass {
+        let mut class = PositionClass::Code;
+        let mut delimiter = '"';
+        let mut rest = text;
+
+        while let Some(c) = rest.chars().next() {
+            match class {
+                PositionClass::BlockComment => match &self.block_comment {
+                    Some((_, close)) if rest.starts_with(close.as_str()) => {
+                        class = PositionClass::Code;
+                        rest = &rest[close.len()..];
+                    }
+                    _ => rest = &rest[c.len_utf8()..],
+                },
+                PositionClass::LineComment => {
+                    let Some(newline) = rest.find('\n') else {
+                        return class;
+                    };
+                    class = PositionClass::Code;
+                    rest = &rest[newline + 1..];
+                }
+                PositionClass::String => {
+                    if c == '\\' {
+                        // Skip the escaped char (if any).
+                        rest = &rest[c.len_utf8()..];
+                        if let Some(escaped) = rest.chars().next() {
+                            rest = &rest[escaped.len_utf8()..];
+                        }
+                        continue;
+                    }
+                    if c == delimiter || c == '\n' {
+                        class = PositionClass::Code;
+                    }
+                    rest = &rest[c.len_utf8()..];
+                }
+                PositionClass::Code => {
+                    if self.line_comment.as_deref().is_some_and(|token| rest.starts_with(token)) {
+                        class = PositionClass::LineComment;
+                        continue;
+                    }
+                    if let Some((open, _)) = &self.block_comment
+                        && rest.starts_with(open.as_str())
+                    {
+                        class = PositionClass::BlockComment;
+                        rest = &rest[open.len()..];
+                        continue;
+                    }
+                    if self.string_delimiters.contains(&c) {
+                        class = PositionClass::String;
+                        delimiter = c;
+                    }
+                    rest = &rest[c.len_utf8()..];
+                }
+            }
+        }
+        class
+    }
+}
+
+
+impl TextBuffer {
+    // Syntax Hints
+
+    // How many lines above a position 'classify_position' scans.
+    pub const CLASSIFY_LOOKBACK_LINES: usize = 500;
+
+    // Classifies 'byte_idx' as code or as inside a string or comment, by scanning forward from
+    // up to 'CLASSIFY_LOOKBACK_LINES' lines above it (taken to start in code) so block comments
+    // opened on earlier lines are seen. A position right after an opening delimiter is inside;
+    // one right after a closing delimiter is not. A heuristic for auto-pairing, bracket matching
+    // and comment toggling, not a parser (see 'classify_end'). Positions past the end are
+    // classified at the end.
+    pub fn classify_position(&self, byte_idx: usize, syntax: &LangSyntax) -> PositionClass {
+        let rope = self.content.lock();
+        let byte_idx = byte_idx.min(rope.len_bytes());
+        let line = rope.byte_to_line(byte_idx);
+        let start = rope.line_to_byte(line.saturating_sub(Self::CLASSIFY_LOOKBACK_LINES));
+        let context = rope.byte_slice(start..rope.char_to_byte(rope.byte_to_char(byte_idx))).to_string();
+        syntax.classify_end(&context)
+    }
+}
@@ -0,0 +1,112 @@
+// Transpose
+// Ctrl+T and its word-level sibling. Both work within the cursor's line: a line break is never
+// swapped, and text never moves from one line to another.
+use ropey::Rope;
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::edits::check_range;
+use crate::rope_utils::split_line_ending;
+use crate::search::is_word_char;
+use crate::{EditError, TextBuffer, TextPosition};
+
+
+// Returns the byte ranges of the words (runs of word chars) in 'text'.
+fn word_ranges(text: &str) -> Vec<(usize, usize)> {
+    let mut words = Vec::new();
+    let mut start = None;
+    for (idx, c) in text.char_indices() {
+        match (start, is_word_char(c)) {
+            (None, true) => start = Some(idx),
+            (Some(word_start), false) => {
+                words.push((word_start, idx));
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    if let Some(word_start) = start {
+        words.push((word_start, text.len()));
+    }
+    words
+}
+
+// Picks the span of the line 'content' to swap for a char transpose at 'offset' and returns it
+// with its swapped text and the cursor offset afterwards: the graphemes around the cursor, or the
+// two before it at the end of the line. None if there aren't two graphemes to swap.
+fn transposed_chars(content: &str, offset: usize) -> Option<(usize, usize, String, usize)> {
+    let before = content[..offset].graphemes(true).next_back()?;
+    match content[offset..].graphemes(true).next() {
+        Some(after) => Some((offset - before.len(), offset + after.len(), format!("{after}{before}"), offset + after.len())),
+        None => {
+            let first = content[..offset - before.len()].graphemes(true).next_back()?;
+            Some((offset - before.len() - first.len(), offset, format!("{before}{first}"), offset))
+        }
+    }
+}
+
+// Like 'transposed_chars' for words: swaps the word the cursor is in or after with the next one,
+// keeping the separator between them. Before the first word the first two are swapped, after the
+// last word the last two. The cursor ends up after the pair.
+fn transposed_words(content: &str, offset: usize) -> Option<(usize, usize, String, usize)> {
+    let words = word_ranges(content);
+    if words.len() < 2 {
+        return None;
+    }
+    let first = words.iter().rposition(|(start, _)| *start < offset).unwrap_or(0).min(words.len() - 2);
+    let ((a_start, a_end), (b_start, b_end)) = (words[first], words[first + 1]);
+    let swapped = format!("{}{}{}", &content[b_start..b_end], &content[a_end..b_start], &content[a_start..a_end]);
+    Some((a_start, b_end, swapped, b_end))
+}
+
+
+impl TextBuffer {
+    // Transpose
+
+    // Swaps the grapheme before the cursor at 'byte_idx' with the one after it and returns the
+    // cursor after both. At the end of a line the two graphemes before the cursor are swapped
+    // instead and the cursor stays. Does nothing where there aren't two graphemes on the line to
+    // swap (e.g. at a line start). Observers get a single event.
+    pub async fn transpose_chars(&self, byte_idx: usize) -> Result<TextPosition, EditError> {
+        self.transpose_in_line(byte_idx, transposed_chars).await
+    }
+
+    // Swaps the word the cursor at 'byte_idx' is in (or follows) with the next word on its line,
+    // keeping what is between them, and returns the cursor after the pair. Does nothing on a line
+    // with fewer than two words. Observers get a single event.
+    pub async fn transpose_words(&self, byte_idx: usize) -> Result<TextPosition, EditError> {
+        self.transpose_in_line(byte_idx, transposed_words).await
+    }
+
+    // Applies the swap 'pick' chooses in the line content around 'byte_idx', as one batch.
+    async fn transpose_in_line(
+        &self,
+        byte_idx: usize,
+        pick: impl FnOnce(&str, usize) -> Option<(usize, usize, String, usize)>,
+    ) -> Result<TextPosition, EditError> {
+        let (event, cursor) = {
+            let mut rope = self.content.lock();
+            check_range(&rope, byte_idx, byte_idx)?;
+            let (line_start, text) = line_around(&rope, byte_idx);
+            let content = split_line_ending(&text).0;
+            let offset = (byte_idx - line_start).min(content.len());
+            let Some((start, end, swapped, cursor)) = pick(content, offset) else {
+                return Ok(TextPosition { byte_idx });
+            };
+            let cursor = TextPosition { byte_idx: line_start + cursor };
+            if swapped == content[start..end] {
+                return Ok(cursor);
+            }
+            let edit = (line_start + start, line_start + end, swapped);
+            let (events, _) = self.apply_edits_locked(&mut rope, vec![edit])?;
+            (self.batch(&rope, events), cursor)
+        };
+        self.notify_observers(event).await;
+        Ok(cursor)
+    }
+}
+
+// Returns the start and text of the line containing 'byte_idx'.
+fn line_around(rope: &Rope, byte_idx: usize) -> (usize, String) {
+    let line = rope.byte_to_line(byte_idx);
+    (rope.line_to_byte(line), rope.line(line).to_string())
+}
@@ -0,0 +1,296 @@
+// Undo History
+// An undo/redo stack fed with the TextBuffer's change events. Edits arriving in quick succession
+// (continuous typing) are coalesced into one group so a single undo reverts the whole burst, and
+// the history is bounded both by group count and by the bytes of text it retains.
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use crate::edits::check_range;
+use crate::{EditError, EditOrigin, TextBuffer, TextBufferChangedEvent, TextRange};
+
+
+// One undoable unit: the events of a coalesced burst, in the order they were applied.
+#[derive(Debug, Clone)]
+struct UndoGroup {
+    events: Vec<TextBufferChangedEvent>,
+    // When the latest event of the group was recorded.
+    last_recorded: Instant,
+    // Text bytes retained by the group's events.
+    size_bytes: usize,
+}
+
+
+// UndoStack Entity
+// Record every event received from the buffer's observer channel with 'record', then call
+// 'undo'/'redo' to apply the inverse/original edits back to the buffer.
+// 'undo'/'redo' edit the buffer with 'EditOrigin::Undo'/'EditOrigin::Redo', so when those events
+// come back through the observer channel 'record' recognizes and skips them. Any fresh
+// ('EditOrigin::User') edit clears the redo stack, as in every other editor.
+#[derive(Debug)]
+pub struct UndoStack {
+    undo: VecDeque<UndoGroup>,
+    redo: Vec<UndoGroup>,
+    // Text bytes retained across both stacks.
+    memory_used: usize,
+    max_entries: usize,
+    max_memory: usize,
+    coalesce_window: Duration,
+    // False after undo/redo, so the next edit starts a fresh group.
+    can_coalesce: bool,
+}
+
+impl Default for UndoStack {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl UndoStack {
+    pub const DEFAULT_MAX_ENTRIES: usize = 1000;
+    pub const DEFAULT_MAX_MEMORY: usize = 64 * 1024 * 1024;
+    pub const DEFAULT_COALESCE_WINDOW: Duration = Duration::from_millis(500);
+
+    // Creates an empty history with the default limits.
+    pub fn new() -> Self {
+        Self {
+            undo: VecDeque::new(),
+            redo: Vec::new(),
+            memory_used: 0,
+            max_entries: Self::DEFAULT_MAX_ENTRIES,
+            max_memory: Self::DEFAULT_MAX_MEMORY,
+            coalesce_window: Self::DEFAULT_COALESCE_WINDOW,
+            can_coalesce: false,
+        }
+    }
+
+    // Sets the maximum number of undo groups kept; the oldest are evicted first.
+    pub fn set_max_entries(&mut self, max_entries: usize) {
+        self.max_entries = max_entries;
+        self.enforce_limits();
+    }
+
+    // Sets the maximum text bytes retained by the history; the oldest groups are evicted first.
+    // A single group larger than the limit is dropped as well, so the cap is never exceeded.
+    pub fn set_max_memory(&mut self, max_bytes: usize) {
+        self.max_memory = max_bytes;
+        self.enforce_limits();
+    }
+
+    // Sets how close together edits must be to join the same undo group.
+    // 'Duration::ZERO' disables coalescing.
+    pub fn set_coalesce_window(&mut self, window: Duration) {
+        self.coalesce_window = window;
+    }
+
+    // Ends the current group, so the next edit starts a new one regardless of timing
+    // (e.g. after the cursor was moved).
+    pub fn break_coalescing(&mut self) {
+        self.can_coalesce = false;
+    }
+
+    // Records an edit event received from the buffer.
+    // Events caused by this stack's own undo/redo are ignored.
+    pub fn record(&mut self, event: TextBufferChangedEvent) {
+        if event.origin() != EditOrigin::User {
+            return;
+        }
+        if matches!(event, TextBufferChangedEvent::Reloaded { .. } | TextBufferChangedEvent::Appended { .. }) {
+            // The text is missing from the event, so it can't be replayed, and nothing before it
+            // can be reverted.
+            self.clear();
+            return;
+        }
+        self.clear_redo();
+
+        let now = Instant::now();
+        let size = event_size(&event);
+
+        match self.undo.back_mut() {
+            Some(group) if self.can_coalesce && now.duration_since(group.last_recorded) < self.coalesce_window => {
+                group.events.push(event);
+                group.last_recorded = now;
+                group.size_bytes += size;
+            }
+            _ => self.undo.push_back(UndoGroup { events: vec![event], last_recorded: now, size_bytes: size }),
+        }
+        self.memory_used += size;
+        self.can_coalesce = true;
+        self.enforce_limits();
+    }
+
+    // Reverts the most recent group, as one edit. Returns false, leaving the group where it is,
+    // if there was nothing to undo or the buffer rejected it (it is read-only, a protected region
+    // is in the way, or the text no longer matches the history); nothing is changed then.
+    pub async fn undo(&mut self, buffer: &TextBuffer) -> bool {
+        let Some(group) = self.undo.back() else {
+            return false;
+        };
+        if buffer.apply_history(&group.events, true, EditOrigin::Undo).await.is_err() {
+            return false;
+        }
+        if let Some(group) = self.undo.pop_back() {
+            self.redo.push(group);
+        }
+        self.can_coalesce = false;
+        true
+    }
+
+    // Re-applies the most recently undone group, as one edit. Returns false like 'undo'.
+    pub async fn redo(&mut self, buffer: &TextBuffer) -> bool {
+        let Some(group) = self.redo.last() else {
+            return false;
+        };
+        if buffer.apply_history(&group.events, false, EditOrigin::Redo).await.is_err() {
+            return false;
+        }
+        if let Some(group) = self.redo.pop() {
+            self.undo.push_back(group);
+        }
+        self.can_coalesce = false;
+        true
+    }
+
+    // Returns true if there is something to undo (e.g. to enable a toolbar button).
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty()
+    }
+
+    // Returns true if there is something to redo.
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+
+    // Returns the number of undo groups, mainly for debugging.
+    pub fn undo_depth(&self) -> usize {
+        self.undo.len()
+    }
+
+    // Returns the number of redo groups, mainly for debugging.
+    pub fn redo_depth(&self) -> usize {
+        self.redo.len()
+    }
+
+    // Returns the text bytes currently retained by the history.
+    pub fn memory_used(&self) -> usize {
+        self.memory_used
+    }
+
+    // Forgets the whole history.
+    pub fn clear(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+        self.memory_used = 0;
+        self.can_coalesce = false;
+    }
+
+    fn clear_redo(&mut self) {
+        for group in self.redo.drain(..) {
+            self.memory_used -= group.size_bytes;
+        }
+    }
+
+    fn enforce_limits(&mut self) {
+        while self.undo.len() > self.max_entries || (self.memory_used > self.max_memory && !self.undo.is_empty()) {
+            if let Some(evicted) = self.undo.pop_front() {
+                self.memory_used -= evicted.size_bytes;
+            }
+        }
+    }
+}
+
+
+fn event_size(event: &TextBufferChangedEvent) -> usize {
+    event.events().iter()
+        .map(|member| match member {
+            TextBufferChangedEvent::Inserted { text, .. } | TextBufferChangedEvent::Removed { text, .. } => text.len(),
+            TextBufferChangedEvent::Reloaded { .. } | TextBufferChangedEvent::Appended { .. } | TextBufferChangedEvent::Batch(_) => 0,
+        })
+        .sum()
+}
+
+
+// One step of reverting or re-applying history: 'text' is inserted at, or removed from,
+// 'start_byte_idx'.
+struct HistoryStep<'a> {
+    start_byte_idx: usize,
+    text: &'a str,
+    insert: bool,
+}
+
+// Returns the steps re-applying 'events' or, with 'invert', undoing them, in the order they must
+// be applied.
+fn history_steps(events: &[TextBufferChangedEvent], invert: bool) -> Result<Vec<HistoryStep<'_>>, EditError> {
+    let members = events.iter().flat_map(TextBufferChangedEvent::events);
+    let step = |member| history_step(member, invert);
+    if invert { members.rev().map(step).collect() } else { members.map(step).collect() }
+}
+
+fn history_step(member: &TextBufferChangedEvent, invert: bool) -> Result<HistoryStep<'_>, EditError> {
+    match member {
+        TextBufferChangedEvent::Inserted { start_byte_idx, text, .. } => {
+            Ok(HistoryStep { start_byte_idx: *start_byte_idx, text, insert: !invert })
+        }
+        TextBufferChangedEvent::Removed { start_byte_idx, text, .. } => {
+            Ok(HistoryStep { start_byte_idx: *start_byte_idx, text, insert: invert })
+        }
+        TextBufferChangedEvent::Reloaded { .. } | TextBufferChangedEvent::Appended { .. } | TextBufferChangedEvent::Batch(_) => {
+            Err(EditError::NotInvertible)
+        }
+    }
+}
+
+
+impl TextBuffer {
+    // External Undo
+
+    // Reverts 'event' for hosts that keep their own history: re-inserts what it removed and
+    // removes what it inserted, as one edit tagged 'EditOrigin::Undo' (a single event, or a
+    // 'Batch' when the inverse has several steps). It must be the most recent change to the
+    // text it touched: if the inserted text is no longer where the event says, it fails with
+    // 'TextMismatch'. 'Reloaded' and 'Appended' events fail with 'NotInvertible'. Every step is
+    // checked before anything is applied, so a failure leaves the buffer untouched.
+    pub async fn apply_inverse(&self, event: &TextBufferChangedEvent) -> Result<(), EditError> {
+        self.apply_history(std::slice::from_ref(event), true, EditOrigin::Undo).await
+    }
+
+    // Re-applies 'events' (or undoes them, with 'invert') as one edit tagged 'origin'. Every step
+    // is checked on a scratch copy first, so a rejected step leaves the buffer untouched.
+    async fn apply_history(&self, events: &[TextBufferChangedEvent], invert: bool, origin: EditOrigin) -> Result<(), EditError> {
+        let steps = history_steps(events, invert)?;
+        let edit = {
+            let mut rope = self.content.lock();
+            let mut scratch = rope.clone();
+            for step in &steps {
+                let end = if step.insert { step.start_byte_idx } else { step.start_byte_idx + step.text.len() };
+                check_range(&scratch, step.start_byte_idx, end)?;
+                let range = TextRange::new(step.start_byte_idx, end);
+                if step.insert {
+                    self.check_edit(&scratch, range, step.text, origin)?;
+                    scratch.insert(scratch.byte_to_char(step.start_byte_idx), step.text);
+                } else {
+                    if scratch.byte_slice(range.start..range.end) != step.text {
+                        return Err(EditError::TextMismatch);
+                    }
+                    self.check_edit(&scratch, range, "", origin)?;
+                    scratch.remove(scratch.byte_to_char(range.start)..scratch.byte_to_char(range.end));
+                }
+            }
+            let mut events: Vec<_> = steps.iter()
+                .map(|step| if step.insert {
+                    self.apply_insert_unchecked(&mut rope, step.start_byte_idx, step.text, origin)
+                } else {
+                    self.apply_remove_unchecked(&mut rope, step.start_byte_idx, step.text.len(), origin)
+                })
+                .collect();
+            match events.len() {
+                0 => None,
+                1 => Some(events.remove(0)),
+                _ => Some(self.batch(&rope, events)),
+            }
+        };
+        if let Some(edit) = edit {
+            self.notify_observers(edit).await;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,41 @@
+// View Requests
+// Requests for the views showing a buffer that aren't edits ("scroll to this range" after
+// go-to-definition or a search hit). They travel on their own channel, separate from
+// 'TextBufferChangedEvent', so change observers never see them.
+use tokio::sync::mpsc;
+
+use crate::{TextBuffer, TextRange};
+
+
+// View Request
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ViewRequest {
+    // Scroll so that 'range' is visible.
+    Reveal(TextRange),
+}
+
+
+impl TextBuffer {
+    // View Requests
+
+    // Creates a channel holding up to 'buffer_size' view requests and returns its receiving end.
+    // Dropping the receiver unsubscribes on the next request.
+    pub fn subscribe_view_requests(&self, buffer_size: usize) -> mpsc::Receiver<ViewRequest> {
+        let (sender, receiver) = mpsc::channel(buffer_size);
+        self.view_subscribers.lock().push(sender);
+        receiver
+    }
+
+    // Asks every subscribed view to reveal 'range'. Never waits: a view whose channel is full
+    // misses the request, as only the latest scroll target matters to it.
+    pub fn request_reveal(&self, range: TextRange) {
+        self.send_view_request(ViewRequest::Reveal(range));
+    }
+
+    fn send_view_request(&self, request: ViewRequest) {
+        self.view_subscribers.lock().retain(|sender| match sender.try_send(request.clone()) {
+            Ok(()) | Err(mpsc::error::TrySendError::Full(_)) => true,
+            Err(mpsc::error::TrySendError::Closed(_)) => false,
+        });
+    }
+}
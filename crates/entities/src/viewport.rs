@@ -0,0 +1,36 @@
+// Viewport
+// The lines a renderer draws, read in one go. Fetching each line and its offset separately locks
+// the buffer once per call, and an edit landing between the calls tears the picture (line
+// contents that don't match their offsets, or a line count that doesn't match either).
+use crate::rope_utils::line_content_len;
+use crate::TextBuffer;
+
+
+// Viewport Struct
+// 'lines' holds the start byte and the text (without its line break) of each visible line, in
+// order; 'total_lines' is the buffer's line count at the same moment, for sizing the scroll bar.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Viewport {
+    pub lines: Vec<(usize, String)>,
+    pub total_lines: usize,
+}
+
+
+impl TextBuffer {
+    // Viewport
+
+    // Returns up to 'line_count' lines from 'first_line' on, all read under one lock. Lines past
+    // the end of the buffer are left out, so 'lines' is empty if 'first_line' is past it.
+    pub fn viewport(&self, first_line: usize, line_count: usize) -> Viewport {
+        let rope = self.content.lock();
+        let total_lines = rope.len_lines();
+        let end = first_line.saturating_add(line_count).min(total_lines);
+        let lines = (first_line.min(end)..end)
+            .map(|line| {
+                let text = rope.line(line).byte_slice(..line_content_len(&rope, line)).to_string();
+                (rope.line_to_byte(line), text)
+            })
+            .collect();
+        Viewport { lines, total_lines }
+    }
+}
@@ -0,0 +1,149 @@
+// Soft Wrap
+// Splits logical lines into visual rows for word wrap. Widths are measured in terminal-style
+// cells: tabs expand to the next tab stop, East Asian wide chars and emoji take two cells and
+// combining marks none (a grapheme cluster is as wide as its first char).
+use std::ops::Range;
+
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::rope_utils::split_line_ending;
+use crate::{TextBuffer, TextRange};
+
+
+// Returns the number of cells 'c' occupies. An approximation of Unicode East Asian Width that
+// covers the common wide blocks without pulling in the full tables.
+pub(crate) fn char_width(c: char) -> usize {
+    match c as u32 {
+        0x0300..=0x036F | 0x200B..=0x200F | 0xFE00..=0xFE0F => 0,
+        0x1100..=0x115F
+        | 0x2E80..=0x303E
+        | 0x3041..=0x33FF
+        | 0x3400..=0x4DBF
+        | 0x4E00..=0x9FFF
+        | 0xA000..=0xA4CF
+        | 0xAC00..=0xD7A3
+        | 0xF900..=0xFAFF
+        | 0xFE30..=0xFE4F
+        | 0xFF00..=0xFF60
+        | 0xFFE0..=0xFFE6
+        | 0x1F300..=0x1F64F
+        | 0x1F900..=0x1F9FF
+        | 0x20000..=0x3FFFD => 2,
+        _ => 1,
+    }
+}
+
+// Returns the cells taken by 'grapheme' when it starts at cell 'column'.
+fn grapheme_width(grapheme: &str, column: usize, tab_width: usize) -> usize {
+    match grapheme.chars().next() {
+        Some('\t') => tab_width - column % tab_width,
+        Some(c) => char_width(c),
+        None => 0,
+    }
+}
+
+// Returns the cells 'text' takes when it starts at cell 0.
+fn display_width(text: &str, tab_width: usize) -> usize {
+    text.graphemes(true).fold(0, |column, g| column + grapheme_width(g, column, tab_width))
+}
+
+// Shortens 's' to at most 'max_cols' cells (measured like the rest of this module), for tab
+// titles and breadcrumbs: if it doesn't fit, it is cut between graphemes and '…' (one cell) is
+// appended. Text that fits is returned unchanged.
+pub fn truncate_display(s: &str, max_cols: usize, tab_width: usize) -> String {
+    let tab_width = tab_width.max(1);
+    if display_width(s, tab_width) <= max_cols {
+        return s.to_string();
+    }
+    let mut out = String::new();
+    let mut column = 0;
+    for grapheme in s.graphemes(true) {
+        let width = grapheme_width(grapheme, column, tab_width);
+        if column + width + 1 > max_cols {
+            break;
+        }
+        out.push_str(grapheme);
+        column += width;
+    }
+    if max_cols > 0 {
+        out.push('…');
+    }
+    out
+}
+
+
+impl TextBuffer {
+    // Soft Wrap
+
+    // Returns the number of cells 'line' takes on screen, without its line break, measured like
+    // 'wrapped_lines' does without wrapping. Returns None if the line doesn't exist.
+    pub fn line_display_width(&self, line: usize, tab_width: usize) -> Option<usize> {
+        let rope = self.content.lock();
+        let text = rope.get_line(line)?.to_string();
+        Some(display_width(split_line_ending(&text).0, tab_width.max(1)))
+    }
+
+    // Returns the display width of each line in 'lines' (see 'line_display_width'), for sizing
+    // a horizontal scroll area. Lines past the end of the buffer are left out.
+    pub fn line_display_widths(&self, lines: Range<usize>, tab_width: usize) -> Vec<usize> {
+        let rope = self.content.lock();
+        let end = lines.end.min(rope.len_lines());
+        (lines.start.min(end)..end)
+            .map(|line| display_width(split_line_ending(&rope.line(line).to_string()).0, tab_width.max(1)))
+            .collect()
+    }
+
+    // Splits 'line' into visual rows at most 'wrap_cols' cells wide and returns each row's byte
+    // range, without the line break. Rows break before a word that doesn't fit; whitespace after
+    // a word stays at the end of its row (it may overhang). A word wider than a whole row is
+    // broken between graphemes. Tabs expand relative to the start of their row.
+    // An empty line has one empty row; 'wrap_cols' of 0 disables wrapping. Returns no rows if the
+    // line doesn't exist.
+    pub fn wrapped_lines(&self, line: usize, wrap_cols: usize, tab_width: usize) -> Vec<TextRange> {
+        let (line_start, text) = {
+            let rope = self.content.lock();
+            let Some(slice) = rope.get_line(line) else {
+                return Vec::new();
+            };
+            (rope.line_to_byte(line), slice.to_string())
+        };
+        let content = split_line_ending(&text).0;
+        let tab_width = tab_width.max(1);
+        if wrap_cols == 0 {
+            return vec![TextRange::new(line_start, line_start + content.len())];
+        }
+
+        let mut rows = Vec::new();
+        let (mut row_start, mut column) = (0, 0);
+        for (word_start, word) in content.split_word_bounds().scan(0, |offset, word| {
+            let start = *offset;
+            *offset += word.len();
+            Some((start, word))
+        }) {
+            let is_space = word.chars().all(char::is_whitespace);
+            let word_width = |column: usize| {
+                word.graphemes(true).fold(column, |column, g| column + grapheme_width(g, column, tab_width)) - column
+            };
+            if is_space || column + word_width(column) <= wrap_cols {
+                column += word_width(column);
+                continue;
+            }
+            if column > 0 {
+                rows.push(TextRange::new(line_start + row_start, line_start + word_start));
+                row_start = word_start;
+                column = 0;
+            }
+            // Hard-break a word that is still too wide for a row of its own.
+            for (offset, grapheme) in word.grapheme_indices(true) {
+                if column > 0 && column + grapheme_width(grapheme, column, tab_width) > wrap_cols {
+                    rows.push(TextRange::new(line_start + row_start, line_start + word_start + offset));
+                    row_start = word_start + offset;
+                    column = 0;
+                }
+                column += grapheme_width(grapheme, column, tab_width);
+            }
+        }
+        rows.push(TextRange::new(line_start + row_start, line_start + content.len()));
+        rows
+    }
+}
@@ -0,0 +1,101 @@
+// Buffer Writer
+// 'std::fmt::Write' adapter for generating text straight into a buffer with 'write!' and friends.
+use std::fmt;
+use std::sync::Arc;
+
+use parking_lot::MutexGuard;
+use ropey::Rope;
+
+use crate::{rope_utils, EditKind, EditOrigin, TextBuffer, TextBufferChangedEvent, TextPosition, TextRange};
+
+
+// BufferWriter Struct
+// Inserts everything written to it at a position that advances with each write. The buffer stays
+// locked while the writer exists, so no other edit can land in the middle of the output, and
+// observers get a single 'Inserted' event covering all of it when 'finish' is called.
+// Dropping the writer instead (e.g. on an early return from 'write!(..)?') still records the
+// edit, so the version, 'changes_since' and the stats stay right, but observers never hear
+// about it.
+// Each write is checked like an insertion ('fmt::Error' inside a protected region or when a
+// pre-edit observer vetoes it); the text written before a failed write stays.
+#[must_use = "call 'finish' to notify observers of the written text"]
+pub struct BufferWriter<'a> {
+    buffer: &'a TextBuffer,
+    rope: MutexGuard<'a, Rope>,
+    start: usize,
+    position: usize,
+}
+
+impl<'a> BufferWriter<'a> {
+    // Starts writing at 'position'.
+    pub fn new(buffer: &'a TextBuffer, position: TextPosition) -> Self {
+        Self { buffer, rope: buffer.content.lock(), start: position.byte_idx, position: position.byte_idx }
+    }
+
+    // Returns the position the next write goes to.
+    pub fn position(&self) -> TextPosition {
+        TextPosition { byte_idx: self.position }
+    }
+
+    // Releases the buffer, notifies observers and returns the position after the written text.
+    pub async fn finish(mut self) -> TextPosition {
+        let buffer = self.buffer;
+        let event = self.record();
+        let position = self.position();
+        drop(self);
+        if let Some(event) = event {
+            buffer.notify_observers(event).await;
+        }
+        position
+    }
+
+    // Bumps the version, logs the change and counts the words for everything written since the
+    // last call, and builds its event.
+    fn record(&mut self) -> Option<TextBufferChangedEvent> {
+        let (buffer, rope, start, position) = (self.buffer, &self.rope, self.start, self.position);
+        if position == start {
+            return None;
+        }
+        self.start = position;
+        let (start_line, end_line) = (rope.byte_to_line(start), rope.byte_to_line(position));
+        let text = rope.byte_slice(start..position).to_string();
+        let event = TextBufferChangedEvent::Inserted {
+            start_byte_idx: start,
+            start_line_col: rope_utils::line_col(rope, start),
+            len_bytes: position - start,
+            kind: EditKind::classify(&text, || rope_utils::line_prefix(rope, start)),
+            text: Arc::from(text),
+            dirty_line_range: start_line..end_line + 1,
+            affects_line_count: end_line != start_line,
+            origin: EditOrigin::User,
+            version: buffer.bump_version(),
+        };
+        buffer.changes.lock().record(&event);
+        buffer.count_inserted_words(rope, start, position);
+        Some(buffer.coarsen(rope, event, position - start, rope.len_bytes()))
+    }
+}
+
+impl Drop for BufferWriter<'_> {
+    fn drop(&mut self) {
+        self.record();
+    }
+}
+
+impl fmt::Write for BufferWriter<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        // Out of bounds or inside a char.
+        if self.rope.get_byte_slice(self.position..self.position).is_none() {
+            return Err(fmt::Error);
+        }
+        let range = TextRange::new(self.position, self.position);
+        if self.buffer.check_edit(&self.rope, range, s, EditOrigin::User).is_err() {
+            return Err(fmt::Error);
+        }
+        let char_idx = self.rope.byte_to_char(self.position);
+        self.rope.insert(char_idx, s);
+        self.buffer.anchors.lock().shift_for_insert(self.position, s.len());
+        self.position += s.len();
+        Ok(())
+    }
+}
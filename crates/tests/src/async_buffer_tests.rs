@@ -0,0 +1,22 @@
+// AsyncTextBuffer Tests
+use entities::{AsyncTextBuffer, TextPosition};
+use tokio::sync::mpsc;
+
+#[tokio::test]
+async fn read_guard_can_be_held_across_awaits() {
+    let buffer = AsyncTextBuffer::new("hello");
+    let (tx, mut rx) = mpsc::channel(4);
+    buffer.add_observer(tx);
+
+    {
+        let rope = buffer.read().await;
+        tokio::task::yield_now().await;
+        assert_eq!(rope.len_bytes(), 5);
+    }
+
+    buffer.insert(TextPosition { byte_idx: 5 }, " world").await;
+    assert_eq!(rx.recv().await.unwrap().dirty_line_range(), 0..1);
+    buffer.remove(TextPosition { byte_idx: 0 }, 6).await;
+    assert_eq!(buffer.get_text().await, "world");
+    assert_eq!(buffer.len_lines().await, 1);
+}
@@ -0,0 +1,125 @@
+// Concurrency Tests
+// Stress the buffer from several threads at once. These exercise interleavings rather than exact
+// outcomes, so they check invariants that must hold whatever the scheduling was.
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use entities::{ISyncTextBufferObserver, TextBuffer, TextBufferChangedEvent, TextPosition};
+use tokio::sync::mpsc;
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn observers_churning_during_notification_are_pruned_correctly() {
+    let buffer = Arc::new(TextBuffer::new(""));
+
+    // One task keeps editing while others register observers and drop half of them again,
+    // often in the middle of a notification.
+    let editor = {
+        let buffer = Arc::clone(&buffer);
+        tokio::spawn(async move {
+            for _ in 0..200 {
+                buffer.insert(TextPosition { byte_idx: 0 }, "x").await;
+                tokio::task::yield_now().await;
+            }
+        })
+    };
+    let churners: Vec<_> = (0..4).map(|_| {
+        let buffer = Arc::clone(&buffer);
+        tokio::spawn(async move {
+            let mut kept = Vec::new();
+            for i in 0..50 {
+                let (tx, rx) = mpsc::channel(1024);
+                buffer.add_observer(tx);
+                if i % 2 == 0 {
+                    kept.push(rx);
+                }
+                tokio::task::yield_now().await;
+            }
+            kept
+        })
+    }).collect();
+
+    editor.await.unwrap();
+    let mut kept = Vec::new();
+    for churner in churners {
+        kept.extend(churner.await.unwrap());
+    }
+
+    // One more edit prunes every dropped receiver, and only those.
+    buffer.insert(TextPosition { byte_idx: 0 }, "!").await;
+    assert_eq!(buffer.observer_count(), kept.len());
+    let final_version = buffer.version();
+    for mut rx in kept {
+        let mut last = None;
+        while let Ok(event) = rx.try_recv() {
+            last = Some(event.version());
+        }
+        assert_eq!(last, Some(final_version));
+    }
+}
+
+// Counts the notifications that have started.
+#[derive(Default)]
+struct StartedNotifications(AtomicUsize);
+
+impl ISyncTextBufferObserver for StartedNotifications {
+    fn on_buffer_changed(&self, _event: &TextBufferChangedEvent) {
+        self.0.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn flush_notifications_waits_for_sends_in_progress() {
+    let buffer = Arc::new(TextBuffer::new(""));
+    let started = Arc::new(StartedNotifications::default());
+    buffer.add_sync_observer(started.clone());
+    let (tx, mut rx) = mpsc::channel(1);
+    buffer.add_observer(tx);
+
+    let editor = {
+        let buffer = Arc::clone(&buffer);
+        tokio::spawn(async move {
+            buffer.insert(TextPosition { byte_idx: 0 }, "a").await;
+            buffer.insert(TextPosition { byte_idx: 1 }, "b").await;
+        })
+    };
+    // The second notification is now stuck on the full channel.
+    while started.0.load(Ordering::SeqCst) < 2 {
+        tokio::task::yield_now().await;
+    }
+    let flushed = Arc::new(AtomicBool::new(false));
+    let flusher = {
+        let (buffer, flushed) = (Arc::clone(&buffer), Arc::clone(&flushed));
+        tokio::spawn(async move {
+            buffer.flush_notifications().await;
+            flushed.store(true, Ordering::SeqCst);
+        })
+    };
+    std::thread::sleep(std::time::Duration::from_millis(20));
+    assert!(!flushed.load(Ordering::SeqCst));
+
+    assert!(rx.recv().await.is_some());
+    assert!(rx.recv().await.is_some());
+    flusher.await.unwrap();
+    assert!(flushed.load(Ordering::SeqCst));
+    editor.await.unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn resync_pairs_text_with_its_version() {
+    let buffer = Arc::new(TextBuffer::new(""));
+    let writer = {
+        let buffer = Arc::clone(&buffer);
+        tokio::spawn(async move {
+            for i in 0..500 {
+                buffer.insert(TextPosition { byte_idx: i }, "x").await;
+            }
+        })
+    };
+    // Every edit inserts one byte, so the length always equals the version.
+    for _ in 0..500 {
+        let (text, version) = buffer.resync();
+        assert_eq!(text.len() as u64, version);
+        tokio::task::yield_now().await;
+    }
+    writer.await.unwrap();
+}
@@ -0,0 +1,468 @@
+// Document Tests
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use entities::{detect_encoding, truncate_display, Document, DocMetrics, DocOrder, DocumentBuilder, DocumentEvent, DocumentManager, DocumentSettings, DocumentStore, EditError, Encoding, Indentation, LineEnding, TextPosition, WorkspaceEditError};
+use tokio::sync::mpsc;
+
+#[tokio::test]
+async fn set_language_reports_changes() {
+    let document = Document::new(Some("notes.txt".to_string()), "", "plaintext".to_string());
+    let (tx, mut rx) = mpsc::channel(4);
+    document.add_observer(tx);
+
+    // Setting the same language again is not a change.
+    document.set_language("plaintext".to_string()).await;
+    document.set_language("rust".to_string()).await;
+    assert_eq!(document.language_id(), "rust");
+    assert_eq!(
+        rx.recv().await.unwrap(),
+        DocumentEvent::LanguageChanged { old: "plaintext".to_string(), new: "rust".to_string() },
+    );
+    assert!(rx.try_recv().is_err());
+}
+
+#[tokio::test]
+async fn open_guesses_the_encoding_and_can_reopen() {
+    assert_eq!(detect_encoding(b"\xFF\xFEh\0i\0").encoding, Encoding::Utf16Le);
+    assert_eq!(detect_encoding(b"\0h\0i").encoding, Encoding::Utf16Be);
+    assert!(!detect_encoding("caf\u{e9}".as_bytes()).is_guess());
+
+    let path = std::env::temp_dir().join(format!("entities-encoding-{}.txt", std::process::id()));
+    std::fs::write(&path, b"caf\xE9\n").unwrap();
+    let document = Document::open(path.to_str().unwrap(), "plaintext".to_string()).await.unwrap();
+    assert_eq!(document.encoding().encoding, Encoding::Latin1);
+    assert!(document.encoding().is_guess());
+    assert_eq!(document.text_buffer.get_text(), "caf\u{e9}\n");
+    // The file is compared as decoded text, not as (invalid) UTF-8.
+    assert_eq!(document.diff_against_disk().await.unwrap(), "");
+
+    document.reopen_with_encoding(Encoding::Utf8).await.unwrap();
+    assert_eq!(document.text_buffer.get_text(), "caf\u{fffd}\n");
+    assert!(!document.encoding().is_guess());
+    std::fs::remove_file(path).unwrap();
+}
+
+#[tokio::test]
+async fn changed_lines_compare_against_the_file() {
+    let path = std::env::temp_dir().join(format!("entities-changed-{}.txt", std::process::id()));
+    std::fs::write(&path, "one\ntwo\nthree\n").unwrap();
+    let document = Document::open(path.to_str().unwrap(), "plaintext".to_string()).await.unwrap();
+    assert_eq!(document.changed_lines().await.unwrap(), Vec::<usize>::new());
+
+    let buffer = document.get_text_buffer();
+    buffer.insert(TextPosition { byte_idx: 4 }, "2").await;
+    buffer.insert(TextPosition { byte_idx: 0 }, "zero\n").await;
+    buffer.remove(TextPosition { byte_idx: 14 }, 6).await;
+    assert_eq!(buffer.get_text(), "zero\none\n2two\n");
+    assert_eq!(document.changed_lines().await.unwrap(), vec![0, 2]);
+    std::fs::remove_file(path).unwrap();
+}
+
+// A store keeping files in memory, standing in for a remote backend.
+#[derive(Default)]
+struct MemoryStore {
+    files: Mutex<HashMap<String, Vec<u8>>>,
+}
+
+#[async_trait::async_trait]
+impl DocumentStore for MemoryStore {
+    async fn read(&self, path: &str) -> std::io::Result<Vec<u8>> {
+        self.files.lock().unwrap().get(path).cloned().ok_or_else(|| std::io::ErrorKind::NotFound.into())
+    }
+
+    async fn write(&self, path: &str, bytes: &[u8]) -> std::io::Result<()> {
+        self.files.lock().unwrap().insert(path.to_string(), bytes.to_vec());
+        Ok(())
+    }
+}
+
+#[tokio::test]
+async fn documents_load_and_save_through_a_store() {
+    let store = MemoryStore::default();
+    store.write("remote/a.txt", b"\xFF\xFEh\0i\0").await.unwrap();
+    assert!(Document::open_with(&store, "remote/missing.txt", "plaintext".to_string()).await.is_err());
+
+    let document = Document::open_with(&store, "remote/a.txt", "plaintext".to_string()).await.unwrap();
+    assert_eq!(document.text_buffer.get_text(), "hi");
+    document.text_buffer.insert(TextPosition { byte_idx: 2 }, "!").await;
+    document.set_dirty(true);
+    document.save_with(&store).await.unwrap();
+
+    // Saved back in the encoding it was read with, BOM included.
+    assert_eq!(store.read("remote/a.txt").await.unwrap(), b"\xFF\xFEh\0i\0!\0");
+    assert!(!document.is_dirty());
+    assert_eq!(document.changed_lines_with(&store).await.unwrap(), Vec::<usize>::new());
+
+    // Diffs, gutter markers and re-opening read the store too.
+    document.text_buffer.insert(TextPosition { byte_idx: 3 }, "?").await;
+    assert_eq!(document.changed_lines_with(&store).await.unwrap(), vec![0]);
+    assert!(document.diff_against_disk_with(&store).await.unwrap().contains("-hi!\n"));
+    document.reopen_with_encoding_with(&store, Encoding::Utf16Le).await.unwrap();
+    assert_eq!(document.text_buffer.get_text(), "hi!");
+    assert!(document.diff_against_disk().await.is_err());
+}
+
+#[tokio::test]
+async fn settings_are_detected_and_drive_save_cleanups() {
+    let store = MemoryStore::default();
+    store.write("a.py", b"def f():\r\n  x = 1  \r\n  if x:\r\n    pass").await.unwrap();
+    let document = Document::open_with(&store, "a.py", "python".to_string()).await.unwrap();
+    let settings = document.settings();
+    assert_eq!(settings.indentation, Indentation { soft_tabs: true, tab_width: 2 });
+    assert_eq!(settings.line_ending, LineEnding::CrLf);
+
+    document.set_settings(DocumentSettings {
+        trim_trailing_whitespace_on_save: true,
+        ensure_final_newline_on_save: true,
+        ..settings
+    });
+    document.save_with(&store).await.unwrap();
+    assert_eq!(store.read("a.py").await.unwrap(), b"def f():\r\n  x = 1\r\n  if x:\r\n    pass\r\n");
+
+    // A read-only document is written exactly as it is.
+    let cleanups = DocumentSettings { trim_trailing_whitespace_on_save: true, ensure_final_newline_on_save: true, ..settings };
+    let view = DocumentBuilder::new().path("b.txt").initial_content("x  ").settings(cleanups).read_only(true).build();
+    view.save_with(&store).await.unwrap();
+    assert_eq!(store.read("b.txt").await.unwrap(), b"x  ");
+    assert!(!view.text_buffer.ensure_final_newline(LineEnding::Lf).await);
+}
+
+#[tokio::test]
+async fn save_all_saves_dirty_documents_and_reports_each() {
+    let store = MemoryStore::default();
+    let manager = DocumentManager::new();
+    let a = manager.add(Document::new(Some("a.txt".to_string()), "a", "plaintext".to_string()));
+    manager.add(Document::new(Some("clean.txt".to_string()), "c", "plaintext".to_string()));
+    let untitled = manager.add(Document::new(None, "u", "plaintext".to_string()));
+    a.set_dirty(true);
+    untitled.set_dirty(true);
+
+    let results = manager.save_all_with(&store).await;
+    assert_eq!(results.len(), 2);
+    assert_eq!(results[0].0, "a.txt");
+    assert!(results[0].1.is_ok());
+    assert_eq!(results[1].0, "untitled");
+    assert_eq!(results[1].1.as_ref().unwrap_err().kind(), std::io::ErrorKind::InvalidInput);
+    assert_eq!(store.read("a.txt").await.unwrap(), b"a");
+    assert!(store.read("clean.txt").await.is_err());
+    assert!(!a.is_dirty() && untitled.is_dirty());
+
+    assert!(manager.remove(&untitled));
+    assert_eq!(manager.documents().len(), 2);
+}
+
+#[tokio::test]
+async fn builder_configures_every_option() {
+    let settings = DocumentSettings { wrap_columns: Some(80), ..DocumentSettings::default() };
+    let document = DocumentBuilder::new()
+        .path("scratch.txt")
+        .language("markdown")
+        .encoding(Encoding::Latin1)
+        .initial_content("café")
+        .settings(settings.clone())
+        .read_only(true)
+        .dirty(true)
+        .build();
+    assert_eq!(document.file_path.as_deref(), Some("scratch.txt"));
+    assert_eq!(document.language_id(), "markdown");
+    assert_eq!(document.encoding().encoding, Encoding::Latin1);
+    assert_eq!(document.settings(), settings);
+    assert!(document.is_read_only() && document.is_dirty());
+
+    assert_eq!(document.text_buffer.replace(entities::TextRange::new(0, 1), "C").await, Err(entities::EditError::Vetoed));
+    assert_eq!(document.text_buffer.backspace(entities::TextRange::new(5, 5)).await, Err(EditError::Vetoed));
+    assert_eq!(document.text_buffer.cut(entities::TextRange::new(0, 2)).await, Err(EditError::Vetoed));
+    assert_eq!(document.insert_tab(TextPosition { byte_idx: 0 }).await, Err(EditError::Vetoed));
+    assert_eq!(document.text_buffer.get_text(), "café");
+
+    let plain = DocumentBuilder::new().build();
+    assert!(plain.file_path.is_none() && !plain.is_read_only() && !plain.is_dirty());
+    assert_eq!(plain.language_id(), "plaintext");
+}
+
+#[tokio::test]
+async fn open_at_clamps_the_target_to_the_file() {
+    let path = std::env::temp_dir().join(format!("entities-open-at-{}.txt", std::process::id()));
+    std::fs::write(&path, "fn main() {\n    é!\n}\n").unwrap();
+    let path_str = path.to_str().unwrap();
+
+    let (document, position) = Document::open_at(path_str, 1, 5, "rust".to_string()).await.unwrap();
+    assert_eq!(document.text_buffer.get_text().len(), 22);
+    assert_eq!(position, TextPosition { byte_idx: 18 });
+    // Past the line's end, and past the last line.
+    assert_eq!(Document::open_at(path_str, 1, 50, "rust".to_string()).await.unwrap().1, TextPosition { byte_idx: 19 });
+    assert_eq!(Document::open_at(path_str, 120, 8, "rust".to_string()).await.unwrap().1, TextPosition { byte_idx: 21 });
+    std::fs::remove_file(&path).unwrap();
+    assert!(Document::open_at(path_str, 0, 0, "rust".to_string()).await.is_err());
+}
+
+#[tokio::test]
+async fn concurrent_local_saves_of_one_path_do_not_mix() {
+    let dir = std::env::temp_dir().join(format!("entities-concurrent-save-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("shared.txt").to_str().unwrap().to_string();
+    let ours = Document::new(Some(path.clone()), &"a".repeat(100_000), "plaintext".to_string());
+    let theirs = Document::new(Some(path.clone()), &"b".repeat(50_000), "plaintext".to_string());
+
+    let (a, b) = tokio::join!(ours.save(), theirs.save());
+    a.unwrap();
+    b.unwrap();
+    let saved = std::fs::read_to_string(&path).unwrap();
+    assert!(saved == ours.text_buffer.get_text() || saved == theirs.text_buffer.get_text());
+    // Both temporary files were renamed away.
+    assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn checked_save_refuses_to_clobber_external_changes() {
+    let store = MemoryStore::default();
+    store.write("shared.txt", b"original\n").await.unwrap();
+    let document = Document::open_with(&store, "shared.txt", "plaintext".to_string()).await.unwrap();
+    document.text_buffer.insert(TextPosition { byte_idx: 0 }, "mine: ").await;
+    assert!(document.save_checked_with(&store).await.is_ok());
+    assert_eq!(store.read("shared.txt").await.unwrap(), b"mine: original\n");
+
+    store.write("shared.txt", b"theirs\n").await.unwrap();
+    document.text_buffer.insert(TextPosition { byte_idx: 0 }, "again ").await;
+    let result = document.save_checked_with(&store).await;
+    assert!(matches!(result, Err(entities::DocumentError::ConflictingExternalChange)));
+    assert_eq!(store.read("shared.txt").await.unwrap(), b"theirs\n");
+
+    // Overwriting deliberately makes the new content the baseline.
+    document.save_with(&store).await.unwrap();
+    assert!(document.save_checked_with(&store).await.is_ok());
+}
+
+#[tokio::test]
+async fn saving_refuses_text_the_encoding_cannot_hold() {
+    assert_eq!(Encoding::Latin1.encode("café", false).unwrap(), b"caf\xE9");
+    assert_eq!(Encoding::Latin1.encode("5 €", false).unwrap_err().kind(), std::io::ErrorKind::InvalidData);
+
+    let store = MemoryStore::default();
+    store.write("latin.txt", b"caf\xE9\n").await.unwrap();
+    let document = Document::open_with(&store, "latin.txt", "plaintext".to_string()).await.unwrap();
+    document.text_buffer.insert(TextPosition { byte_idx: 0 }, "€ ").await;
+    for _ in 0..2 {
+        let result = document.save_checked_with(&store).await;
+        assert!(matches!(result, Err(entities::DocumentError::Io(error)) if error.kind() == std::io::ErrorKind::InvalidData));
+    }
+    assert_eq!(store.read("latin.txt").await.unwrap(), b"caf\xE9\n");
+
+    document.text_buffer.remove(TextPosition { byte_idx: 0 }, 4).await;
+    assert!(document.save_checked_with(&store).await.is_ok());
+    assert!(document.save_checked_with(&store).await.is_ok());
+}
+
+// A store the user keeps typing into while it writes.
+struct TypingStore {
+    files: MemoryStore,
+    buffer: std::sync::Arc<entities::TextBuffer>,
+}
+
+#[async_trait::async_trait]
+impl DocumentStore for TypingStore {
+    async fn read(&self, path: &str) -> std::io::Result<Vec<u8>> {
+        self.files.read(path).await
+    }
+
+    async fn write(&self, path: &str, bytes: &[u8]) -> std::io::Result<()> {
+        self.files.write(path, bytes).await?;
+        self.buffer.insert(TextPosition { byte_idx: 0 }, "!").await;
+        Ok(())
+    }
+}
+
+#[tokio::test]
+async fn saving_records_the_text_that_was_written() {
+    let document = Document::new(Some("typed.txt".to_string()), "draft", "plaintext".to_string());
+    document.set_dirty(true);
+    let store = TypingStore { files: MemoryStore::default(), buffer: document.get_text_buffer() };
+    document.save_with(&store).await.unwrap();
+    assert_eq!(store.read("typed.txt").await.unwrap(), b"draft");
+    // The edit made during the write isn't on disk, and the file still matches the baseline.
+    assert_eq!(document.text_buffer.get_text(), "!draft");
+    assert!(document.is_dirty());
+    assert!(document.save_checked_with(&store).await.is_ok());
+    assert_eq!(store.read("typed.txt").await.unwrap(), b"!draft");
+}
+
+#[tokio::test]
+async fn autosave_saves_eligible_dirty_documents_on_a_timer() {
+    let store = std::sync::Arc::new(MemoryStore::default());
+    store.write("conflict.txt", b"old").await.unwrap();
+    let manager = DocumentManager::new();
+    let a = manager.add(Document::new(Some("a.txt".to_string()), "a", "plaintext".to_string()));
+    let untitled = manager.add(Document::new(None, "u", "plaintext".to_string()));
+    let locked = manager.add(DocumentBuilder::new().path("locked.txt").read_only(true).dirty(true).build());
+    let conflicted = manager.add(Document::open_with(&*store, "conflict.txt", "plaintext".to_string()).await.unwrap());
+    store.write("conflict.txt", b"changed elsewhere").await.unwrap();
+    for document in [&a, &untitled, &conflicted] {
+        document.set_dirty(true);
+    }
+    let (tx, mut rx) = mpsc::channel(4);
+    a.add_observer(tx);
+
+    manager.enable_autosave_with(store.clone(), std::time::Duration::from_millis(10));
+    assert_eq!(rx.recv().await.unwrap(), DocumentEvent::Saved { path: "a.txt".to_string() });
+    manager.disable_autosave();
+    assert_eq!(store.read("a.txt").await.unwrap(), b"a");
+    assert!(!a.is_dirty());
+    assert!(untitled.is_dirty() && locked.is_dirty() && conflicted.is_dirty());
+    assert!(store.read("locked.txt").await.is_err());
+    assert_eq!(store.read("conflict.txt").await.unwrap(), b"changed elsewhere");
+
+    // Nothing is saved once auto-save is off.
+    a.set_dirty(true);
+    tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+    assert!(a.is_dirty());
+}
+
+#[tokio::test]
+async fn untracked_documents_never_become_dirty() {
+    let document = Document::new(None, "", "plaintext".to_string());
+    document.set_dirty(true);
+    document.set_track_dirty(false);
+    assert!(!document.is_dirty());
+    assert!(!document.tracks_dirty());
+
+    // Still editable, just never dirty.
+    document.text_buffer.insert(TextPosition { byte_idx: 0 }, "query").await;
+    document.set_dirty(true);
+    assert!(!document.is_dirty());
+    assert_eq!(document.text_buffer.get_text(), "query");
+
+    document.set_track_dirty(true);
+    assert!(!document.is_dirty());
+    document.set_dirty(true);
+    assert!(document.is_dirty());
+}
+
+#[tokio::test]
+async fn memory_documents_are_named_read_only_views() {
+    let blob = Document::from_memory("main.rs @ 3f2a1c", "fn main() {}\n", "rust");
+    assert_eq!(blob.display_name(), "main.rs @ 3f2a1c");
+    assert!(blob.file_path.is_none() && blob.is_in_memory() && blob.is_read_only());
+    assert_eq!(blob.language_id(), "rust");
+    blob.set_dirty(true);
+    assert!(!blob.is_dirty());
+    assert_eq!(blob.text_buffer.replace(entities::TextRange::new(0, 0), "x").await, Err(entities::EditError::Vetoed));
+    assert_eq!(blob.text_buffer.backspace(entities::TextRange::new(3, 3)).await, Err(EditError::Vetoed));
+    assert_eq!(blob.text_buffer.cut(entities::TextRange::new(0, 2)).await, Err(EditError::Vetoed));
+    assert_eq!(blob.insert_tab(TextPosition { byte_idx: 0 }).await, Err(EditError::Vetoed));
+    assert!(!blob.text_buffer.delete_line(0).await);
+    // With no path there is nothing to save to, and the on-save cleanups don't run either.
+    assert!(blob.save().await.is_err());
+    assert_eq!(blob.text_buffer.get_text(), "fn main() {}\n");
+
+    let untitled = Document::new(None, "", "plaintext".to_string());
+    assert_eq!(untitled.display_name(), "Untitled");
+    assert!(!untitled.is_in_memory());
+    assert_eq!(Document::new(Some("src/lib.rs".to_string()), "", "rust".to_string()).display_name(), "lib.rs");
+}
+
+#[tokio::test]
+async fn emptiness_changes_are_reported_once_per_transition() {
+    let document = Document::new(None, "", "plaintext".to_string());
+    let (tx, mut rx) = mpsc::channel(8);
+    document.add_observer(tx);
+    let buffer = document.get_text_buffer();
+
+    buffer.insert(TextPosition { byte_idx: 0 }, "a").await;
+    buffer.insert(TextPosition { byte_idx: 1 }, "b").await;
+    assert_eq!(rx.try_recv().unwrap(), DocumentEvent::EmptinessChanged(false));
+    assert!(rx.try_recv().is_err());
+
+    buffer.replace(entities::TextRange::new(0, 1), "").await.unwrap();
+    assert!(rx.try_recv().is_err());
+    buffer.replace(entities::TextRange::new(0, 1), "").await.unwrap();
+    assert_eq!(rx.try_recv().unwrap(), DocumentEvent::EmptinessChanged(true));
+}
+
+#[tokio::test]
+async fn workspace_edits_apply_to_every_file_or_none() {
+    let store = MemoryStore::default();
+    store.write("b.txt", b"world").await.unwrap();
+    let manager = DocumentManager::new();
+    let a = manager.add(Document::new(Some("a.txt".to_string()), "hello", "plaintext".to_string()));
+
+    let edits = HashMap::from([
+        ("a.txt".to_string(), vec![(0, 5, "HELLO".to_string())]),
+        ("b.txt".to_string(), vec![(0, 0, ">".to_string())]),
+    ]);
+    manager.apply_workspace_edit_with(&store, edits).await.unwrap();
+    assert_eq!(a.text_buffer.get_text(), "HELLO");
+    let b = manager.find("b.txt").unwrap();
+    assert_eq!(b.text_buffer.get_text(), ">world");
+    assert!(a.is_dirty() && b.is_dirty());
+    assert_eq!(store.read("b.txt").await.unwrap(), b"world");
+
+    // An invalid edit in a later file rolls back the earlier ones.
+    a.set_dirty(false);
+    let edits = HashMap::from([
+        ("a.txt".to_string(), vec![(0, 1, "j".to_string())]),
+        ("b.txt".to_string(), vec![(0, 100, String::new())]),
+    ]);
+    let error = manager.apply_workspace_edit_with(&store, edits).await.unwrap_err();
+    assert_eq!(error.path(), "b.txt");
+    assert!(matches!(error, WorkspaceEditError::Edit { error: EditError::OutOfBounds { .. }, .. }));
+    assert_eq!(a.text_buffer.get_text(), "HELLO");
+    assert!(!a.is_dirty());
+
+    // So does a file that can't be opened, which is never added.
+    let edits = HashMap::from([
+        ("a.txt".to_string(), vec![(0, 1, "j".to_string())]),
+        ("missing.txt".to_string(), vec![(0, 0, "x".to_string())]),
+    ]);
+    let error = manager.apply_workspace_edit_with(&store, edits).await.unwrap_err();
+    assert!(matches!(error, WorkspaceEditError::Io { ref path, .. } if path == "missing.txt"));
+    assert_eq!(a.text_buffer.get_text(), "HELLO");
+    assert_eq!(manager.documents().len(), 2);
+}
+
+#[test]
+fn names_are_truncated_by_display_width() {
+    assert_eq!(truncate_display("main.rs", 7, 4), "main.rs");
+    assert_eq!(truncate_display("main.rs", 5, 4), "main…");
+    // Wide chars take two cells and are never split; neither are combining sequences.
+    assert_eq!(truncate_display("日本語.txt", 6, 4), "日本…");
+    assert_eq!(truncate_display("e\u{301}e\u{301}e\u{301}", 2, 4), "e\u{301}…");
+    assert_eq!(truncate_display("abc", 0, 4), "");
+
+    let document = Document::new(Some("src/very_long_module_name.rs".to_string()), "", "rust".to_string());
+    assert_eq!(document.display_name_truncated(10), "very_long…");
+}
+
+#[tokio::test]
+async fn metrics_follow_edits() {
+    let document = Document::new(None, "héllo\nworld\n", "plaintext".to_string());
+    assert_eq!(document.metrics(), DocMetrics { bytes: 13, chars: 12, lines: 2 });
+    document.text_buffer.insert(TextPosition { byte_idx: 13 }, "again").await;
+    assert_eq!(document.metrics(), DocMetrics { bytes: 18, chars: 17, lines: 3 });
+    document.text_buffer.reload("").await;
+    assert_eq!(document.metrics(), DocMetrics { bytes: 0, chars: 0, lines: 1 });
+}
+
+#[test]
+fn documents_are_listed_by_path_recency_or_dirtiness() {
+    let manager = DocumentManager::new();
+    let b = manager.add(Document::new(Some("b.rs".to_string()), "", "rust".to_string()));
+    let untitled = manager.add(Document::new(None, "", "plaintext".to_string()));
+    let a = manager.add(Document::new(Some("a.rs".to_string()), "", "rust".to_string()));
+    let paths = |documents: Vec<std::sync::Arc<Document>>| -> Vec<Option<String>> {
+        documents.iter().map(|document| document.file_path.clone()).collect()
+    };
+    let (a_path, b_path) = (Some("a.rs".to_string()), Some("b.rs".to_string()));
+
+    assert_eq!(paths(manager.documents_sorted(DocOrder::ByPath)), vec![a_path.clone(), b_path.clone(), None]);
+    manager.touch("a.rs");
+    manager.touch("b.rs");
+    assert_eq!(paths(manager.documents_sorted(DocOrder::ByRecency)), vec![b_path.clone(), a_path.clone(), None]);
+    manager.touch("a.rs");
+    assert_eq!(paths(manager.documents_sorted(DocOrder::ByRecency)), vec![a_path.clone(), b_path.clone(), None]);
+
+    untitled.set_dirty(true);
+    assert_eq!(paths(manager.documents_sorted(DocOrder::ByDirty)), vec![None, b_path, a_path]);
+    assert!(manager.remove(&a) && manager.remove(&b));
+}
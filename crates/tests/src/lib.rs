@@ -12,3 +12,18 @@ mod tests {
         assert_eq!(result, 4);
     }
 }
+
+#[cfg(test)]
+mod text_buffer_tests;
+
+#[cfg(test)]
+mod undo_tests;
+
+#[cfg(test)]
+mod async_buffer_tests;
+
+#[cfg(test)]
+mod document_tests;
+
+#[cfg(test)]
+mod concurrency_tests;
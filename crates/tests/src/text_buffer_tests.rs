@@ -0,0 +1,1590 @@
+// TextBuffer Tests
+// Exercise the buffer through its public API, the same way the outer layers use it.
+use entities::{load_recording, merge_selections, replay_recording, save_recording, BufferWriter, CursorTracker, DirtySince, EditDecision, EditError, EditIntent, FrameDirtyTracker, Indentation, LangSyntax, LineEnding, LineDecorations, PositionClass, PreEditObserver, RecordingObserver, Registers, ReplayError, SearchOptions, SnippetSession, TextBuffer, TextBufferChangedEvent, TextPosition, TextRange};
+use tokio::sync::mpsc;
+
+#[tokio::test]
+async fn events_report_dirty_line_ranges() {
+    let buffer = TextBuffer::new("one\ntwo\nthree\n");
+    let (tx, mut rx) = mpsc::channel(8);
+    buffer.add_observer(tx);
+
+    // A keystroke within a line dirties just that line.
+    buffer.insert(TextPosition { byte_idx: 5 }, "x").await;
+    let event = rx.recv().await.unwrap();
+    assert_eq!(event.dirty_line_range(), 1..2);
+    assert!(!event.affects_line_count());
+
+    // A multi-line paste dirties every line it touches.
+    buffer.insert(TextPosition { byte_idx: 0 }, "a\nb\n").await;
+    let event = rx.recv().await.unwrap();
+    assert_eq!(event.dirty_line_range(), 0..3);
+    assert!(event.affects_line_count());
+
+    // A removal spanning a line break reports the pre-edit span it collapsed.
+    buffer.remove(TextPosition { byte_idx: 1 }, 2).await;
+    match rx.recv().await.unwrap() {
+        TextBufferChangedEvent::Removed { dirty_line_range, .. } => assert_eq!(dirty_line_range, 0..2),
+        other => panic!("unexpected event: {other:?}"),
+    }
+    assert_eq!(buffer.get_text(), "a\none\ntxwo\nthree\n");
+}
+
+#[tokio::test]
+async fn snippet_tab_stops_track_edits() {
+    let buffer = TextBuffer::new("\n");
+    let stops = buffer.insert_snippet(TextPosition { byte_idx: 0 }, "for ${1:item} in ${2:iter}:\n\t$0").await.unwrap();
+    assert_eq!(buffer.get_text(), "for item in iter:\n\t\n");
+
+    let resolved: Vec<_> = stops.iter().map(|r| buffer.resolve_range(r).unwrap()).collect();
+    assert_eq!(resolved, vec![TextRange::new(4, 8), TextRange::new(12, 16), TextRange::new(19, 19)]);
+
+    // Replacing the first placeholder shifts the later tab stops.
+    buffer.remove(TextPosition { byte_idx: 4 }, 4).await;
+    buffer.insert(TextPosition { byte_idx: 4 }, "x").await;
+    assert_eq!(buffer.resolve_range(&stops[0]), Some(TextRange::new(4, 5)));
+    assert_eq!(buffer.resolve_range(&stops[1]), Some(TextRange::new(9, 13)));
+
+    // A position past the end is rejected instead of panicking.
+    assert!(matches!(buffer.insert_snippet(TextPosition { byte_idx: 99 }, "$0").await, Err(EditError::OutOfBounds { .. })));
+}
+
+#[tokio::test]
+async fn auto_pairing_closes_and_skips() {
+    let buffer = TextBuffer::new("call\n");
+    let rust = LangSyntax::rust();
+
+    let cursor = buffer.auto_pair_insert(TextPosition { byte_idx: 4 }, '(', Some(&rust)).await.unwrap();
+    assert_eq!((buffer.get_text().as_str(), cursor.byte_idx), ("call()\n", 5));
+
+    // Typing the closer steps over the auto-inserted one.
+    let cursor = buffer.auto_pair_insert(cursor, ')', Some(&rust)).await.unwrap();
+    assert_eq!((buffer.get_text().as_str(), cursor.byte_idx), ("call()\n", 6));
+    assert!(matches!(buffer.auto_pair_insert(TextPosition { byte_idx: 40 }, '(', Some(&rust)).await, Err(EditError::OutOfBounds { .. })));
+
+    // No pairing inside a comment.
+    let buffer = TextBuffer::new("// see \n");
+    buffer.auto_pair_insert(TextPosition { byte_idx: 7 }, '(', Some(&rust)).await.unwrap();
+    assert_eq!(buffer.get_text(), "// see (\n");
+    buffer.add_protected_region(TextRange::new(0, 9));
+    assert_eq!(buffer.auto_pair_insert(TextPosition { byte_idx: 3 }, '[', None).await, Err(EditError::ProtectedRegion));
+}
+
+#[test]
+fn search_counts_matches_across_chunks() {
+    // Long enough that matches straddle rope chunk boundaries.
+    let text = "needle hay ".repeat(50) + "NEEDLE";
+    let buffer = TextBuffer::new(&text);
+
+    let ranges = buffer.find_all("needle");
+    assert_eq!(ranges.len(), 50);
+    assert!(ranges.iter().all(|r| &text[r.start..r.end] == "needle"));
+
+    assert_eq!(buffer.count_matches("needle", true), 50);
+    assert_eq!(buffer.count_matches("needle", false), 51);
+    assert_eq!(buffer.count_matches("le h", true), 50);
+    assert_eq!(buffer.count_matches("", true), 0);
+}
+
+#[test]
+fn search_options_whole_word_and_case_folding() {
+    let buffer = TextBuffer::new(&("cat concat Cat cat_x ΣΊΣΥΦΟΣ ".repeat(10) + "cat"));
+    let whole = SearchOptions { whole_word: true, ..SearchOptions::default() };
+    assert_eq!(buffer.find_all_opts("cat", whole).len(), 11);
+
+    let folded = SearchOptions { case_insensitive: true, whole_word: true };
+    assert_eq!(buffer.find_all_opts("CAT", folded).len(), 21);
+    // Final sigma folds like a medial sigma.
+    assert_eq!(buffer.find_all_opts("σίσυφοσ", folded).len(), 10);
+}
+
+#[test]
+fn search_within_range_requires_full_containment() {
+    let buffer = TextBuffer::new("foo foo foobar foo");
+    let opts = SearchOptions::default();
+    // The range cuts the second "foo" in half.
+    assert_eq!(buffer.find_all_in_range("foo", TextRange::new(1, 9), opts), vec![TextRange::new(4, 7)]);
+    // "foo" inside "foobar" is selected but isn't a whole word.
+    let whole = SearchOptions { whole_word: true, ..opts };
+    assert!(buffer.find_all_in_range("foo", TextRange::new(8, 11), whole).is_empty());
+}
+
+#[test]
+fn unified_diff_formats_hunks() {
+    let old = "a\nb\nc\nd\ne\nf\ng\nh\ni\nj\n";
+    let new = "a\nB\nc\nd\ne\nf\ng\nh\ni\nj\nk";
+    let diff = entities::unified_diff(old, new, "a/f.txt", "b/f.txt", 3);
+    assert_eq!(diff, "--- a/f.txt\n+++ b/f.txt\n\
+        @@ -1,5 +1,5 @@\n a\n-b\n+B\n c\n d\n e\n\
+        @@ -8,3 +8,4 @@\n h\n i\n j\n+k\n\\ No newline at end of file\n");
+    assert_eq!(entities::unified_diff(old, old, "a", "b", 3), "");
+}
+
+#[tokio::test]
+async fn line_operations_edit_whole_lines() {
+    let buffer = TextBuffer::new("a\nb\nc");
+    assert!(buffer.duplicate_line(2).await);
+    assert_eq!(buffer.get_text(), "a\nb\nc\nc");
+    let (tx, mut rx) = mpsc::channel(8);
+    buffer.add_observer(tx);
+    assert!(buffer.move_line(3, 0).await);
+    assert_eq!(buffer.get_text(), "c\na\nb\nc");
+    // The move reaches observers as one event.
+    assert!(matches!(rx.try_recv(), Ok(TextBufferChangedEvent::Batch(events)) if events.len() == 2));
+    assert!(rx.try_recv().is_err());
+    assert!(buffer.delete_line(3).await);
+    assert_eq!(buffer.get_text(), "c\na\nb");
+    assert!(!buffer.delete_line(5).await);
+
+    let rust = LangSyntax::rust();
+    let buffer = TextBuffer::new("fn f() {\n    x();\n\n    y();\n}\n");
+    let (tx, mut rx) = mpsc::channel(8);
+    buffer.add_observer(tx);
+    assert!(buffer.toggle_line_comment(1..4, &rust).await);
+    assert_eq!(buffer.get_text(), "fn f() {\n    // x();\n\n    // y();\n}\n");
+    assert!(matches!(rx.try_recv(), Ok(TextBufferChangedEvent::Batch(events)) if events.len() == 2));
+    assert!(rx.try_recv().is_err());
+    assert!(buffer.toggle_line_comment(1..4, &rust).await);
+    assert_eq!(buffer.get_text(), "fn f() {\n    x();\n\n    y();\n}\n");
+
+    // A protected line can't be commented, deleted or moved, and then nothing else changes either.
+    buffer.add_protected_region(TextRange::new(19, 27));
+    assert!(!buffer.toggle_line_comment(1..4, &rust).await);
+    assert!(!buffer.delete_line(3).await);
+    assert!(!buffer.move_line(1, 3).await);
+    assert_eq!(buffer.get_text(), "fn f() {\n    x();\n\n    y();\n}\n");
+}
+
+#[test]
+fn line_offset_helpers_check_bounds() {
+    let buffer = TextBuffer::new("ab\ncd\n");
+    assert_eq!(buffer.line_to_byte(1), Some(3));
+    assert_eq!(buffer.line_to_byte(2), Some(6));
+    assert_eq!(buffer.line_to_byte(3), None);
+    assert_eq!(buffer.byte_to_line(4), 1);
+    assert_eq!(buffer.byte_to_line(100), 2);
+}
+
+#[test]
+fn first_last_difference_skips_common_prefix_and_suffix() {
+    let buffer = TextBuffer::new("fn main() {\n    println!(\"hi\");\n}\n");
+    assert_eq!(buffer.first_last_difference("fn main() {\n    println!(\"hi\");\n}\n"), None);
+    assert_eq!(buffer.first_last_difference("fn main() {\n    println!(\"hello\");\n}\n"), Some((27, 28)));
+    // Pure insertion: an empty range at the insertion point.
+    assert_eq!(buffer.first_last_difference("// x\nfn main() {\n    println!(\"hi\");\n}\n"), Some((0, 0)));
+
+    // "é" and "è" share their first UTF-8 byte; the range still covers the whole char.
+    let buffer = TextBuffer::new("café");
+    assert_eq!(buffer.first_last_difference("cafè"), Some((3, 5)));
+}
+
+#[test]
+fn try_read_for_reads_an_unlocked_buffer() {
+    let buffer = TextBuffer::new("abc\ndef");
+    let timeout = std::time::Duration::from_millis(10);
+    assert_eq!(buffer.try_get_text_timeout(timeout).as_deref(), Some("abc\ndef"));
+    assert_eq!(buffer.try_read_for(timeout, |rope| rope.len_lines()), Some(2));
+}
+
+#[tokio::test]
+async fn line_decorations_follow_their_lines() {
+    let buffer = std::sync::Arc::new(TextBuffer::new("a\nb\nc\nd\n"));
+    let mut breakpoints = LineDecorations::new(buffer.clone());
+    assert!(breakpoints.set(1, "b"));
+    assert!(breakpoints.set(3, "d"));
+    assert!(!breakpoints.set(9, "x"));
+
+    // A line inserted above shifts both.
+    buffer.insert(TextPosition { byte_idx: 0 }, "new\n").await;
+    assert_eq!(breakpoints.iter(), vec![(2, &"b"), (4, &"d")]);
+
+    // Deleting a decorated line drops its decoration.
+    assert!(buffer.delete_line(2).await);
+    assert_eq!(breakpoints.get(2), None);
+    assert_eq!(breakpoints.iter(), vec![(3, &"d")]);
+
+    // Joining lines (backspace at the start of "d") keeps the decoration on the joined line.
+    buffer.remove(TextPosition { byte_idx: 7 }, 1).await;
+    assert_eq!(buffer.get_text(), "new\na\ncd\n");
+    assert_eq!(breakpoints.get(2), Some(&"d"));
+}
+
+#[tokio::test]
+async fn grouped_edits_arrive_as_one_batch() {
+    let buffer = TextBuffer::new("let a = 1;\nlet b = 2;\n");
+    let (tx, mut rx) = mpsc::channel(8);
+    buffer.add_observer(tx);
+
+    // Offsets refer to the original text, in any order.
+    let edits = vec![(15, 16, "bee".to_string()), (4, 5, "ay".to_string()), (0, 0, "// x\n".to_string())];
+    buffer.apply_edits(edits).await.unwrap();
+    assert_eq!(buffer.get_text(), "// x\nlet ay = 1;\nlet bee = 2;\n");
+    let batch = rx.recv().await.unwrap();
+    assert_eq!(batch.events().len(), 5);
+    assert_eq!(batch.dirty_line_range(), 0..3);
+
+    // Invalid groups are rejected without touching the buffer.
+    assert_eq!(buffer.apply_edits(vec![(0, 4, String::new()), (2, 6, String::new())]).await, Err(EditError::OverlappingEdits));
+    assert!(matches!(buffer.apply_edits(vec![(0, 999, String::new())]).await, Err(EditError::OutOfBounds { .. })));
+
+    let len = buffer.transaction(|tx| {
+        tx.insert(TextPosition { byte_idx: 0 }, "ab").unwrap();
+        tx.remove(TextPosition { byte_idx: 1 }, 1).unwrap();
+        tx.len_bytes()
+    }).await;
+    assert_eq!(len, buffer.len_bytes());
+    assert_eq!(rx.recv().await.unwrap().events().len(), 2);
+    assert!(rx.try_recv().is_err());
+
+    // Transactions are checked edit by edit like everything else.
+    buffer.add_protected_region(TextRange::new(0, 2));
+    let rejected = buffer.transaction(|tx| {
+        (tx.insert(TextPosition { byte_idx: 1 }, "x"), tx.remove(TextPosition { byte_idx: 0 }, 1))
+    }).await;
+    assert_eq!(rejected, (Err(EditError::ProtectedRegion), Err(EditError::ProtectedRegion)));
+    assert!(rx.try_recv().is_err());
+}
+
+#[test]
+fn mixed_line_endings_are_located() {
+    // Long enough that a "\r\n" straddles a rope chunk boundary.
+    let text = format!("{}\r\nb\r\nc\nd\r\ne\r", "a".repeat(15));
+    let buffer = TextBuffer::new(&text);
+    assert_eq!(buffer.detect_line_ending(), Some(LineEnding::CrLf));
+    assert_eq!(buffer.mixed_line_ending_locations(), vec![21, 26]);
+    assert_eq!(TextBuffer::new("a\nb\n").mixed_line_ending_locations(), Vec::<usize>::new());
+    assert_eq!(TextBuffer::new("ab").detect_line_ending(), None);
+}
+
+#[tokio::test]
+async fn insert_at_line_col_pads_virtual_space() {
+    let buffer = TextBuffer::new("long line\nab\n");
+    let end = buffer.insert_at_line_col(1, 5, "x", true).await;
+    assert_eq!(buffer.get_text(), "long line\nab   x\n");
+    assert_eq!(end, Ok(TextPosition { byte_idx: 16 }));
+
+    buffer.insert_at_line_col(0, 20, "!", false).await.unwrap();
+    buffer.insert_at_line_col(0, 4, "_", true).await.unwrap();
+    assert_eq!(buffer.get_text(), "long_ line!\nab   x\n");
+
+    buffer.add_protected_region(TextRange::new(0, 11));
+    assert_eq!(buffer.insert_at_line_col(0, 2, "?", true).await, Err(EditError::ProtectedRegion));
+    assert_eq!(buffer.get_text(), "long_ line!\nab   x\n");
+}
+
+#[tokio::test]
+async fn buffer_writer_emits_one_event() {
+    use std::fmt::Write as _;
+
+    let buffer = TextBuffer::new("fn main() {}\n");
+    let (tx, mut rx) = mpsc::channel(4);
+    buffer.add_observer(tx);
+
+    let mut writer = BufferWriter::new(&buffer, TextPosition { byte_idx: 0 });
+    for i in 0..3 {
+        writeln!(writer, "const C{i}: u32 = {i};").unwrap();
+    }
+    let end = writer.finish().await;
+
+    assert_eq!(buffer.get_range(0, end.byte_idx), "const C0: u32 = 0;\nconst C1: u32 = 1;\nconst C2: u32 = 2;\n");
+    assert_eq!(rx.recv().await.unwrap().dirty_line_range(), 0..4);
+    assert!(rx.try_recv().is_err());
+
+    // Writing into protected text fails instead of slipping past the check.
+    buffer.add_protected_region(TextRange::new(0, end.byte_idx));
+    let mut writer = BufferWriter::new(&buffer, TextPosition { byte_idx: 4 });
+    assert!(write!(writer, "x").is_err());
+    writer.finish().await;
+    assert!(buffer.get_text().starts_with("const C0: u32"));
+
+    // A writer dropped without 'finish' (an early return from 'write!(..)?') still records the
+    // edit, though observers aren't told.
+    let buffer = TextBuffer::new("one");
+    let mut writer = BufferWriter::new(&buffer, TextPosition { byte_idx: 3 });
+    write!(writer, " two three").unwrap();
+    drop(writer);
+    assert_eq!(buffer.get_text(), "one two three");
+    assert_eq!(buffer.version(), 1);
+    assert_eq!(buffer.stats().words, 3);
+    assert_eq!(buffer.changes_since(0), Some(vec![TextRange::new(3, 13)]));
+}
+
+#[test]
+fn line_count_ignores_the_phantom_last_line() {
+    assert_eq!(TextBuffer::new("a\nb\nc").line_count(), 3);
+    assert_eq!(TextBuffer::new("a\nb\nc\n").line_count(), 3);
+    assert_eq!(TextBuffer::new("a\nb\nc\n").len_lines(), 4);
+    assert_eq!(TextBuffer::new("").line_count(), 1);
+    assert_eq!(TextBuffer::new("\n").line_count(), 1);
+}
+
+#[tokio::test]
+async fn register_returns_a_removable_receiver() {
+    let buffer = TextBuffer::new("");
+    let (mut rx, id) = buffer.register(4);
+    buffer.insert(TextPosition { byte_idx: 0 }, "a").await;
+    assert!(rx.recv().await.is_some());
+
+    assert!(buffer.remove_observer(id));
+    assert!(!buffer.remove_observer(id));
+    // The sender was dropped with the registration, so the channel closes.
+    assert!(rx.recv().await.is_none());
+}
+
+#[tokio::test]
+async fn backspace_and_delete_remove_whole_graphemes() {
+    let buffer = TextBuffer::new("ae\u{0301}\r\nb");
+    // Backspace at the start of "b" removes the whole "\r\n".
+    assert_eq!(buffer.backspace(TextRange::new(6, 6)).await, Ok(TextPosition { byte_idx: 4 }));
+    // "e" + combining acute is one grapheme.
+    assert_eq!(buffer.backspace(TextRange::new(4, 4)).await, Ok(TextPosition { byte_idx: 1 }));
+    assert_eq!(buffer.get_text(), "ab");
+
+    assert_eq!(buffer.delete_forward(TextRange::new(0, 0)).await, Ok(TextPosition { byte_idx: 0 }));
+    assert_eq!(buffer.get_text(), "b");
+    // With a selection only the selection goes; at the buffer edges nothing happens.
+    assert_eq!(buffer.delete_forward(TextRange::new(1, 1)).await, Ok(TextPosition { byte_idx: 1 }));
+    assert_eq!(buffer.backspace(TextRange::new(0, 1)).await, Ok(TextPosition { byte_idx: 0 }));
+    assert_eq!(buffer.get_text(), "");
+    assert!(buffer.backspace(TextRange::new(3, 3)).await.is_err());
+}
+
+#[tokio::test]
+async fn large_edits_are_reported_as_reloads_above_the_threshold() {
+    let buffer = TextBuffer::new("0123456789");
+    let (mut rx, _) = buffer.register(8);
+
+    // Off by default.
+    buffer.remove(TextPosition { byte_idx: 0 }, 9).await;
+    assert!(matches!(rx.recv().await.unwrap(), TextBufferChangedEvent::Removed { .. }));
+
+    buffer.set_reload_threshold(Some(0.5));
+    buffer.insert(TextPosition { byte_idx: 1 }, "a").await;
+    assert!(matches!(rx.recv().await.unwrap(), TextBufferChangedEvent::Inserted { .. }));
+    buffer.insert(TextPosition { byte_idx: 0 }, "line\nline\n").await;
+    match rx.recv().await.unwrap() {
+        TextBufferChangedEvent::Reloaded { len_bytes, len_lines, .. } => assert_eq!((len_bytes, len_lines), (12, 3)),
+        other => panic!("unexpected event: {other:?}"),
+    }
+}
+
+#[tokio::test]
+async fn observer_count_tracks_registration_and_pruning() {
+    let buffer = TextBuffer::new("");
+    // No observers at all is fine.
+    buffer.insert(TextPosition { byte_idx: 0 }, "a").await;
+    assert_eq!(buffer.observer_count(), 0);
+
+    buffer.add_observer(entities::devnull_sender());
+    let (rx, _) = buffer.register(1);
+    assert_eq!(buffer.observer_count(), 2);
+
+    // More events than the devnull channel holds never block; the dropped receiver is pruned.
+    drop(rx);
+    for i in 0..40 {
+        buffer.insert(TextPosition { byte_idx: i }, "b").await;
+    }
+    assert_eq!(buffer.observer_count(), 1);
+}
+
+#[test]
+fn previews_never_split_graphemes() {
+    let family = "\u{1F468}\u{200D}\u{1F469}\u{200D}\u{1F467}";
+    let buffer = TextBuffer::new(&format!("{family}e\u{0301}xyz\r\nsecond line"));
+    assert_eq!(buffer.prefix(2), format!("{family}e\u{0301}"));
+    assert_eq!(buffer.line_prefix(0, 100), format!("{family}e\u{0301}xyz"));
+    assert_eq!(buffer.line_prefix(1, 6), "second");
+    assert_eq!(buffer.line_prefix(7, 6), "");
+}
+
+#[test]
+fn line_start_and_end_positions() {
+    let buffer = TextBuffer::new("ab\r\ncd");
+    assert_eq!(buffer.position_at_line_start(1), Some(TextPosition { byte_idx: 4 }));
+    assert_eq!(buffer.position_at_line_end(0), Some(TextPosition { byte_idx: 2 }));
+    assert_eq!(buffer.position_at_line_end(1), Some(TextPosition { byte_idx: 6 }));
+    assert_eq!(buffer.position_at_line_end(2), None);
+}
+
+#[tokio::test]
+async fn frame_tracker_merges_dirty_lines_between_frames() {
+    let buffer = TextBuffer::new("a\nb\nc\nd\ne\n");
+    let tracker = std::sync::Arc::new(FrameDirtyTracker::new(2));
+    buffer.add_sync_observer(tracker.clone());
+
+    buffer.insert(TextPosition { byte_idx: 2 }, "x").await;
+    buffer.insert(TextPosition { byte_idx: 5 }, "y").await;
+    buffer.insert(TextPosition { byte_idx: 10 }, "z").await;
+    let frame = tracker.take_frame();
+    assert_eq!(frame.dirty_lines, vec![1..3, 4..5]);
+    assert_eq!(frame.version, buffer.version());
+    assert!(tracker.take_frame().is_clean());
+
+    // A new line shifts everything below it.
+    buffer.insert(TextPosition { byte_idx: 0 }, "\n").await;
+    assert_eq!(tracker.take_frame().dirty_lines, vec![0..usize::MAX]);
+
+    // Too many separate ranges fall back to a full repaint.
+    for line in [1, 3, 5] {
+        let position = buffer.position_at_line_start(line).unwrap();
+        buffer.insert(position, "w").await;
+    }
+    let frame = tracker.take_frame();
+    assert!(frame.full_repaint && frame.dirty_lines.is_empty());
+}
+
+#[tokio::test]
+async fn set_contents_diffed_only_touches_changed_lines() {
+    let buffer = TextBuffer::new("fn a() {\nx=1;\n}\nfn b() {}\n");
+    let anchor = buffer.create_anchor(TextPosition { byte_idx: 16 }, entities::AnchorBias::Left);
+    let (tx, mut rx) = mpsc::channel(8);
+    buffer.add_observer(tx);
+
+    buffer.set_contents_diffed("fn a() {\n    x = 1;\n}\nfn b() {}\n").await;
+    assert_eq!(buffer.get_text(), "fn a() {\n    x = 1;\n}\nfn b() {}\n");
+    let event = rx.recv().await.unwrap();
+    assert!(matches!(event, TextBufferChangedEvent::Batch(_)));
+    assert_eq!(event.dirty_line_range().start, 1);
+    // "fn b" moved by the six added bytes.
+    assert_eq!(buffer.anchor_position(anchor), Some(TextPosition { byte_idx: 22 }));
+
+    // Unchanged content emits nothing; a rewrite is reported as a reload.
+    buffer.set_contents_diffed("fn a() {\n    x = 1;\n}\nfn b() {}\n").await;
+    buffer.set_contents_diffed("something else\n").await;
+    assert!(matches!(rx.recv().await.unwrap(), TextBufferChangedEvent::Reloaded { .. }));
+}
+
+#[tokio::test]
+async fn events_carry_the_start_line_and_column() {
+    let buffer = TextBuffer::new("héllo\nwörld\n");
+    let (tx, mut rx) = mpsc::channel(8);
+    buffer.add_observer(tx);
+
+    // Columns count chars, not bytes.
+    buffer.insert(TextPosition { byte_idx: 11 }, "!").await;
+    assert_eq!(rx.recv().await.unwrap().start_line_col(), Some((1, 3)));
+    buffer.remove(TextPosition { byte_idx: 3 }, 2).await;
+    assert_eq!(rx.recv().await.unwrap().start_line_col(), Some((0, 2)));
+}
+
+#[tokio::test]
+async fn tab_inserts_to_the_next_stop_and_shift_tab_dedents() {
+    let buffer = TextBuffer::new("\tab\n");
+    let soft = Indentation { soft_tabs: true, tab_width: 4 };
+
+    // The tab is 4 columns wide, so "a" sits at column 5 and the next stop is 8.
+    let after = buffer.insert_tab(TextPosition { byte_idx: 2 }, &soft).await;
+    assert_eq!(buffer.get_text(), "\ta   b\n");
+    assert_eq!(after, Ok(TextPosition { byte_idx: 5 }));
+    let hard = Indentation { soft_tabs: false, ..soft };
+    buffer.insert_tab(TextPosition { byte_idx: 0 }, &hard).await.unwrap();
+    assert_eq!(buffer.get_text(), "\t\ta   b\n");
+
+    assert_eq!(buffer.dedent_line(0, &soft).await, Ok(1));
+    buffer.insert(TextPosition { byte_idx: 0 }, "      ").await;
+    assert_eq!(buffer.dedent_line(0, &soft).await, Ok(4));
+    assert_eq!(buffer.get_text(), "  \ta   b\n");
+    assert_eq!(buffer.dedent_line(1, &soft).await, Ok(0));
+
+    // Positions and lines past the end are rejected instead of panicking, and so are edits of
+    // protected text.
+    assert!(matches!(buffer.insert_tab(TextPosition { byte_idx: 99 }, &soft).await, Err(EditError::OutOfBounds { .. })));
+    assert_eq!(buffer.dedent_line(5, &soft).await, Err(EditError::LineOutOfBounds { line: 5, len_lines: 2 }));
+    buffer.add_protected_region(TextRange::new(0, 4));
+    assert_eq!(buffer.dedent_line(0, &soft).await, Err(EditError::ProtectedRegion));
+    assert_eq!(buffer.insert_tab(TextPosition { byte_idx: 2 }, &hard).await, Err(EditError::ProtectedRegion));
+    assert_eq!(buffer.get_text(), "  \ta   b\n");
+}
+
+#[test]
+fn byte_chunks_stream_the_whole_text() {
+    let text = "fn main() {\n    println!(\"héllo, wörld\");\n}\n".repeat(20);
+    let buffer = TextBuffer::new(&text);
+    let streamed: Vec<u8> = buffer.byte_chunks().flat_map(|chunk| chunk.to_vec()).collect();
+    assert_eq!(streamed, text.as_bytes());
+
+    // The hash only depends on the content.
+    assert_eq!(buffer.content_hash(), TextBuffer::new(&text).content_hash());
+    assert_ne!(buffer.content_hash(), TextBuffer::new("").content_hash());
+}
+
+#[tokio::test]
+async fn cut_and_copy_fill_the_registers() {
+    let buffer = TextBuffer::new("alpha beta\r\ngamma");
+    let mut registers = Registers::new();
+
+    registers.copy_from(&buffer, TextRange::new(0, 5), Some('a')).unwrap();
+    registers.cut_from(&buffer, TextRange::new(5, 10), None).await.unwrap();
+    assert_eq!(buffer.get_text(), "alpha\r\ngamma");
+    assert_eq!(registers.get('a'), Some("alpha"));
+    assert_eq!(registers.get(Registers::UNNAMED), Some(" beta"));
+    assert_eq!(registers.get('b'), None);
+    assert_eq!(buffer.cut(TextRange::new(3, 40)).await, Err(EditError::OutOfBounds { start: 3, end: 40, len_bytes: 12 }));
+    // A protected range can be copied but not cut, and the register keeps its old text.
+    buffer.add_protected_region(TextRange::new(0, 5));
+    assert_eq!(registers.cut_from(&buffer, TextRange::new(2, 7), None).await, Err(EditError::ProtectedRegion));
+    assert_eq!(buffer.get_text(), "alpha\r\ngamma");
+    assert_eq!(registers.get(Registers::UNNAMED), Some(" beta"));
+
+    // A linewise copy of the last line still ends with the buffer's line ending.
+    assert_eq!(buffer.copy_lines(1..5), "gamma\r\n");
+}
+
+#[tokio::test]
+async fn empty_edits_are_not_reported() {
+    let buffer = TextBuffer::new("abc");
+    let (tx, mut rx) = mpsc::channel(8);
+    buffer.add_observer(tx);
+
+    buffer.insert(TextPosition { byte_idx: 1 }, "").await;
+    buffer.remove(TextPosition { byte_idx: 1 }, 0).await;
+    // Nor are the empty edits of the other commands.
+    let at = |byte_idx| TextPosition { byte_idx };
+    assert_eq!(buffer.insert_snippet(at(1), "$1").await.unwrap().len(), 2);
+    assert_eq!(buffer.insert_snippet(at(1), "$0").await.unwrap().len(), 1);
+    assert_eq!(buffer.insert_at_line_col(0, 1, "", false).await, Ok(at(1)));
+    assert_eq!(buffer.insert_char_idx(1, "").await, Ok(1));
+    assert_eq!(buffer.replace(TextRange::new(1, 1), "").await, Ok(()));
+    assert_eq!(buffer.backspace(TextRange::new(0, 0)).await, Ok(at(0)));
+    assert_eq!(buffer.cut(TextRange::new(1, 1)).await, Ok(String::new()));
+    assert_eq!(buffer.transaction(|tx| tx.insert(at(1), "").and(tx.remove(at(1), 0))).await, Ok(()));
+    assert_eq!(buffer.get_text(), "abc");
+    assert_eq!(buffer.version(), 0);
+    assert!(rx.try_recv().is_err());
+}
+
+#[tokio::test]
+async fn edit_summaries_remap_positions() {
+    let buffer = TextBuffer::new("let a = 1;\nlet b = 2;\n");
+    let edits = vec![(4, 5, "alpha".to_string()), (8, 9, String::new()), (11, 11, "// c\n".to_string())];
+    let summary = buffer.apply_edits(edits).await.unwrap();
+    assert_eq!(buffer.get_text(), "let alpha = ;\n// c\nlet b = 2;\n");
+    assert_eq!(summary.delta(), 8);
+
+    assert_eq!(summary.map_position(2), 2);
+    // Inside (or at the start of) a replaced region: its new start.
+    assert_eq!(summary.map_position(4), 4);
+    assert_eq!(summary.map_position(8), 12);
+    // After every edit, including at the insertion point.
+    assert_eq!(summary.map_position(11), 19);
+    assert_eq!(summary.map_position(15), 23);
+}
+
+#[test]
+fn indent_blocks_span_deeper_lines() {
+    let text = "def f():\n    a = 1\n\n    if a:\n        b()\n    return a\n\nprint(f())\n";
+    let buffer = TextBuffer::new(text);
+    let block = buffer.indent_block_at(3).unwrap();
+    assert_eq!(&text[block.start..block.end], "    a = 1\n\n    if a:\n        b()\n    return a\n");
+    let block = buffer.indent_block_at(4).unwrap();
+    assert_eq!(&text[block.start..block.end], "        b()\n");
+    let block = buffer.indent_block_at(7).unwrap();
+    assert_eq!(&text[block.start..block.end], text);
+    assert_eq!(buffer.indent_block_at(20), None);
+}
+
+#[test]
+fn wrapped_lines_break_at_words() {
+    let buffer = TextBuffer::new("the quick brown fox\n\tindented\n漢字漢字漢字\n");
+    let rows = |line, cols| -> Vec<String> {
+        buffer.wrapped_lines(line, cols, 4).iter().map(|r| buffer.get_range(r.start, r.end)).collect()
+    };
+    assert_eq!(rows(0, 10), ["the quick ", "brown fox"]);
+    // Words longer than a row are hard-broken.
+    assert_eq!(rows(0, 3), ["the ", "qui", "ck ", "bro", "wn ", "fox"]);
+    // The tab takes four cells, wide chars two each.
+    assert_eq!(rows(1, 6), ["\t", "indent", "ed"]);
+    assert_eq!(rows(2, 5), ["漢字", "漢字", "漢字"]);
+    assert_eq!(rows(3, 5), [""]);
+    assert!(buffer.wrapped_lines(4, 5, 4).is_empty());
+}
+
+#[tokio::test]
+async fn reload_skips_identical_content() {
+    let buffer = TextBuffer::new("same text\n");
+    let (tx, mut rx) = mpsc::channel(8);
+    buffer.add_observer(tx);
+
+    assert!(!buffer.reload("same text\n").await);
+    assert!(rx.try_recv().is_err());
+    assert_eq!(buffer.version(), 0);
+
+    assert!(buffer.reload("new text\n").await);
+    assert_eq!(buffer.get_text(), "new text\n");
+    assert!(matches!(rx.recv().await.unwrap(), TextBufferChangedEvent::Reloaded { len_bytes: 9, .. }));
+}
+
+#[tokio::test]
+async fn stats_track_words_across_edits() {
+    let buffer = TextBuffer::new("hello world\n");
+    assert_eq!(buffer.stats(), entities::TextStats { bytes: 12, chars: 12, lines: 1, words: 2 });
+
+    // Splitting a word, joining two words, and editing inside a word.
+    buffer.insert(TextPosition { byte_idx: 2 }, " ").await;
+    assert_eq!(buffer.stats().words, 3);
+    buffer.remove(TextPosition { byte_idx: 6 }, 1).await;
+    assert_eq!(buffer.get_text(), "he lloworld\n");
+    assert_eq!(buffer.stats().words, 2);
+    buffer.insert(TextPosition { byte_idx: 4 }, "ö").await;
+    assert_eq!(buffer.stats().words, 2);
+    buffer.insert(TextPosition { byte_idx: 14 }, "two more\n").await;
+    assert_eq!(buffer.stats(), entities::TextStats { bytes: 23, chars: 22, lines: 2, words: 4 });
+    buffer.reload("").await;
+    assert_eq!(buffer.stats().words, 0);
+}
+
+#[tokio::test]
+async fn char_indexed_edits_convert_under_the_lock() {
+    let buffer = TextBuffer::new("héllo");
+    assert_eq!(buffer.insert_char_idx(2, "ü").await, Ok(3));
+    assert_eq!(buffer.get_text(), "héüllo");
+    assert_eq!(buffer.remove_char_range(1, 3).await, Ok(1));
+    assert_eq!(buffer.get_text(), "hllo");
+    assert_eq!(buffer.insert_char_idx(9, "x").await, Err(EditError::CharOutOfBounds { start: 9, end: 9, len_chars: 4 }));
+    assert!(buffer.remove_char_range(3, 2).await.is_err());
+}
+
+#[test]
+fn long_lines_can_be_measured_and_windowed() {
+    let long = "ab€".repeat(10_000);
+    let buffer = TextBuffer::new(&format!("{long}\r\nshort"));
+    assert_eq!(buffer.line_len_bytes(0), Some(long.len()));
+    assert_eq!(buffer.line_len_bytes(1), Some(5));
+    assert_eq!(buffer.line_len_bytes(2), None);
+
+    assert_eq!(buffer.line_slice(0, 5..10), "ab€");
+    // Widened to whole chars, and never past the line's content.
+    assert_eq!(buffer.line_slice(0, 3..4), "€");
+    assert_eq!(buffer.line_slice(0, long.len() - 2..long.len() + 50), "€");
+    assert_eq!(buffer.line_slice(1, 2..100), "ort");
+    assert_eq!(buffer.line_slice(7, 0..10), "");
+}
+
+// Rejects any edit that would insert a digit or remove one.
+struct NoDigits;
+
+impl PreEditObserver for NoDigits {
+    fn before_edit(&self, intent: &EditIntent<'_>) -> EditDecision {
+        let has_digit = |text: &str| text.chars().any(|c| c.is_ascii_digit());
+        if has_digit(intent.text) || has_digit(intent.old_text) { EditDecision::Veto } else { EditDecision::Allow }
+    }
+}
+
+#[tokio::test]
+async fn pre_edit_observers_can_veto_edits() {
+    let buffer = TextBuffer::new("abc 123");
+    let id = buffer.add_pre_edit_observer(std::sync::Arc::new(NoDigits));
+    let (tx, mut rx) = mpsc::channel(8);
+    buffer.add_observer(tx);
+
+    assert_eq!(buffer.replace(TextRange::new(4, 5), "x").await, Err(EditError::Vetoed));
+    buffer.insert(TextPosition { byte_idx: 0 }, "9").await;
+    assert_eq!(buffer.apply_edits(vec![(0, 1, "z".to_string()), (3, 3, "4".to_string())]).await, Err(EditError::Vetoed));
+    assert_eq!(buffer.backspace(TextRange::new(7, 7)).await, Err(EditError::Vetoed));
+    assert_eq!(buffer.delete_forward(TextRange::new(4, 4)).await, Err(EditError::Vetoed));
+    assert_eq!(buffer.get_text(), "abc 123");
+    assert!(rx.try_recv().is_err());
+
+    assert_eq!(buffer.replace(TextRange::new(0, 3), "xy").await, Ok(()));
+    assert_eq!(buffer.get_text(), "xy 123");
+    assert!(matches!(rx.try_recv(), Ok(TextBufferChangedEvent::Batch(events)) if events.len() == 2));
+
+    assert!(buffer.remove_observer(id));
+    buffer.remove(TextPosition { byte_idx: 3 }, 3).await;
+    assert_eq!(buffer.get_text(), "xy ");
+}
+
+struct VetoAll;
+
+impl PreEditObserver for VetoAll {
+    fn before_edit(&self, _intent: &EditIntent<'_>) -> EditDecision {
+        EditDecision::Veto
+    }
+}
+
+#[tokio::test]
+async fn a_vetoing_observer_blocks_every_edit_command() {
+    use std::fmt::Write;
+
+    let text = "fn f() {\n    hello there  \n    world\n}";
+    let buffer = TextBuffer::new(text);
+    buffer.add_pre_edit_observer(std::sync::Arc::new(VetoAll));
+    let (tx, mut rx) = mpsc::channel(64);
+    buffer.add_observer(tx);
+    let at = |byte_idx| TextPosition { byte_idx };
+    let indent = Indentation::default();
+    let rust = LangSyntax::rust();
+
+    buffer.insert(at(0), "x").await;
+    buffer.remove(at(0), 2).await;
+    assert_eq!(buffer.replace(TextRange::new(0, 2), "x").await, Err(EditError::Vetoed));
+    assert_eq!(buffer.apply_edits(vec![(0, 2, "x".to_string())]).await, Err(EditError::Vetoed));
+    assert_eq!(buffer.insert_char_idx(0, "x").await, Err(EditError::Vetoed));
+    assert_eq!(buffer.remove_char_range(0, 2).await, Err(EditError::Vetoed));
+    assert_eq!(buffer.backspace(TextRange::new(14, 14)).await, Err(EditError::Vetoed));
+    assert_eq!(buffer.delete_forward(TextRange::new(13, 13)).await, Err(EditError::Vetoed));
+    assert_eq!(buffer.cut(TextRange::new(13, 18)).await, Err(EditError::Vetoed));
+    assert_eq!(buffer.insert_tab(at(13), &indent).await, Err(EditError::Vetoed));
+    assert_eq!(buffer.dedent_line(1, &indent).await, Err(EditError::Vetoed));
+    assert!(!buffer.delete_line(1).await);
+    assert_eq!(buffer.delete_lines(1, 2, at(0)).await, Err(EditError::Vetoed));
+    assert_eq!(buffer.replace_line(1, "x").await, Err(EditError::Vetoed));
+    assert!(!buffer.duplicate_line(1).await);
+    assert!(!buffer.move_line(1, 2).await);
+    assert_eq!(buffer.insert_at_line_col(1, 2, "x", false).await, Err(EditError::Vetoed));
+    assert!(!buffer.toggle_line_comment(1..3, &rust).await);
+    assert_eq!(buffer.prefix_lines(0..4, "> ", true).await, 0);
+    assert_eq!(buffer.suffix_lines(0..4, ";", true).await, 0);
+    assert!(!buffer.trim_trailing_whitespace().await);
+    assert!(!buffer.ensure_final_newline(LineEnding::Lf).await);
+    assert!(!buffer.normalize_line_endings(LineEnding::CrLf).await);
+    assert_eq!(buffer.auto_pair_insert(at(13), '(', Some(&rust)).await, Err(EditError::Vetoed));
+    assert_eq!(buffer.surround(TextRange::new(13, 18), "(", ")").await, Err(EditError::Vetoed));
+    assert_eq!(buffer.insert_snippet(at(13), "${1:x}").await.err(), Some(EditError::Vetoed));
+    assert_eq!(buffer.transpose_chars(14).await, Err(EditError::Vetoed));
+    assert_eq!(buffer.transpose_words(13).await, Err(EditError::Vetoed));
+    let mut writer = BufferWriter::new(&buffer, at(0));
+    assert!(write!(writer, "x").is_err());
+    writer.finish().await;
+    assert_eq!(buffer.transaction(|tx| tx.insert(at(0), "x")).await, Err(EditError::Vetoed));
+    assert!(buffer.splice(TextRange::new(0, 2), "x".as_bytes()).await.is_err());
+    buffer.set_contents_diffed("something else").await;
+
+    assert_eq!(buffer.get_text(), text);
+    assert!(rx.try_recv().is_err());
+}
+
+#[tokio::test]
+async fn protected_regions_reject_edits_and_follow_the_text() {
+    // A REPL: the prompt and earlier output are protected, the input after "> " is not.
+    let buffer = TextBuffer::new("out\n> ");
+    let region = buffer.add_protected_region(TextRange::new(0, 6));
+
+    buffer.insert(TextPosition { byte_idx: 6 }, "ls").await;
+    assert_eq!(buffer.get_text(), "out\n> ls");
+    buffer.insert(TextPosition { byte_idx: 2 }, "x").await;
+    buffer.remove(TextPosition { byte_idx: 5 }, 2).await;
+    assert_eq!(buffer.get_text(), "out\n> ls");
+    assert_eq!(buffer.replace(TextRange::new(3, 4), "").await, Err(EditError::ProtectedRegion));
+    assert_eq!(buffer.insert_char_idx(1, "y").await, Err(EditError::ProtectedRegion));
+    // Backspace at the start of the input would eat into the prompt.
+    assert_eq!(buffer.backspace(TextRange::new(6, 6)).await, Err(EditError::ProtectedRegion));
+    assert_eq!(buffer.cut(TextRange::new(5, 8)).await, Err(EditError::ProtectedRegion));
+    assert_eq!(buffer.insert_tab(TextPosition { byte_idx: 1 }, &Indentation::default()).await, Err(EditError::ProtectedRegion));
+    assert!(!buffer.delete_line(1).await);
+    assert_eq!(buffer.get_text(), "out\n> ls");
+    assert_eq!(buffer.backspace(TextRange::new(8, 8)).await, Ok(TextPosition { byte_idx: 7 }));
+    buffer.insert(TextPosition { byte_idx: 7 }, "s").await;
+
+    // Text typed at the region's start stays outside it and shifts it.
+    buffer.insert(TextPosition { byte_idx: 0 }, "$ ").await;
+    assert_eq!(buffer.protected_region_range(region), Some(TextRange::new(2, 8)));
+    assert_eq!(buffer.replace(TextRange::new(0, 2), "").await, Ok(()));
+
+    assert!(buffer.remove_protected_region(region));
+    assert!(!buffer.remove_protected_region(region));
+    buffer.remove(TextPosition { byte_idx: 0 }, 4).await;
+    assert_eq!(buffer.get_text(), "> ls");
+}
+
+#[test]
+fn line_predicates_read_the_rope_in_place() {
+    let buffer = TextBuffer::new("  // note\n \t\r\n\nfn main");
+    assert!(buffer.line_starts_with(0, "  //"));
+    assert!(!buffer.line_starts_with(0, "//"));
+    assert!(buffer.line_starts_with(3, "fn main"));
+    assert!(!buffer.line_starts_with(3, "fn main()"));
+    assert!(!buffer.line_starts_with(9, ""));
+
+    assert!(!buffer.line_is_blank(0));
+    assert!(buffer.line_is_blank(1));
+    assert!(buffer.line_is_blank(2));
+    assert!(!buffer.line_is_blank(4));
+}
+
+#[tokio::test]
+async fn transpose_swaps_graphemes_and_words_within_the_line() {
+    let buffer = TextBuffer::new("abe\u{301}\nfoo, bar baz");
+    let (tx, mut rx) = mpsc::channel(8);
+    buffer.add_observer(tx);
+
+    // Around the cursor, then at the end of the line (keeping the accent on its letter).
+    assert_eq!(buffer.transpose_chars(1).await, Ok(TextPosition { byte_idx: 2 }));
+    assert_eq!(buffer.get_text(), "bae\u{301}\nfoo, bar baz");
+    assert!(matches!(rx.try_recv(), Ok(TextBufferChangedEvent::Batch(_))));
+    assert_eq!(buffer.transpose_chars(5).await, Ok(TextPosition { byte_idx: 5 }));
+    assert_eq!(buffer.get_text(), "be\u{301}a\nfoo, bar baz");
+    // Nothing to swap at a line start.
+    assert_eq!(buffer.transpose_chars(6).await, Ok(TextPosition { byte_idx: 6 }));
+    assert!(buffer.transpose_chars(99).await.is_err());
+
+    assert_eq!(buffer.transpose_words(7).await, Ok(TextPosition { byte_idx: 14 }));
+    assert_eq!(buffer.get_text(), "be\u{301}a\nbar, foo baz");
+    assert_eq!(buffer.transpose_words(buffer.get_text().len()).await, Ok(TextPosition { byte_idx: 18 }));
+    assert_eq!(buffer.get_text(), "be\u{301}a\nbar, baz foo");
+}
+
+#[tokio::test]
+async fn events_classify_whitespace_and_indentation_edits() {
+    let buffer = TextBuffer::new("fn f() {\r\nx  \n}");
+    let (tx, mut rx) = mpsc::channel(16);
+    buffer.add_observer(tx);
+    let mut next_kind = || rx.try_recv().expect("one event per edit").kind();
+
+    buffer.insert(TextPosition { byte_idx: 10 }, "    ").await;
+    assert_eq!(next_kind(), entities::EditKind::IndentationOnly);
+    buffer.insert(TextPosition { byte_idx: 8 }, " ").await;
+    assert_eq!(next_kind(), entities::EditKind::WhitespaceOnly);
+    buffer.insert(TextPosition { byte_idx: 0 }, "pub ").await;
+    assert_eq!(next_kind(), entities::EditKind::Normal);
+    assert!(buffer.trim_trailing_whitespace().await);
+    assert_eq!(next_kind(), entities::EditKind::WhitespaceOnly);
+    assert!(buffer.normalize_line_endings(LineEnding::Lf).await);
+    assert_eq!(next_kind(), entities::EditKind::WhitespaceOnly);
+    assert_eq!(buffer.get_text(), "pub fn f() {\n    x\n}");
+    assert!(!buffer.normalize_line_endings(LineEnding::Lf).await);
+    buffer.reload("").await;
+    assert_eq!(next_kind(), entities::EditKind::Reload);
+}
+
+#[tokio::test]
+async fn highlight_spans_follow_edits_and_go_stale_when_touched() {
+    use entities::{HighlightLayer, StyleId};
+    let buffer = std::sync::Arc::new(TextBuffer::new("fn main() { let x = 1; }"));
+    let mut layer = HighlightLayer::new(buffer.clone());
+    let (keyword, ident) = (StyleId(1), StyleId(2));
+    layer.set_spans(vec![(TextRange::new(0, 2), keyword), (TextRange::new(3, 7), ident), (TextRange::new(12, 15), keyword)]);
+
+    // An edit between spans shifts the later one; typing at a span's edge makes it stale.
+    buffer.insert(TextPosition { byte_idx: 10 }, "  ").await;
+    buffer.insert(TextPosition { byte_idx: 7 }, "_2").await;
+    assert_eq!(layer.spans_in_range(TextRange::new(0, 100)), vec![(TextRange::new(0, 2), keyword), (TextRange::new(16, 19), keyword)]);
+    assert_eq!(layer.spans_in_range(TextRange::new(2, 16)), vec![]);
+    assert_eq!(layer.take_stale(), vec![TextRange::new(3, 9)]);
+    assert_eq!(layer.take_stale(), vec![]);
+
+    layer.set_spans(vec![(TextRange::new(3, 9), ident)]);
+    assert_eq!(layer.spans_in_range(TextRange::new(0, 100)), vec![(TextRange::new(3, 9), ident)]);
+}
+
+#[tokio::test]
+async fn changes_since_merges_edits_and_reports_eviction() {
+    let buffer = TextBuffer::new("alpha beta gamma");
+    let start = buffer.version();
+    assert_eq!(buffer.changes_since(start), Some(vec![]));
+
+    buffer.insert(TextPosition { byte_idx: 5 }, "-one").await;
+    buffer.remove(TextPosition { byte_idx: 14 }, 6).await;
+    buffer.insert(TextPosition { byte_idx: 0 }, ">").await;
+    assert_eq!(buffer.get_text(), ">alpha-one beta");
+    // The first insertion shifted by the last one; the removed " gamma" left an empty range.
+    assert_eq!(buffer.changes_since(start), Some(vec![TextRange::new(0, 1), TextRange::new(6, 10), TextRange::new(15, 15)]));
+    assert_eq!(buffer.changes_since(buffer.version() - 1), Some(vec![TextRange::new(0, 1)]));
+    assert_eq!(buffer.changes_since(buffer.version() + 1), None);
+
+    for _ in 0..1024 {
+        buffer.insert(TextPosition { byte_idx: 0 }, "x").await;
+    }
+    assert_eq!(buffer.changes_since(start), None);
+    assert_eq!(buffer.changes_since(buffer.version() - 2), Some(vec![TextRange::new(0, 2)]));
+}
+
+#[test]
+fn indentation_of_line_expands_tabs_and_covers_blank_lines() {
+    let buffer = TextBuffer::new("fn f() {\n  \tx\n   \n\t");
+    assert_eq!(buffer.indentation_of_line(0, 4), Some((0, 0)));
+    assert_eq!(buffer.indentation_of_line(1, 4), Some((3, 4)));
+    assert_eq!(buffer.indentation_of_line(1, 8), Some((3, 8)));
+    assert_eq!(buffer.indentation_of_line(2, 4), Some((3, 3)));
+    assert_eq!(buffer.indentation_of_line(3, 2), Some((1, 2)));
+    assert_eq!(buffer.indentation_of_line(4, 4), None);
+}
+
+#[test]
+fn char_boundaries_step_whole_codepoints() {
+    let buffer = TextBuffer::new("aé€");
+    assert_eq!(buffer.next_char_boundary(0), Some(1));
+    assert_eq!(buffer.next_char_boundary(1), Some(3));
+    assert_eq!(buffer.next_char_boundary(4), Some(6));
+    assert_eq!(buffer.next_char_boundary(6), Some(6));
+    assert_eq!(buffer.prev_char_boundary(6), Some(3));
+    assert_eq!(buffer.prev_char_boundary(2), Some(1));
+    assert_eq!(buffer.prev_char_boundary(0), Some(0));
+    assert_eq!(buffer.next_char_boundary(7), None);
+    assert_eq!(buffer.prev_char_boundary(7), None);
+}
+
+#[test]
+fn positions_from_line_and_column_are_validated() {
+    let buffer = TextBuffer::new("héllo\r\nwörld\n");
+    assert_eq!(buffer.position_at(0, 2), Some(TextPosition { byte_idx: 3 }));
+    assert_eq!(buffer.position_at(0, 5), Some(TextPosition { byte_idx: 6 }));
+    assert_eq!(buffer.position_at(0, 6), None);
+    assert_eq!(TextPosition::at_line_col(&buffer, 1, 2), Some(TextPosition { byte_idx: 11 }));
+    assert_eq!(buffer.position_at(2, 0), Some(TextPosition { byte_idx: 15 }));
+    assert_eq!(buffer.position_at(3, 0), None);
+}
+
+#[tokio::test]
+async fn log_buffer_coalesces_appends_and_trims_old_lines() {
+    let log = entities::LogBuffer::new(3);
+    let (tx, mut rx) = mpsc::channel(8);
+    log.buffer().add_observer(tx);
+    assert!(!log.flush().await);
+
+    log.append_line("one");
+    log.append_line("two");
+    assert!(log.flush().await);
+    assert!(matches!(rx.try_recv(), Ok(TextBufferChangedEvent::Appended { start_byte_idx: 0, line_count: 2, .. })));
+    assert!(rx.try_recv().is_err());
+
+    log.append_line("three");
+    log.append_line("four");
+    log.append_line("five");
+    assert!(log.flush().await);
+    assert!(matches!(rx.try_recv(), Ok(TextBufferChangedEvent::Appended { start_byte_idx: 8, line_count: 3, dirty_line_range, .. }) if dirty_line_range == (2..6)));
+    assert!(matches!(rx.try_recv(), Ok(TextBufferChangedEvent::Removed { start_byte_idx: 0, len_bytes: 8, .. })));
+    assert_eq!(log.buffer().get_text(), "three\nfour\nfive\n");
+    assert_eq!(log.buffer().changes_since(0), Some(vec![TextRange::new(0, 16)]));
+}
+
+#[test]
+fn home_and_end_targets_skip_surrounding_whitespace() {
+    let buffer = TextBuffer::new("\t  let x = 1;  \t\r\n   \nend");
+    assert_eq!(buffer.first_non_whitespace(0), Some(TextPosition { byte_idx: 3 }));
+    assert_eq!(buffer.line_content_end(0), Some(TextPosition { byte_idx: 13 }));
+    // A blank line: its whitespace end, and its start.
+    assert_eq!(buffer.first_non_whitespace(1), Some(TextPosition { byte_idx: 21 }));
+    assert_eq!(buffer.line_content_end(1), Some(TextPosition { byte_idx: 18 }));
+    assert_eq!(buffer.first_non_whitespace(2), Some(TextPosition { byte_idx: 22 }));
+    assert_eq!(buffer.line_content_end(2), Some(TextPosition { byte_idx: 25 }));
+    assert_eq!(buffer.first_non_whitespace(3), None);
+    assert_eq!(buffer.line_content_end(3), None);
+}
+
+#[test]
+fn scratch_buffers_read_back_as_a_single_line() {
+    let field = TextBuffer::scratch("new_\r\nname\n");
+    assert_eq!(field.as_single_line(), "new_name");
+    assert_eq!(TextBuffer::scratch("").as_single_line(), "");
+    assert_eq!(TextBuffer::scratch("a\rb\u{2028}c").as_single_line(), "abc");
+}
+
+#[test]
+fn ranges_come_with_char_and_line_counts() {
+    let buffer = TextBuffer::new("héllo\r\nwörld\nend");
+    let meta = buffer.get_range_with_meta(TextRange::new(1, 15));
+    assert_eq!(meta, entities::RangeText { text: "éllo\r\nwörld\n".to_string(), char_len: 12, line_count: 3 });
+    assert_eq!(buffer.get_range_with_meta(TextRange::new(0, 0)).line_count, 1);
+}
+
+#[tokio::test]
+async fn markers_iterate_in_current_document_order() {
+    use entities::{Bookmark, Bookmarks, Diagnostic, DiagnosticSeverity, Diagnostics};
+    let buffer = std::sync::Arc::new(TextBuffer::new("let a = b;\nlet c = d;\n"));
+    let mut diagnostics = Diagnostics::new(buffer.clone());
+    let error = |message: &str| Diagnostic { severity: DiagnosticSeverity::Error, message: message.to_string() };
+    assert!(diagnostics.add(TextRange::new(19, 20), error("unknown d")));
+    assert!(diagnostics.add(TextRange::new(8, 9), error("unknown b")));
+    assert!(!diagnostics.add(TextRange::new(20, 40), error("past the end")));
+    let mut bookmarks = Bookmarks::new(buffer.clone());
+    bookmarks.add(TextRange::new(11, 11), Bookmark::default());
+
+    buffer.insert(TextPosition { byte_idx: 11 }, "x;\n").await;
+    let ranges: Vec<TextRange> = diagnostics.iter_sorted().map(|(range, _)| range).collect();
+    assert_eq!(ranges, vec![TextRange::new(8, 9), TextRange::new(22, 23)]);
+    assert_eq!(diagnostics.iter_sorted().next().unwrap().1.message, "unknown b");
+    assert_eq!(bookmarks.iter_sorted().map(|(range, _)| range).collect::<Vec<_>>(), vec![TextRange::new(11, 11)]);
+}
+
+#[tokio::test]
+async fn detached_observers_miss_edits_until_resynced() {
+    let buffer = TextBuffer::new("abc");
+    let (mut rx, id) = buffer.register(8);
+    let saved = buffer.detach_observers();
+    assert_eq!(buffer.observer_count(), 0);
+    buffer.insert(TextPosition { byte_idx: 3 }, "def").await;
+    buffer.remove(TextPosition { byte_idx: 0 }, 1).await;
+    assert!(rx.try_recv().is_err());
+
+    buffer.reattach_observers(saved);
+    buffer.resync_observers().await;
+    match rx.try_recv().unwrap() {
+        TextBufferChangedEvent::Reloaded { len_bytes, version, .. } => {
+            assert_eq!(len_bytes, 5);
+            assert_eq!(version, buffer.version());
+        }
+        other => panic!("unexpected event: {other:?}"),
+    }
+    assert!(rx.try_recv().is_err());
+    assert!(buffer.remove_observer(id));
+}
+
+#[tokio::test]
+async fn nfc_normalization_composes_inserted_text() {
+    use entities::{Normalization, TextRange};
+    let buffer = TextBuffer::new("");
+    buffer.insert(TextPosition { byte_idx: 0 }, "e\u{301}").await;
+    assert_eq!(buffer.len_bytes(), 3);
+
+    buffer.set_normalization(Normalization::Nfc);
+    let (mut rx, _) = buffer.register(4);
+    buffer.insert(TextPosition { byte_idx: 3 }, " cafe\u{301}").await;
+    assert_eq!(buffer.get_text(), "e\u{301} café");
+    match rx.try_recv().unwrap() {
+        TextBufferChangedEvent::Inserted { len_bytes, .. } => assert_eq!(len_bytes, 6),
+        other => panic!("unexpected event: {other:?}"),
+    }
+    buffer.replace(TextRange::new(0, 3), "n\u{303}").await.unwrap();
+    assert_eq!(buffer.get_text(), "ñ café");
+}
+
+#[test]
+fn expand_selection_grows_word_line_block_buffer() {
+    let buffer = TextBuffer::new("fn main() {\n    let value = 1;\n    if x {\n        go();\n    }\n}\n");
+    assert_eq!(buffer.word_at(22), Some(TextRange::new(20, 25)));
+    assert_eq!(buffer.word_at(25), Some(TextRange::new(20, 25)));
+    assert_eq!(buffer.word_at(26), None);
+
+    let mut selection = TextRange::new(22, 22);
+    let mut steps = Vec::new();
+    for _ in 0..5 {
+        selection = buffer.expand_selection(selection);
+        steps.push(selection);
+    }
+    assert_eq!(steps, vec![
+        TextRange::new(20, 25),
+        TextRange::new(12, 31),
+        TextRange::new(12, 62),
+        TextRange::new(0, 64),
+        TextRange::new(0, 64),
+    ]);
+
+    assert_eq!(buffer.shrink_selection(TextRange::new(12, 62)), TextRange::new(12, 31));
+    assert_eq!(buffer.shrink_selection(TextRange::new(12, 31)), TextRange::new(12, 12));
+    assert_eq!(buffer.shrink_selection(TextRange::new(20, 62)), TextRange::new(20, 25));
+}
+
+#[test]
+fn trimmed_lines_drop_any_line_ending() {
+    let buffer = TextBuffer::new("one\r\ntwo\nthree");
+    assert_eq!(buffer.line_trimmed(0).as_deref(), Some("one"));
+    assert_eq!(buffer.line_trimmed(2).as_deref(), Some("three"));
+    assert_eq!(buffer.line_trimmed(3), None);
+    assert_eq!(buffer.lines_trimmed_in_range(1..10), vec!["two", "three"]);
+
+    let buffer = TextBuffer::new("a\n");
+    assert_eq!(buffer.lines_trimmed_in_range(0..2), vec!["a", ""]);
+}
+
+#[tokio::test]
+async fn edit_locations_follow_later_edits() {
+    let buffer = TextBuffer::new("one\ntwo\nthree\n");
+    assert_eq!(buffer.last_edit_position(), None);
+
+    buffer.insert(TextPosition { byte_idx: 7 }, "s").await;
+    buffer.insert(TextPosition { byte_idx: 8 }, "!").await;
+    buffer.remove(TextPosition { byte_idx: 10 }, 3).await;
+    assert_eq!(buffer.last_edit_position(), Some(TextPosition { byte_idx: 10 }));
+
+    // An edit above shifts the remembered locations.
+    buffer.insert(TextPosition { byte_idx: 0 }, ">> ").await;
+    assert_eq!(buffer.edit_history_positions(5), vec![
+        TextPosition { byte_idx: 3 },
+        TextPosition { byte_idx: 13 },
+        TextPosition { byte_idx: 12 },
+    ]);
+}
+
+#[tokio::test]
+async fn display_streams_the_text_and_debug_dump_summarizes() {
+    let buffer = TextBuffer::new("fn main() {\n\t\"hi\"\n}");
+    assert_eq!(buffer.to_string(), buffer.get_text());
+    assert_eq!(format!("[{buffer}]"), "[fn main() {\n\t\"hi\"\n}]");
+
+    buffer.insert(TextPosition { byte_idx: 0 }, "pub ").await;
+    let (_rx, _) = buffer.register(1);
+    assert_eq!(
+        buffer.debug_dump(),
+        r#"TextBuffer { version: 1, len_bytes: 23, len_lines: 3, observer_count: 1, preview: "pub fn main() {\n\t\"hi\"\n}" }"#,
+    );
+    let long = TextBuffer::new(&"ab".repeat(30));
+    assert!(long.debug_dump().ends_with(&format!("preview: \"{}\"... }}", "ab".repeat(20))));
+}
+
+#[test]
+fn contains_finds_matches_spanning_chunks() {
+    let text = "hay ".repeat(2000) + "Needle" + &" hay".repeat(10);
+    let buffer = TextBuffer::new(&text);
+    assert!(buffer.contains("Needle", true));
+    assert!(!buffer.contains("needle", true));
+    assert!(buffer.contains("needle", false));
+    assert!(buffer.contains("hay Needle hay", true));
+    assert!(!buffer.contains("hayhay", false));
+    assert!(!buffer.contains("", true));
+}
+
+#[test]
+fn grapheme_columns_count_clusters_per_line() {
+    let family = "\u{1F468}\u{200D}\u{1F469}\u{200D}\u{1F467}";
+    let buffer = TextBuffer::new(&format!("{family}e\u{0301}xyz\r\nsecond"));
+    assert_eq!(buffer.grapheme_to_byte(0, 0), Some(0));
+    assert_eq!(buffer.grapheme_to_byte(0, 1), Some(18));
+    assert_eq!(buffer.grapheme_to_byte(0, 2), Some(21));
+    assert_eq!(buffer.grapheme_to_byte(0, 5), Some(24));
+    assert_eq!(buffer.grapheme_to_byte(0, 6), None);
+    assert_eq!(buffer.grapheme_to_byte(1, 3), Some(29));
+    assert_eq!(buffer.grapheme_to_byte(2, 0), None);
+
+    assert_eq!(buffer.byte_to_grapheme(21), Some((0, 2)));
+    assert_eq!(buffer.byte_to_grapheme(7), Some((0, 0)));
+    assert_eq!(buffer.byte_to_grapheme(25), Some((0, 5)));
+    assert_eq!(buffer.byte_to_grapheme(32), Some((1, 6)));
+    assert_eq!(buffer.byte_to_grapheme(33), None);
+}
+
+#[tokio::test]
+async fn apply_inverse_reverts_events_as_undo_edits() {
+    use entities::{EditOrigin, TextRange};
+    let buffer = TextBuffer::new("hello world");
+    let (mut rx, _) = buffer.register(8);
+    buffer.replace(TextRange::new(0, 5), "goodbye").await.unwrap();
+    let replaced = rx.try_recv().unwrap();
+    buffer.insert(TextPosition { byte_idx: 13 }, "!").await;
+    let inserted = rx.try_recv().unwrap();
+
+    buffer.apply_inverse(&inserted).await.unwrap();
+    let inverse = rx.try_recv().unwrap();
+    assert_eq!(inverse.origin(), EditOrigin::Undo);
+    buffer.apply_inverse(&replaced).await.unwrap();
+    assert_eq!(buffer.get_text(), "hello world");
+    assert!(matches!(rx.try_recv().unwrap(), TextBufferChangedEvent::Batch(_)));
+
+    // The inserted text is gone now, so inverting again can't work.
+    assert_eq!(buffer.apply_inverse(&replaced).await, Err(EditError::TextMismatch));
+    assert!(matches!(buffer.apply_inverse(&inserted).await, Err(EditError::OutOfBounds { .. })));
+    buffer.reload("other").await;
+    let reloaded = rx.try_recv().unwrap();
+    assert_eq!(buffer.apply_inverse(&reloaded).await, Err(EditError::NotInvertible));
+    assert_eq!(buffer.get_text(), "other");
+}
+
+#[tokio::test]
+async fn prefix_and_suffix_lines_in_one_event() {
+    let buffer = TextBuffer::new("one\n\ntwo\r\nthree");
+    let (mut rx, _) = buffer.register(4);
+    assert_eq!(buffer.prefix_lines(0..3, "> ", true).await, 4);
+    assert_eq!(buffer.get_text(), "> one\n\n> two\r\nthree");
+    match rx.try_recv().unwrap() {
+        TextBufferChangedEvent::Batch(members) => assert_eq!(members.len(), 2),
+        other => panic!("unexpected event: {other:?}"),
+    }
+
+    assert_eq!(buffer.suffix_lines(1..10, ";", false).await, 3);
+    assert_eq!(buffer.get_text(), "> one\n;\n> two;\r\nthree;");
+    assert_eq!(buffer.prefix_lines(5..9, "\t", false).await, 0);
+    assert!(rx.try_recv().is_ok() && rx.try_recv().is_err());
+}
+
+#[tokio::test]
+async fn dropping_an_observer_guard_unsubscribes() {
+    let buffer = TextBuffer::new("");
+    let (mut rx, guard) = buffer.register_guarded(4);
+    let (_other_rx, _other) = buffer.register(4);
+    buffer.insert(TextPosition { byte_idx: 0 }, "a").await;
+    assert!(rx.try_recv().is_ok());
+    assert_eq!(buffer.observer_count(), 2);
+
+    let id = guard.id();
+    drop(guard);
+    assert_eq!(buffer.observer_count(), 1);
+    assert!(!buffer.remove_observer(id));
+    buffer.insert(TextPosition { byte_idx: 1 }, "b").await;
+    assert!(rx.try_recv().is_err());
+
+    // A guard outliving its buffer is harmless.
+    let buffer = TextBuffer::new("");
+    let (_rx, guard) = buffer.register_guarded(1);
+    drop(buffer);
+    drop(guard);
+}
+
+#[tokio::test]
+async fn reveal_requests_bypass_change_observers() {
+    use entities::ViewRequest;
+    let buffer = TextBuffer::new("fn a() {}\nfn b() {}\n");
+    let (mut changes, _) = buffer.register(4);
+    let mut view = buffer.subscribe_view_requests(1);
+    let closed = buffer.subscribe_view_requests(1);
+    drop(closed);
+
+    buffer.request_reveal(TextRange::new(10, 19));
+    // The channel is full, so this one is dropped rather than waited for.
+    buffer.request_reveal(TextRange::new(0, 9));
+    assert_eq!(view.try_recv().unwrap(), ViewRequest::Reveal(TextRange::new(10, 19)));
+    assert!(view.try_recv().is_err());
+    assert!(changes.try_recv().is_err());
+    assert_eq!(buffer.version(), 0);
+}
+
+#[test]
+fn brackets_match_and_enclose_with_nesting() {
+    let buffer = TextBuffer::new("call(a[1], {b}) + (c");
+    assert_eq!(buffer.matching_bracket(4), Some(14));
+    assert_eq!(buffer.matching_bracket(14), Some(4));
+    assert_eq!(buffer.matching_bracket(11), Some(13));
+    assert_eq!(buffer.matching_bracket(18), None);
+    assert_eq!(buffer.matching_bracket(0), None);
+
+    assert_eq!(buffer.enclosing_brackets(7), Some((6, 8)));
+    assert_eq!(buffer.enclosing_brackets(9), Some((4, 14)));
+    assert_eq!(buffer.enclosing_brackets(11), Some((4, 14)));
+    assert_eq!(buffer.enclosing_brackets(15), None);
+    // The unclosed '(' at the end isn't a pair.
+    assert_eq!(buffer.enclosing_brackets(20), None);
+}
+
+// Hands out its bytes a few at a time, so chars get split across reads.
+struct TrickleReader<'a>(&'a [u8]);
+
+impl std::io::Read for TrickleReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let len = self.0.len().min(buf.len()).min(3);
+        buf[..len].copy_from_slice(&self.0[..len]);
+        self.0 = &self.0[len..];
+        Ok(len)
+    }
+}
+
+#[tokio::test]
+async fn splice_streams_the_replacement_in() {
+    use entities::TextRange;
+    let buffer = TextBuffer::new("keep [old middle] keep");
+    let (mut rx, _) = buffer.register(4);
+    let replaced = buffer.splice(TextRange::new(5, 17), TrickleReader("«naïve» ✓ text".as_bytes())).await.unwrap();
+    assert_eq!(buffer.get_text(), "keep «naïve» ✓ text keep");
+    assert_eq!(replaced, TextRange::new(5, 5 + "«naïve» ✓ text".len()));
+    assert!(matches!(rx.try_recv().unwrap(), TextBufferChangedEvent::Reloaded { version: 1, .. }));
+    assert_eq!(buffer.stats().words, 5);
+
+    // Invalid UTF-8 stops the splice but keeps (and reports) what was read.
+    let error = buffer.splice(TextRange::new(0, 4), &b"ab\xFFcd"[..]).await.unwrap_err();
+    assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+    assert!(buffer.get_text().starts_with("ab «"));
+    assert!(rx.try_recv().is_ok());
+    assert_eq!(
+        buffer.splice(TextRange::new(0, 99), &b""[..]).await.unwrap_err().kind(),
+        std::io::ErrorKind::InvalidInput,
+    );
+}
+
+#[test]
+fn line_display_widths_count_cells() {
+    let buffer = TextBuffer::new("a\tb\n日本\r\ne\u{301}x\n");
+    assert_eq!(buffer.line_display_width(0, 4), Some(5));
+    assert_eq!(buffer.line_display_width(1, 4), Some(4));
+    assert_eq!(buffer.line_display_width(2, 4), Some(2));
+    assert_eq!(buffer.line_display_width(3, 4), Some(0));
+    assert_eq!(buffer.line_display_width(4, 4), None);
+    assert_eq!(buffer.line_display_widths(0..10, 8), vec![9, 4, 2, 0]);
+    assert_eq!(buffer.line_display_widths(5..6, 8), Vec::<usize>::new());
+}
+
+#[test]
+fn classify_position_sees_strings_and_comments() {
+    let syntax = LangSyntax::c_like();
+    let buffer = TextBuffer::new("a(\"x)\") // c\n/* one\ntwo */ b \"\\\"\" '\nc");
+    assert_eq!(buffer.classify_position(2, &syntax), PositionClass::Code);
+    assert_eq!(buffer.classify_position(3, &syntax), PositionClass::String);
+    assert_eq!(buffer.classify_position(6, &syntax), PositionClass::Code);
+    assert_eq!(buffer.classify_position(11, &syntax), PositionClass::LineComment);
+    // A block comment opened on an earlier line.
+    assert_eq!(buffer.classify_position(21, &syntax), PositionClass::BlockComment);
+    assert_eq!(buffer.classify_position(28, &syntax), PositionClass::Code);
+    // An escaped quote doesn't close the string; an unterminated one ends with its line.
+    assert_eq!(buffer.classify_position(32, &syntax), PositionClass::String);
+    assert_eq!(buffer.classify_position(34, &syntax), PositionClass::Code);
+    assert_eq!(buffer.classify_position(35, &syntax), PositionClass::String);
+    assert_eq!(buffer.classify_position(36, &syntax), PositionClass::Code);
+}
+
+#[test]
+fn small_buffers_can_be_read_without_copying() {
+    let buffer = TextBuffer::new("[core]\nname = x\n");
+    assert_eq!(buffer.as_str_if_contiguous(|text| text.map(str::len)), Some(16));
+    let large = TextBuffer::new(&"line of text\n".repeat(100_000));
+    assert!(large.as_str_if_contiguous(|text| text.is_none()));
+}
+
+#[tokio::test]
+async fn delete_lines_remaps_the_cursor() {
+    let buffer = TextBuffer::new("a\nbcd\nef\ng");
+    let cursor = buffer.delete_lines(1, 1, TextPosition { byte_idx: 4 }).await.unwrap();
+    assert_eq!(buffer.get_text(), "a\nef\ng");
+    assert_eq!(cursor, TextPosition { byte_idx: 4 });
+
+    // A cursor below the deleted lines moves up with its line.
+    let cursor = buffer.delete_lines(0, 1, TextPosition { byte_idx: 5 }).await.unwrap();
+    assert_eq!(buffer.get_text(), "ef\ng");
+    assert_eq!(cursor, TextPosition { byte_idx: 3 });
+
+    // The count is clamped, and deleting the last line takes the break before it.
+    let cursor = buffer.delete_lines(1, 10, TextPosition { byte_idx: 4 }).await.unwrap();
+    assert_eq!(buffer.get_text(), "ef");
+    assert_eq!(cursor, TextPosition { byte_idx: 1 });
+
+    assert_eq!(
+        buffer.delete_lines(1, 1, TextPosition { byte_idx: 0 }).await,
+        Err(EditError::LineOutOfBounds { line: 1, len_lines: 1 })
+    );
+    assert_eq!(buffer.delete_lines(0, 0, TextPosition { byte_idx: 1 }).await, Ok(TextPosition { byte_idx: 1 }));
+    assert_eq!(buffer.get_text(), "ef");
+}
+
+#[test]
+fn case_insensitive_search_uses_full_case_folding() {
+    let buffer = TextBuffer::new("Straße und STRASSE, ﬁle");
+    let opts = SearchOptions { case_insensitive: true, ..SearchOptions::default() };
+    let expected = vec![TextRange::new(0, 7), TextRange::new(12, 19)];
+    assert_eq!(buffer.find_all_opts("strasse", opts), expected);
+    assert_eq!(buffer.find_all_opts("STRAẞE", opts), expected);
+    assert_eq!(buffer.find_all_opts("FILE", opts), vec![TextRange::new(21, 26)]);
+    // A match can't end inside the expansion of one char.
+    assert_eq!(buffer.count_matches("stras", false), 1);
+    assert!(!buffer.contains("f", false));
+}
+
+#[test]
+fn viewport_reads_lines_with_their_offsets() {
+    let buffer = TextBuffer::new("one\r\ntwo\nthree\n");
+    let viewport = buffer.viewport(1, 5);
+    assert_eq!(viewport.total_lines, 4);
+    assert_eq!(viewport.lines, vec![(5, "two".to_string()), (9, "three".to_string()), (15, String::new())]);
+    assert!(buffer.viewport(9, 2).lines.is_empty());
+    assert_eq!(buffer.viewport(0, 1).lines, vec![(0, "one".to_string())]);
+}
+
+#[tokio::test]
+async fn dirty_span_since_merges_changes_into_one_span() {
+    let buffer = TextBuffer::new("alpha beta gamma");
+    let start = buffer.version();
+    assert_eq!(buffer.dirty_span_since(start), DirtySince::UpToDate);
+
+    buffer.insert(TextPosition { byte_idx: 5 }, "-one").await;
+    buffer.remove(TextPosition { byte_idx: 14 }, 6).await;
+    assert_eq!(buffer.dirty_span_since(start), DirtySince::Changed(TextRange::new(5, 14)));
+    assert_eq!(buffer.dirty_span_since(buffer.version() - 1), DirtySince::Changed(TextRange::new(14, 14)));
+    assert_eq!(buffer.dirty_span_since(buffer.version() + 1), DirtySince::FullResync);
+}
+
+#[tokio::test]
+async fn snippet_sessions_navigate_shifted_tab_stops() {
+    let buffer = std::sync::Arc::new(TextBuffer::new("\n"));
+    let mut session = SnippetSession::insert(buffer.clone(), TextPosition { byte_idx: 0 }, "for ${1:item} in ${2:iter}:\n\t$0").await.unwrap();
+    assert_eq!(session.current(), Some(TextRange::new(4, 8)));
+    assert_eq!(session.prev(), None);
+
+    // Typing over the first placeholder shifts the second.
+    buffer.replace(TextRange::new(4, 8), "x").await.unwrap();
+    assert_eq!(session.next(), Some(TextRange::new(9, 13)));
+    assert_eq!(session.prev(), Some(TextRange::new(4, 5)));
+    assert_eq!(session.next(), Some(TextRange::new(9, 13)));
+    assert!(!session.is_complete());
+
+    // Reaching '$0' ends the session.
+    assert_eq!(session.next(), Some(TextRange::new(16, 16)));
+    assert!(session.is_complete());
+    assert_eq!(session.next(), None);
+    assert_eq!(session.current(), None);
+}
+
+#[test]
+fn merge_selections_collapses_overlaps_and_keeps_the_primary() {
+    let mut ranges = vec![
+        TextRange::new(10, 12),
+        TextRange::new(0, 3),
+        TextRange::new(3, 5),
+        TextRange::new(20, 20),
+        TextRange::new(11, 15),
+        TextRange::new(20, 20),
+    ];
+    assert_eq!(merge_selections(&mut ranges, 4), 1);
+    assert_eq!(ranges, vec![TextRange::new(0, 5), TextRange::new(10, 15), TextRange::new(20, 20)]);
+
+    let mut ranges = vec![TextRange::new(7, 7), TextRange::new(1, 1)];
+    assert_eq!(merge_selections(&mut ranges, 0), 1);
+    assert_eq!(ranges, vec![TextRange::new(1, 1), TextRange::new(7, 7)]);
+    assert_eq!(merge_selections(&mut Vec::new(), 3), 0);
+}
+
+#[tokio::test]
+async fn large_inserts_are_reported_in_bounded_chunks() {
+    let buffer = TextBuffer::new("<>");
+    buffer.set_max_event_payload(Some(4));
+    let (mut rx, _) = buffer.register(8);
+    buffer.insert(TextPosition { byte_idx: 1 }, "abcdéfgh").await;
+    assert_eq!(buffer.get_text(), "<abcdéfgh>");
+
+    let mut chunks = Vec::new();
+    while let Ok(TextBufferChangedEvent::Inserted { start_byte_idx, text, version, .. }) = rx.try_recv() {
+        chunks.push((start_byte_idx, text.to_string(), version));
+    }
+    assert_eq!(chunks, vec![(1, "abcd".to_string(), 1), (5, "éfg".to_string(), 2), (9, "h".to_string(), 3)]);
+    assert_eq!(buffer.version(), 3);
+
+    // Inserts within the limit stay whole.
+    buffer.insert(TextPosition { byte_idx: 0 }, "1234").await;
+    assert!(matches!(rx.try_recv(), Ok(TextBufferChangedEvent::Inserted { len_bytes: 4, .. })));
+    assert_eq!(buffer.max_event_payload(), Some(4));
+}
+
+#[test]
+fn columns_of_bytes_count_chars_and_tabs() {
+    let buffer = TextBuffer::new("ab\n\tké\tx\n");
+    assert_eq!(buffer.column_of_byte(1), 1);
+    assert_eq!(buffer.column_of_byte(3), 0);
+    // 'é' is two bytes; the byte inside it counts as its start.
+    assert_eq!(buffer.column_of_byte(7), 3);
+    assert_eq!(buffer.column_of_byte(6), 2);
+    assert_eq!(buffer.column_of_byte(100), 0);
+    assert_eq!(buffer.visual_column_of_byte(4, 4), 4);
+    assert_eq!(buffer.visual_column_of_byte(8, 4), 8);
+    assert_eq!(buffer.visual_column_of_byte(9, 4), 9);
+    assert_eq!(buffer.visual_column_of_byte(9, 0), 5);
+}
+
+#[tokio::test]
+async fn recordings_round_trip_and_replay() {
+    let buffer = TextBuffer::new("fn main() {}\n");
+    let recorder = RecordingObserver::attach(&buffer);
+    buffer.insert(TextPosition { byte_idx: 11 }, "\n    println!(\"hi\");\n").await;
+    buffer.remove(TextPosition { byte_idx: 0 }, 3).await;
+    buffer.apply_edits(vec![(0, 4, "start".to_string()), (7, 7, " ".to_string())]).await.unwrap();
+    assert_eq!(recorder.len(), 3);
+    let recording = recorder.finish(&buffer);
+    assert_eq!(recording.final_text, buffer.get_text());
+
+    let path = std::env::temp_dir().join(format!("entities-recording-{}.json", std::process::id()));
+    save_recording(&recording, &path).unwrap();
+    let loaded = load_recording(&path).unwrap();
+    assert_eq!(format!("{loaded:?}"), format!("{recording:?}"));
+
+    // A file cut short, or one that isn't a recording, is rejected.
+    let saved = std::fs::read_to_string(&path).unwrap();
+    std::fs::write(&path, &saved[..saved.len() / 2]).unwrap();
+    assert!(load_recording(&path).is_err());
+    std::fs::write(&path, saved.replace("\"Inserted\"", "\"Pasted\"")).unwrap();
+    assert_eq!(load_recording(&path).unwrap_err().kind(), std::io::ErrorKind::InvalidData);
+    std::fs::remove_file(&path).unwrap();
+
+    let fresh = TextBuffer::new(&loaded.initial_text);
+    replay_recording(&fresh, &loaded).await.unwrap();
+    assert_eq!(fresh.get_text(), buffer.get_text());
+
+    let mut tampered = loaded.clone();
+    tampered.final_text.push('!');
+    assert_eq!(replay_recording(&TextBuffer::new(&loaded.initial_text), &tampered).await, Err(ReplayError::FinalStateMismatch));
+    assert_eq!(replay_recording(&TextBuffer::new(""), &loaded).await, Err(ReplayError::InitialTextMismatch));
+}
+
+#[tokio::test]
+async fn can_insert_and_can_remove_validate_without_editing() {
+    let buffer = TextBuffer::new("héllo world");
+    let version = buffer.version();
+    assert_eq!(buffer.can_insert(TextPosition { byte_idx: 0 }, "x"), Ok(()));
+    assert_eq!(buffer.can_insert(TextPosition { byte_idx: 2 }, "x"), Err(EditError::NotCharBoundary { byte_idx: 2 }));
+    assert_eq!(buffer.can_remove(TextPosition { byte_idx: 6 }, 6), Ok(()));
+    assert_eq!(buffer.can_remove(TextPosition { byte_idx: 6 }, 7), Err(EditError::OutOfBounds { start: 6, end: 13, len_bytes: 12 }));
+
+    buffer.add_protected_region(TextRange::new(0, 6));
+    assert_eq!(buffer.can_remove(TextPosition { byte_idx: 3 }, 2), Err(EditError::ProtectedRegion));
+    assert_eq!(buffer.get_text(), "héllo world");
+    assert_eq!(buffer.version(), version);
+}
+
+#[test]
+fn same_line_compares_lines_of_two_offsets() {
+    let buffer = TextBuffer::new("ab\ncd\n");
+    assert!(buffer.same_line(0, 2));
+    assert!(!buffer.same_line(2, 3));
+    assert!(buffer.same_line(5, 3));
+    assert!(buffer.same_line(6, 100));
+}
+
+#[tokio::test]
+async fn replace_line_keeps_the_line_ending() {
+    let buffer = TextBuffer::new("use b;\r\nuse a, c;\nend");
+    assert_eq!(buffer.replace_line(1, "use {a, c};").await, Ok(TextPosition { byte_idx: 19 }));
+    assert_eq!(buffer.replace_line(0, "").await, Ok(TextPosition { byte_idx: 0 }));
+    assert_eq!(buffer.replace_line(2, "fin").await, Ok(TextPosition { byte_idx: 17 }));
+    assert_eq!(buffer.get_text(), "\r\nuse {a, c};\nfin");
+    assert_eq!(buffer.replace_line(3, "x").await, Err(EditError::LineOutOfBounds { line: 3, len_lines: 3 }));
+}
+
+#[tokio::test]
+async fn on_idle_reports_the_version_once_editing_pauses() {
+    let buffer = TextBuffer::new("");
+    let mut idle = buffer.on_idle(std::time::Duration::from_millis(30));
+    buffer.insert(TextPosition { byte_idx: 0 }, "a").await;
+    buffer.insert(TextPosition { byte_idx: 1 }, "b").await;
+    let version = tokio::time::timeout(std::time::Duration::from_secs(2), idle.recv()).await.unwrap();
+    assert_eq!(version, Some(2));
+
+    // Nothing more arrives without further edits.
+    assert!(tokio::time::timeout(std::time::Duration::from_millis(80), idle.recv()).await.is_err());
+    buffer.insert(TextPosition { byte_idx: 2 }, "c").await;
+    let version = tokio::time::timeout(std::time::Duration::from_secs(2), idle.recv()).await.unwrap();
+    assert_eq!(version, Some(3));
+}
+
+#[test]
+fn remap_through_diff_follows_unchanged_text() {
+    let buffer = TextBuffer::new("a\nb\nc\n");
+    let new_text = "// x\na\nB\nc\nd\n";
+    // 'a' moves down, positions on the changed line go to its start, 'c' stays on 'c'.
+    assert_eq!(buffer.remap_through_diff(&[0, 2, 3, 4, 5, 99], new_text), vec![5, 7, 7, 9, 10, 13]);
+    assert_eq!(buffer.remap_through_diff(&[3], "a\nb\nc\n"), vec![3]);
+}
+
+#[tokio::test]
+async fn surround_wraps_the_range_in_one_batch() {
+    let buffer = TextBuffer::new("let x = name;");
+    let (tx, mut rx) = mpsc::channel(8);
+    buffer.add_observer(tx);
+
+    let wrapped = buffer.surround(TextRange::new(8, 12), "\"", "\"").await.unwrap();
+    assert_eq!(buffer.get_text(), "let x = \"name\";");
+    assert_eq!(wrapped, TextRange::new(8, 14));
+    assert!(matches!(rx.try_recv().unwrap(), TextBufferChangedEvent::Batch(ref events) if events.len() == 2));
+    assert!(rx.try_recv().is_err());
+
+    assert!(matches!(buffer.surround(TextRange::new(8, 99), "(", ")").await, Err(EditError::OutOfBounds { .. })));
+    assert_eq!(buffer.get_text(), "let x = \"name\";");
+}
+
+#[tokio::test]
+async fn cursor_tracker_steps_by_grapheme_and_follows_edits() {
+    let buffer = std::sync::Arc::new(TextBuffer::new("e\u{301}x\n👍🏽\nabc"));
+    let mut cursor = CursorTracker::new(std::sync::Arc::clone(&buffer), TextPosition { byte_idx: 1 });
+    // Inside "é", so snapped to its start.
+    assert_eq!(cursor.line_col(), (0, 0));
+    assert_eq!(cursor.move_right(), TextPosition { byte_idx: 3 });
+    assert_eq!(cursor.move_right(), TextPosition { byte_idx: 4 });
+    assert_eq!(cursor.move_right(), TextPosition { byte_idx: 5 });
+    assert_eq!(cursor.move_right(), TextPosition { byte_idx: 13 });
+    assert_eq!(cursor.line_col(), (1, 1));
+    assert_eq!(cursor.move_left(), TextPosition { byte_idx: 5 });
+    // Down keeps aiming for column 2 past the one-cluster line.
+    cursor.set_position(TextPosition { byte_idx: 4 });
+    assert_eq!(cursor.move_down(), TextPosition { byte_idx: 13 });
+    assert_eq!(cursor.move_down(), TextPosition { byte_idx: 16 });
+    assert_eq!(cursor.move_up(), TextPosition { byte_idx: 13 });
+
+    // An edit after the line leaves the cursor alone; one before it moves it along.
+    buffer.insert(TextPosition { byte_idx: 14 }, "!").await;
+    assert_eq!(cursor.line_col(), (1, 1));
+    buffer.insert(TextPosition { byte_idx: 0 }, "//\n").await;
+    assert_eq!(cursor.position(), TextPosition { byte_idx: 16 });
+    assert_eq!(cursor.line_col(), (2, 1));
+    assert_eq!(cursor.move_left(), TextPosition { byte_idx: 8 });
+}
@@ -0,0 +1,93 @@
+// UndoStack Tests
+use entities::{TextBuffer, TextBufferChangedEvent, TextPosition, TextRange, UndoStack};
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+#[tokio::test]
+async fn undo_coalesces_bursts_and_respects_memory_cap() {
+    let buffer = TextBuffer::new("");
+    let (tx, mut rx) = mpsc::channel(16);
+    buffer.add_observer(tx);
+    let mut undo = UndoStack::new();
+    undo.set_coalesce_window(Duration::from_secs(60));
+
+    for (i, c) in ["a", "b", "c"].iter().enumerate() {
+        buffer.insert(TextPosition { byte_idx: i }, c).await;
+        undo.record(rx.recv().await.unwrap());
+    }
+    // One undo reverts the whole burst.
+    assert!(undo.undo(&buffer).await);
+    assert_eq!(buffer.get_text(), "");
+    assert!(undo.redo(&buffer).await);
+    assert_eq!(buffer.get_text(), "abc");
+    while rx.try_recv().is_ok() {}
+
+    // Without coalescing each edit is its own group; the byte cap evicts the oldest.
+    let mut undo = UndoStack::new();
+    undo.set_coalesce_window(Duration::ZERO);
+    undo.set_max_memory(2);
+    for c in ["x", "y", "z"] {
+        buffer.insert(TextPosition { byte_idx: 0 }, c).await;
+        undo.record(rx.recv().await.unwrap());
+    }
+    assert_eq!(undo.memory_used(), 2);
+    while undo.undo(&buffer).await {}
+    assert_eq!(buffer.get_text(), "xabc");
+}
+
+#[tokio::test]
+async fn fresh_edits_clear_redo_but_replayed_edits_do_not() {
+    let buffer = TextBuffer::new("");
+    let (tx, mut rx) = mpsc::channel(16);
+    buffer.add_observer(tx);
+    let mut undo = UndoStack::new();
+    undo.set_coalesce_window(Duration::ZERO);
+
+    buffer.insert(TextPosition { byte_idx: 0 }, "a").await;
+    undo.record(rx.recv().await.unwrap());
+    buffer.insert(TextPosition { byte_idx: 1 }, "b").await;
+    undo.record(rx.recv().await.unwrap());
+
+    assert!(undo.undo(&buffer).await);
+    // The undo's own event comes back through the observer and must not clear redo.
+    undo.record(rx.recv().await.unwrap());
+    assert_eq!((undo.undo_depth(), undo.redo_depth()), (1, 1));
+    assert!(undo.can_redo());
+
+    buffer.insert(TextPosition { byte_idx: 1 }, "c").await;
+    undo.record(rx.recv().await.unwrap());
+    assert!(!undo.can_redo());
+    assert_eq!(buffer.get_text(), "ac");
+}
+
+#[tokio::test]
+async fn rejected_undo_keeps_the_group_in_place() {
+    let buffer = TextBuffer::new("hello");
+    let (tx, mut rx) = mpsc::channel(16);
+    buffer.add_observer(tx);
+    let mut undo = UndoStack::new();
+    undo.set_coalesce_window(Duration::from_secs(60));
+    buffer.insert(TextPosition { byte_idx: 5 }, " world").await;
+    undo.record(rx.recv().await.unwrap());
+    buffer.insert(TextPosition { byte_idx: 11 }, "!").await;
+    undo.record(rx.recv().await.unwrap());
+
+    let region = buffer.add_protected_region(TextRange::new(0, 12));
+    assert!(!undo.undo(&buffer).await);
+    assert_eq!(buffer.get_text(), "hello world!");
+    assert_eq!((undo.undo_depth(), undo.redo_depth()), (1, 0));
+    assert!(rx.try_recv().is_err());
+
+    // Once allowed, the whole group is reverted as one edit.
+    buffer.remove_protected_region(region);
+    assert!(undo.undo(&buffer).await);
+    assert_eq!(buffer.get_text(), "hello");
+    assert!(matches!(rx.try_recv(), Ok(TextBufferChangedEvent::Batch(events)) if events.len() == 2));
+    assert_eq!((undo.undo_depth(), undo.redo_depth()), (0, 1));
+
+    // An edit the history didn't see leaves the redo nowhere to go.
+    buffer.remove(TextPosition { byte_idx: 2 }, 3).await;
+    assert!(!undo.redo(&buffer).await);
+    assert_eq!(buffer.get_text(), "he");
+    assert_eq!((undo.undo_depth(), undo.redo_depth()), (0, 1));
+}